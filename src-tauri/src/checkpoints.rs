@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage::session_store::CheckpointRow;
+
+/// A file's contents taken just before a tool modifies it.
+pub struct Snapshot {
+  path: PathBuf,
+  before: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+  pub fn take(path: PathBuf) -> Self {
+    let before = fs::read(&path).ok();
+    Self { path, before }
+  }
+
+  /// The checkpoint to store once the tool has run; `None` when it left the file as it was.
+  pub fn finish(self, session_id: &str, tool_use_id: &str, seq: i64, created_at: i64) -> Option<CheckpointRow> {
+    let after = fs::read(&self.path).ok();
+    if after == self.before {
+      return None;
+    }
+    Some(CheckpointRow {
+      session_id: session_id.to_string(),
+      tool_use_id: tool_use_id.to_string(),
+      seq,
+      path: self.path.to_string_lossy().into_owned(),
+      before: self.before,
+      after,
+      created_at,
+    })
+  }
+}
+
+/// Puts every file in `checkpoints` (oldest first) back the way it was before the first of them.
+/// Nothing is touched if any file no longer matches what the session last wrote, since that
+/// means it was changed outside the session. Returns the restored paths.
+pub fn restore(checkpoints: &[CheckpointRow]) -> Result<Vec<String>, String> {
+  let mut files: Vec<FileHistory> = Vec::new();
+  for checkpoint in checkpoints {
+    match files.iter_mut().find(|file| file.path == checkpoint.path) {
+      Some(file) => file.last_written = &checkpoint.after,
+      None => files.push(FileHistory {
+        path: &checkpoint.path,
+        original: &checkpoint.before,
+        last_written: &checkpoint.after,
+      }),
+    }
+  }
+
+  let conflicts: Vec<&str> = files
+    .iter()
+    .filter(|file| &fs::read(file.path).ok() != file.last_written)
+    .map(|file| file.path)
+    .collect();
+  if !conflicts.is_empty() {
+    return Err(format!("以下文件在会话之外被修改过，已取消回退：{}", conflicts.join("、")));
+  }
+
+  for file in &files {
+    let result = match file.original {
+      Some(content) => write_creating_dirs(Path::new(file.path), content),
+      None => fs::remove_file(file.path),
+    };
+    result.map_err(|error| format!("恢复 {} 失败：{}", file.path, error))?;
+  }
+  Ok(files.into_iter().map(|file| file.path.to_string()).collect())
+}
+
+struct FileHistory<'a> {
+  path: &'a str,
+  original: &'a Option<Vec<u8>>,
+  last_written: &'a Option<Vec<u8>>,
+}
+
+fn write_creating_dirs(path: &Path, content: &[u8]) -> std::io::Result<()> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::tempdir;
+
+  fn change(path: &Path, content: &str, seq: i64) -> CheckpointRow {
+    let snapshot = Snapshot::take(path.to_path_buf());
+    fs::write(path, content).unwrap();
+    snapshot.finish("s1", &format!("tool-{}", seq), seq, 0).expect("file changed")
+  }
+
+  #[test]
+  fn restore_undoes_edits_and_creations() {
+    let dir = tempdir().unwrap();
+    let edited = dir.path().join("a.txt");
+    let created = dir.path().join("new.txt");
+    fs::write(&edited, "v1").unwrap();
+
+    let checkpoints = vec![change(&edited, "v2", 1), change(&created, "hi", 2), change(&edited, "v3", 3)];
+    assert!(Snapshot::take(edited.clone()).finish("s1", "tool-4", 4, 0).is_none());
+
+    let restored = restore(&checkpoints).unwrap();
+    assert_eq!(restored.len(), 2);
+    assert_eq!(fs::read_to_string(&edited).unwrap(), "v1");
+    assert!(!created.exists());
+  }
+
+  #[test]
+  fn restore_refuses_files_changed_outside_the_session() {
+    let dir = tempdir().unwrap();
+    let first = dir.path().join("a.txt");
+    let second = dir.path().join("b.txt");
+    let checkpoints = vec![change(&first, "session", 1), change(&second, "session", 2)];
+    fs::write(&second, "edited by hand").unwrap();
+
+    let error = restore(&checkpoints).unwrap_err();
+    assert!(error.contains("b.txt") && !error.contains("a.txt"));
+    assert_eq!(fs::read_to_string(&first).unwrap(), "session");
+  }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
 use std::time::Duration;
 
 use futures_util::StreamExt;
@@ -8,12 +9,25 @@ use tokio::time::timeout;
 use tauri::{AppHandle, Emitter, Manager, State};
 
 use crate::events::{ClientEvent, PermissionMode, ServerEvent, SessionStatus};
-use crate::providers::registry::ProviderKind;
-use crate::state::{ProviderSettings, SessionState};
+use crate::providers::adapter::{
+  stringify_value, ChatRequest, ModelTurn, ProviderAdapter, StreamError, StreamEvent, ToolCall,
+};
+use crate::providers::attachments::{self, Attachment};
+use crate::providers::params::GenerationParams;
+use crate::providers::registry::ProviderOptions;
+use crate::providers::retry;
+use crate::state::{CancelToken, ProviderSettings, SessionState};
+use crate::permissions::{self, MatchedRule, PermissionPolicy, RuleBehavior, RuleDestination};
+use crate::tools::workspace::{OutsideAccess, Workspace};
 use crate::tools::{self, ToolExecutionResult};
+use crate::checkpoints::{self, Snapshot};
+use crate::compaction;
 use crate::config;
+use crate::system_prompt;
 
 const PERMISSION_TIMEOUT_SECS: u64 = 600;
+const INTERRUPTED_MESSAGE: &str = "Interrupted by user.";
+const USAGE_REPORT_DAYS: u32 = 30;
 
 #[tauri::command]
 pub async fn client_event(
@@ -50,11 +64,14 @@ pub async fn client_event(
       model,
       base_url,
       permission_mode,
-      allowed_tools: _,
+      allowed_tools,
+      openai_api,
+      generation_params,
+      attachments,
     } => {
       let api_key = api_key.trim().to_string();
       let model = model.trim().to_string();
-      if api_key.is_empty() || model.is_empty() {
+      if (provider.requires_api_key() && api_key.is_empty()) || model.is_empty() {
         emit(
           &app,
           ServerEvent::RunnerError {
@@ -71,9 +88,25 @@ pub async fn client_event(
         model,
         base_url: normalize_base_url(base_url),
         permission_mode: permission_mode.unwrap_or(PermissionMode::Ask),
+        allowed_tools: allowed_tools.as_deref().map(permissions::split_rule_list).unwrap_or_default(),
+        options: ProviderOptions {
+          openai_api: openai_api.unwrap_or_default(),
+        },
+        params: generation_params.unwrap_or_default(),
       };
+      let adapter = provider_settings.provider.adapter(&provider_settings.options);
+      let checked = adapter
+        .validate_params(&provider_settings.model, &provider_settings.params)
+        .and_then(|_| check_attachments(adapter.as_ref(), &attachments));
+      if let Err(message) = checked {
+        emit(&app, ServerEvent::RunnerError { session_id: None, message })?;
+        return Ok(());
+      }
 
-      let session = state.create_session(title, cwd.clone(), provider_settings.clone());
+      let session = match state.create_session(title, cwd.clone(), provider_settings.clone()) {
+        Ok(session) => session,
+        Err(message) => return emit(&app, ServerEvent::RunnerError { session_id: None, message }),
+      };
 
       emit(
         &app,
@@ -86,21 +119,25 @@ pub async fn client_event(
         },
       )?;
 
-      record_user_prompt(&state, &session.id, &prompt);
+      record_user_prompt(&state, &session.id, &prompt, &attachments);
       emit(
         &app,
         ServerEvent::StreamUserPrompt {
           session_id: session.id.clone(),
           prompt: prompt.clone(),
+          attachments,
         },
       )?;
 
       let app_handle = app.clone();
       let session_id = session.id.clone();
       let provider_settings_clone = provider_settings.clone();
+      let cancel = state.begin_run(&session_id);
       tauri::async_runtime::spawn(async move {
         let state = app_handle.state::<SessionState>();
-        if let Err(message) = run_session(&app_handle, state.inner(), &session_id, provider_settings_clone).await {
+        if let Err(message) =
+          run_session(&app_handle, state.inner(), &session_id, provider_settings_clone, cancel).await
+        {
           let _ = emit(
             &app_handle,
             ServerEvent::RunnerError {
@@ -113,7 +150,7 @@ pub async fn client_event(
 
       Ok(())
     }
-    ClientEvent::SessionContinue { session_id, prompt } => {
+    ClientEvent::SessionContinue { session_id, prompt, api_key, attachments } => {
       if state.get_session(&session_id).is_none() {
         return emit(
           &app,
@@ -124,18 +161,14 @@ pub async fn client_event(
         );
       }
 
-      let provider_settings = match state.get_provider(&session_id) {
-        Some(settings) => settings,
-        None => {
-          return emit(
-            &app,
-            ServerEvent::RunnerError {
-              session_id: Some(session_id),
-              message: "Session provider config missing.".into(),
-            },
-          );
-        }
+      let provider_settings = match provider_with_key(&state, &session_id, api_key) {
+        Ok(settings) => settings,
+        Err(message) => return emit(&app, ServerEvent::RunnerError { session_id: Some(session_id), message }),
       };
+      let adapter = provider_settings.provider.adapter(&provider_settings.options);
+      if let Err(message) = check_attachments(adapter.as_ref(), &attachments) {
+        return emit(&app, ServerEvent::RunnerError { session_id: Some(session_id), message });
+      }
 
       let _ = state.update_session(&session_id, SessionStatus::Running, None, None);
       emit(
@@ -149,20 +182,24 @@ pub async fn client_event(
         },
       )?;
 
-      record_user_prompt(&state, &session_id, &prompt);
+      record_user_prompt(&state, &session_id, &prompt, &attachments);
       emit(
         &app,
         ServerEvent::StreamUserPrompt {
           session_id: session_id.clone(),
           prompt: prompt.clone(),
+          attachments,
         },
       )?;
 
       let app_handle = app.clone();
       let session_id_clone = session_id.clone();
+      let cancel = state.begin_run(&session_id);
       tauri::async_runtime::spawn(async move {
         let state = app_handle.state::<SessionState>();
-        if let Err(message) = run_session(&app_handle, state.inner(), &session_id_clone, provider_settings).await {
+        if let Err(message) =
+          run_session(&app_handle, state.inner(), &session_id_clone, provider_settings, cancel).await
+        {
           let _ = emit(
             &app_handle,
             ServerEvent::RunnerError {
@@ -176,6 +213,7 @@ pub async fn client_event(
       Ok(())
     }
     ClientEvent::SessionStop { session_id } => {
+      state.cancel_run(&session_id);
       let updated = state.update_session(&session_id, SessionStatus::Idle, None, None);
       let session = updated.ok_or_else(|| "Session not found.".to_string())?;
       emit(
@@ -193,6 +231,120 @@ pub async fn client_event(
       state.delete_session(&session_id);
       emit(&app, ServerEvent::SessionDeleted { session_id })
     }
+    ClientEvent::SessionSearch { query } => {
+      let results = state.search_sessions(&query);
+      emit(&app, ServerEvent::SearchResults { query, results })
+    }
+    ClientEvent::SessionCompact { session_id, api_key } => {
+      let provider_settings = match provider_with_key(&state, &session_id, api_key) {
+        Ok(settings) => settings,
+        Err(message) => return emit(&app, ServerEvent::RunnerError { session_id: Some(session_id), message }),
+      };
+      if state.is_running(&session_id) {
+        return emit(
+          &app,
+          ServerEvent::RunnerError {
+            session_id: Some(session_id),
+            message: "会话正在运行，请等待结束后再压缩上下文。".into(),
+          },
+        );
+      }
+
+      let cancel = state.begin_run(&session_id);
+      let _ = state.update_session(&session_id, SessionStatus::Running, None, None);
+      emit(
+        &app,
+        ServerEvent::SessionStatus {
+          session_id: session_id.clone(),
+          status: SessionStatus::Running,
+          title: None,
+          cwd: None,
+          error: None,
+        },
+      )?;
+
+      let app_handle = app.clone();
+      tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<SessionState>();
+        let result = compact_session(&app_handle, state.inner(), &session_id, &provider_settings, &cancel, true).await;
+        state.finish_run(&session_id, &cancel);
+        if cancel.is_cancelled() {
+          return;
+        }
+        let error = result.as_ref().err().map(|message| format!("压缩上下文失败：{}", message));
+        // Nothing to compact is not a failure, just a note in the transcript.
+        if result == Ok(false) {
+          let note = build_marker_message("compact_skipped", "历史太短，没有可以压缩的内容。");
+          state.add_message(&session_id, note.clone());
+          let _ = emit(&app_handle, ServerEvent::StreamMessage { session_id: session_id.clone(), message: note });
+        }
+        let status = if error.is_some() { SessionStatus::Error } else { SessionStatus::Completed };
+        let _ = state.update_session(&session_id, status.clone(), None, None);
+        let _ = emit(
+          &app_handle,
+          ServerEvent::SessionStatus {
+            session_id: session_id.clone(),
+            status,
+            title: None,
+            cwd: None,
+            error: error.clone(),
+          },
+        );
+        if let Some(message) = error {
+          let _ = emit(&app_handle, ServerEvent::RunnerError { session_id: Some(session_id), message });
+        }
+      });
+      Ok(())
+    }
+    ClientEvent::SessionRewind { session_id, to_message_index } => {
+      if state.is_running(&session_id) {
+        return emit(
+          &app,
+          ServerEvent::RunnerError {
+            session_id: Some(session_id),
+            message: "会话正在运行，请先停止后再回退。".into(),
+          },
+        );
+      }
+      let messages = state.get_messages(&session_id);
+      let prompt = messages
+        .get(to_message_index)
+        .filter(|message| message.get("type").and_then(Value::as_str) == Some("user_prompt"))
+        .and_then(|message| message.get("prompt").and_then(Value::as_str));
+      let Some(prompt) = prompt.map(str::to_string) else {
+        return emit(
+          &app,
+          ServerEvent::RunnerError {
+            session_id: Some(session_id),
+            message: "只能回退到某条用户消息之前。".into(),
+          },
+        );
+      };
+      let restored_files = match checkpoints::restore(&state.checkpoints_since(&session_id, to_message_index)) {
+        Ok(files) => files,
+        Err(message) => return emit(&app, ServerEvent::RunnerError { session_id: Some(session_id), message }),
+      };
+      state.truncate_messages(&session_id, to_message_index);
+
+      emit(
+        &app,
+        ServerEvent::SessionRewound {
+          session_id: session_id.clone(),
+          prompt,
+          restored_files,
+        },
+      )?;
+      let status = state
+        .get_session(&session_id)
+        .map(|session| session.status)
+        .unwrap_or(SessionStatus::Idle);
+      let messages = state.get_messages(&session_id);
+      emit(&app, ServerEvent::SessionHistory { session_id, status, messages })
+    }
+    ClientEvent::UsageReport { days } => {
+      let days = state.usage_by_day(days.unwrap_or(USAGE_REPORT_DAYS));
+      emit(&app, ServerEvent::UsageReport { days })
+    }
     ClientEvent::PermissionResponse { tool_use_id, result, .. } => {
       state.resolve_permission(&tool_use_id, result);
       Ok(())
@@ -200,21 +352,65 @@ pub async fn client_event(
   }
 }
 
+/// The session's provider settings with its API key, taking `api_key` from the request when one was
+/// sent. Keys are not stored on disk, so after a restart the client has to supply it again.
+fn provider_with_key(
+  state: &SessionState,
+  session_id: &str,
+  api_key: Option<String>,
+) -> Result<ProviderSettings, String> {
+  if let Some(api_key) = api_key.map(|key| key.trim().to_string()).filter(|key| !key.is_empty()) {
+    state.set_api_key(session_id, &api_key);
+  }
+  let settings = state.get_provider(session_id).ok_or("Session provider config missing.")?;
+  if settings.provider.requires_api_key() && settings.api_key.is_empty() {
+    return Err("API Key 不会保存到磁盘，应用重启后请重新填写 API Key。".into());
+  }
+  Ok(settings)
+}
+
 async fn run_session(
   app: &AppHandle,
   state: &SessionState,
   session_id: &str,
   provider: ProviderSettings,
+  cancel: CancelToken,
+) -> Result<(), String> {
+  let result = run_agent_loop(app, state, session_id, &provider, &cancel).await;
+  state.finish_run(session_id, &cancel);
+  result
+}
+
+async fn run_agent_loop(
+  app: &AppHandle,
+  state: &SessionState,
+  session_id: &str,
+  provider: &ProviderSettings,
+  cancel: &CancelToken,
 ) -> Result<(), String> {
   let max_iterations = config::max_tool_iterations();
   let mut iterations = 0usize;
+  // Built once per run: a date or instruction file changing between turns would otherwise
+  // invalidate the prompt cache, and the files would be read again for every request.
+  let cwd = state.get_session(session_id).and_then(|session| session.cwd);
+  let system = system_prompt::build(cwd.as_deref(), &tools::definitions());
   loop {
     if config::should_stop_tool_loop(iterations, max_iterations) {
       break;
     }
     iterations += 1;
-    let response = match stream_model(app, state, session_id, &provider).await {
-      Ok(response) => response,
+    if let Err(message) = compact_session(app, state, session_id, provider, cancel, false).await {
+      log::warn!("context compaction failed for {}: {}", session_id, message);
+    }
+    if cancel.is_cancelled() {
+      return record_interrupted(app, state, session_id);
+    }
+    let result = stream_model(app, state, session_id, provider, &system, cancel).await;
+    if cancel.is_cancelled() {
+      return record_interrupted(app, state, session_id);
+    }
+    let tool_calls = match result {
+      Ok(tool_calls) => tool_calls,
       Err(message) => {
         let _ = state.update_session(session_id, SessionStatus::Error, None, None);
         emit(
@@ -231,7 +427,11 @@ async fn run_session(
       }
     };
 
-    if response.tool_calls.is_empty() {
+    if tool_calls.is_empty() {
+      // A stop that arrived while the reply was finishing must not be overwritten with Completed.
+      if cancel.is_cancelled() {
+        return record_interrupted(app, state, session_id);
+      }
       let _ = state.update_session(session_id, SessionStatus::Completed, None, None);
       emit(
         app,
@@ -246,7 +446,10 @@ async fn run_session(
       return Ok(());
     }
 
-    handle_tool_calls(app, state, session_id, &response.tool_calls).await?;
+    handle_tool_calls(app, state, session_id, &tool_calls, cancel).await?;
+    if cancel.is_cancelled() {
+      return record_interrupted(app, state, session_id);
+    }
   }
 
   let message = "工具调用循环次数过多，已停止。".to_string();
@@ -264,261 +467,273 @@ async fn run_session(
   Err(message)
 }
 
+/// A failed request attempt; `retry_after` is the server's hint, when it sent one.
+struct AttemptError {
+  message: String,
+  retryable: bool,
+  retry_after: Option<Duration>,
+}
+
+impl From<StreamError> for AttemptError {
+  fn from(error: StreamError) -> Self {
+    Self {
+      message: error.message,
+      retryable: error.retryable,
+      retry_after: None,
+    }
+  }
+}
+
+impl From<reqwest::Error> for AttemptError {
+  /// Connection resets, timeouts and truncated bodies are worth retrying; a malformed request is not.
+  fn from(error: reqwest::Error) -> Self {
+    Self {
+      retryable: !error.is_builder(),
+      message: error.to_string(),
+      retry_after: None,
+    }
+  }
+}
+
 async fn stream_model(
   app: &AppHandle,
   state: &SessionState,
   session_id: &str,
   provider: &ProviderSettings,
-) -> Result<ModelResponse, String> {
-  match provider.provider {
-    ProviderKind::Anthropic => stream_anthropic(app, state, session_id, provider).await,
-    ProviderKind::OpenAI => stream_openai(app, state, session_id, provider).await,
+  system: &str,
+  cancel: &CancelToken,
+) -> Result<Vec<ToolCall>, String> {
+  let adapter = provider.provider.adapter(&provider.options);
+  let history = request_history(&state.get_messages(session_id), provider);
+  // Read from the store, not `provider`: approving a plan switches the mode in the middle of a run.
+  let plan_mode = state.permission_mode(session_id) == PermissionMode::Plan;
+  let mut tool_specs = tools::definitions();
+  if plan_mode {
+    tool_specs.push(tools::exit_plan_mode_definition());
   }
+  let system =
+    if plan_mode { format!("{}\n\n{}", system, system_prompt::PLAN_MODE_PROMPT) } else { system.to_string() };
+  let body = adapter.build_request(&ChatRequest {
+    model: &provider.model,
+    history: &history,
+    tools: &tool_specs,
+    system: Some(&system),
+    params: &provider.params,
+  });
+
+  let turn = request_turn(app, state, session_id, adapter.as_ref(), provider, &body, cancel, true).await?;
+
+  // Tool calls cut off by a stop are never executed, so they must not enter the history. The same
+  // goes for a reply that hit the token limit: its last tool call may have half-written arguments.
+  let truncated = turn.truncated && !cancel.is_cancelled();
+  let dropped_tool_calls = truncated && !turn.tool_calls.is_empty();
+  let turn = if cancel.is_cancelled() || dropped_tool_calls { turn.without_tool_calls() } else { turn };
+  emit_assistant_message(app, state, session_id, turn.content_blocks)?;
+
+  if truncated {
+    let limit = provider.params.max_tokens;
+    let marker = build_marker_message("max_tokens", &truncation_notice(limit, dropped_tool_calls));
+    state.add_message(session_id, marker.clone());
+    emit(app, ServerEvent::StreamMessage { session_id: session_id.to_string(), message: marker })?;
+    if dropped_tool_calls {
+      let limit = limit.map(|limit| format!("（{}）", limit)).unwrap_or_default();
+      return Err(format!("模型输出达到 max_tokens 上限{}，未完成的工具调用已丢弃。请调高 max_tokens 后继续。", limit));
+    }
+  }
+
+  Ok(turn.tool_calls)
 }
 
-async fn stream_openai(
+/// Sends `body`, retrying transient failures, and records what the call cost. `forward` streams
+/// the reply to the UI as it arrives. A cancelled run yields an empty turn.
+#[allow(clippy::too_many_arguments)]
+async fn request_turn(
   app: &AppHandle,
   state: &SessionState,
   session_id: &str,
+  adapter: &dyn ProviderAdapter,
   provider: &ProviderSettings,
-) -> Result<ModelResponse, String> {
+  body: &Value,
+  cancel: &CancelToken,
+  forward: bool,
+) -> Result<ModelTurn, String> {
   let client = reqwest::Client::builder()
     .timeout(Duration::from_secs(120))
     .build()
     .map_err(|e| e.to_string())?;
 
-  let url = provider
-    .base_url
-    .clone()
-    .unwrap_or_else(|| "https://api.openai.com/v1/chat/completions".into());
-
-  let messages = build_openai_messages(state, session_id);
-  let body = json!({
-    "model": provider.model,
-    "messages": messages,
-    "stream": true,
-    "tools": openai_tools(),
-    "tool_choice": "auto"
-  });
-
-  let response = client
-    .post(url)
-    .bearer_auth(&provider.api_key)
-    .json(&body)
-    .send()
-    .await
-    .map_err(|e| e.to_string())?;
-
-  if !response.status().is_success() {
-    let status = response.status();
-    let text = response.text().await.unwrap_or_default();
-    return Err(format!("OpenAI 请求失败({}): {}", status, text));
-  }
-
-  let mut stream = response.bytes_stream();
-  let mut buffer = String::new();
-  let mut assistant_text = String::new();
-  let mut tool_calls: Vec<ToolCallBuilder> = Vec::new();
-  let mut started = false;
-
-  while let Some(chunk) = stream.next().await {
-    let chunk = chunk.map_err(|e| e.to_string())?;
-    buffer.push_str(&String::from_utf8_lossy(&chunk));
-    for event in drain_sse_events(&mut buffer) {
-      for line in event.lines() {
-        let data = match line.trim().strip_prefix("data:") {
-          Some(value) => value.trim(),
-          None => continue,
+  let policy = config::retry_policy();
+  let mut attempt = 0;
+  let turn = loop {
+    let mut forwarded = false;
+    match stream_attempt(app, session_id, adapter, &client, provider, body, cancel, forward, &mut forwarded).await {
+      Ok(turn) => break turn,
+      Err(error) if error.retryable && attempt < policy.max_retries && !cancel.is_cancelled() => {
+        attempt += 1;
+        let Some(delay) = policy.delay(attempt, error.retry_after, fastrand::f64()) else {
+          let wait = error.retry_after.unwrap_or_default().as_secs();
+          return Err(format!("{}（服务端要求 {} 秒后再试，超过了重试等待上限）", error.message, wait));
         };
-        if data == "[DONE]" {
-          break;
+        log::warn!("{} (retry {}/{} in {:?})", error.message, attempt, policy.max_retries, delay);
+        // The next attempt streams the whole reply again, so the UI drops what it has shown so far.
+        if forwarded {
+          emit_reset_event(app, session_id)?;
+        }
+        if forward {
+          emit_retry_event(app, session_id, &error.message, delay, attempt, policy.max_retries)?;
         }
-        let payload: Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
-        if let Some(delta) = payload.pointer("/choices/0/delta") {
-          if let Some(content) = delta.get("content").and_then(Value::as_str) {
-            if !started {
-              started = true;
-              emit_stream_event(app, session_id, "content_block_start", None)?;
-            }
-            assistant_text.push_str(content);
-            emit_stream_event(
-              app,
-              session_id,
-              "content_block_delta",
-              Some(json!({ "type": "text_delta", "text": content })),
-            )?;
-          }
-          if let Some(calls) = delta.get("tool_calls").and_then(Value::as_array) {
-            for call in calls {
-              let index = call.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
-              if tool_calls.len() <= index {
-                tool_calls.resize_with(index + 1, ToolCallBuilder::default);
-              }
-              let entry = &mut tool_calls[index];
-              if let Some(id) = call.get("id").and_then(Value::as_str) {
-                entry.id = Some(id.to_string());
-              }
-              if let Some(name) = call.pointer("/function/name").and_then(Value::as_str) {
-                entry.name = Some(name.to_string());
-              }
-              if let Some(args) = call.pointer("/function/arguments").and_then(Value::as_str) {
-                entry.arguments.push_str(args);
-              }
-            }
-          }
+        if until_cancelled(cancel, tokio::time::sleep(delay)).await.is_none() {
+          return Ok(ModelTurn::default());
         }
       }
+      Err(error) => return Err(error.message),
     }
-  }
+  };
 
-  if started {
-    emit_stream_event(app, session_id, "content_block_stop", None)?;
+  // Tokens of a stopped turn were still billed, so usage is recorded either way.
+  if !turn.usage.is_empty() {
+    let usage = state.record_usage(session_id, provider, &turn.usage);
+    emit(app, ServerEvent::SessionUsage { session_id: session_id.to_string(), usage })?;
   }
+  Ok(turn)
+}
 
-  let tool_calls = finalize_tool_calls(tool_calls);
-  let content_blocks = build_content_blocks(&assistant_text, &tool_calls);
-  emit_assistant_message(app, state, session_id, content_blocks)?;
+fn context_window(provider: &ProviderSettings) -> usize {
+  config::context_window_override().unwrap_or_else(|| compaction::context_window(&provider.provider, &provider.model))
+}
 
-  Ok(ModelResponse { tool_calls })
+/// The history sent with a request: everything since the latest summary. When that is still over
+/// the threshold, tool output outside the recent window is shortened for this request only.
+fn request_history(stored: &[Value], provider: &ProviderSettings) -> Vec<Value> {
+  let replay = compaction::replay(stored);
+  let window = context_window(provider);
+  let threshold = config::compaction_threshold() as usize;
+  let mut messages = replay.messages.clone();
+  if threshold > 0 && compaction::estimate_history(&messages) * 100 > window * threshold {
+    if let Some(split) = compaction::split_point(&replay, window) {
+      compaction::truncate_tool_results(&mut messages[..split], compaction::OLD_TOOL_RESULT_CHARS);
+    }
+  }
+  messages
 }
 
-async fn stream_anthropic(
+/// Summarises the older part of the history into a `compaction` marker once it passes the
+/// threshold share of the context window (or always, when `force`d). Returns whether it did.
+async fn compact_session(
   app: &AppHandle,
   state: &SessionState,
   session_id: &str,
   provider: &ProviderSettings,
-) -> Result<ModelResponse, String> {
-  let client = reqwest::Client::builder()
-    .timeout(Duration::from_secs(120))
-    .build()
-    .map_err(|e| e.to_string())?;
+  cancel: &CancelToken,
+  force: bool,
+) -> Result<bool, String> {
+  let threshold = config::compaction_threshold() as usize;
+  if !force && threshold == 0 {
+    return Ok(false);
+  }
+  let window = context_window(provider);
+  let replay = compaction::replay(&state.get_messages(session_id));
+  if !force && compaction::estimate_history(&replay.messages) * 100 <= window * threshold {
+    return Ok(false);
+  }
+  let Some(split) = compaction::split_point(&replay, window) else {
+    return Ok(false);
+  };
 
-  let url = provider
-    .base_url
-    .clone()
-    .unwrap_or_else(|| "https://api.anthropic.com/v1/messages".into());
-
-  let messages = build_anthropic_messages(state, session_id);
-  let body = json!({
-    "model": provider.model,
-    "messages": messages,
-    "stream": true,
-    "max_tokens": 1024,
-    "tools": anthropic_tools()
+  let older = &replay.messages[..split];
+  let history = [json!({ "type": "user_prompt", "prompt": compaction::summary_request(older, window) })];
+  let adapter = provider.provider.adapter(&provider.options);
+  let body = adapter.build_request(&ChatRequest {
+    model: &provider.model,
+    history: &history,
+    tools: &[],
+    system: Some(compaction::SUMMARY_SYSTEM),
+    params: &GenerationParams::default(),
   });
+  let turn = request_turn(app, state, session_id, adapter.as_ref(), provider, &body, cancel, false).await?;
+  if cancel.is_cancelled() {
+    return Ok(false);
+  }
+  let summary: Vec<&str> = turn
+    .content_blocks
+    .iter()
+    .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+    .filter_map(|block| block.get("text").and_then(Value::as_str))
+    .collect();
+  let summary = summary.join("\n").trim().to_string();
+  if summary.is_empty() || turn.truncated {
+    return Err("模型没有返回完整的摘要。".into());
+  }
 
-  let response = client
-    .post(url)
-    .header("x-api-key", &provider.api_key)
-    .header("anthropic-version", "2023-06-01")
-    .json(&body)
-    .send()
-    .await
-    .map_err(|e| e.to_string())?;
+  let marker = compaction::compaction_marker(
+    &summary,
+    replay.stored_index[split],
+    split,
+    compaction::estimate_history(older),
+  );
+  state.add_message(session_id, marker.clone());
+  emit(app, ServerEvent::StreamMessage { session_id: session_id.to_string(), message: marker })?;
+  Ok(true)
+}
+
+fn truncation_notice(max_tokens: Option<u32>, dropped_tool_calls: bool) -> String {
+  let limit = max_tokens.map(|limit| format!(" ({})", limit)).unwrap_or_default();
+  if dropped_tool_calls {
+    format!("Reply hit the max_tokens limit{}; its unfinished tool calls were not run.", limit)
+  } else {
+    format!("Reply hit the max_tokens limit{} and may be incomplete.", limit)
+  }
+}
+
+/// Sends the request once and streams the reply. Nothing is recorded in the history here, so a
+/// failed attempt can be repeated from scratch; `forwarded` tells whether any of it reached the UI.
+#[allow(clippy::too_many_arguments)]
+async fn stream_attempt(
+  app: &AppHandle,
+  session_id: &str,
+  adapter: &dyn ProviderAdapter,
+  client: &reqwest::Client,
+  provider: &ProviderSettings,
+  body: &Value,
+  cancel: &CancelToken,
+  forward: bool,
+  forwarded: &mut bool,
+) -> Result<ModelTurn, AttemptError> {
+  let url = adapter.endpoint(provider.base_url.as_deref(), &provider.model);
+  let request = adapter.authorize(client.post(url), &provider.api_key).json(body).send();
+  let response = match until_cancelled(cancel, request).await {
+    Some(response) => response?,
+    None => return Ok(ModelTurn::default()),
+  };
 
-  if !response.status().is_success() {
-    let status = response.status();
+  let status = response.status();
+  if !status.is_success() {
+    let retry_after = retry::retry_after(response.headers());
     let text = response.text().await.unwrap_or_default();
-    return Err(format!("Anthropic 请求失败({}): {}", status, text));
+    return Err(AttemptError {
+      message: format!("{} 请求失败({}): {}", adapter.name(), status, text),
+      retryable: retry::is_retryable_status(status),
+      retry_after,
+    });
   }
 
   let mut stream = response.bytes_stream();
-  let mut buffer = String::new();
-  let mut blocks: HashMap<u64, AnthropicBlock> = HashMap::new();
-
-  while let Some(chunk) = stream.next().await {
-    let chunk = chunk.map_err(|e| e.to_string())?;
-    buffer.push_str(&String::from_utf8_lossy(&chunk));
-    for event in drain_sse_events(&mut buffer) {
-      let mut event_type = "";
-      let mut data_lines = Vec::new();
-      for line in event.lines() {
-        let line = line.trim();
-        if let Some(value) = line.strip_prefix("event:") {
-          event_type = value.trim();
-        } else if let Some(value) = line.strip_prefix("data:") {
-          data_lines.push(value.trim());
-        }
-      }
-      if data_lines.is_empty() {
+  let mut parser = adapter.stream_parser();
+  while let Some(chunk) = until_cancelled(cancel, stream.next()).await.flatten() {
+    for event in parser.push(&chunk?)? {
+      if !forward {
         continue;
       }
-      let data = data_lines.join("\n");
-      let payload: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-
-      match event_type {
-        "content_block_start" => {
-          let index = payload.get("index").and_then(Value::as_u64).unwrap_or(0);
-          if let Some(block) = payload.get("content_block") {
-            if block.get("type").and_then(Value::as_str) == Some("text") {
-              blocks.insert(index, AnthropicBlock::Text(String::new()));
-              emit_stream_event(app, session_id, "content_block_start", None)?;
-            } else if block.get("type").and_then(Value::as_str) == Some("tool_use") {
-              let id = block.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
-              let name = block.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
-              let input = block.get("input").cloned();
-              blocks.insert(index, AnthropicBlock::ToolUse { id, name, input_json: String::new(), input });
-            }
-          }
-        }
-        "content_block_delta" => {
-          let index = payload.get("index").and_then(Value::as_u64).unwrap_or(0);
-          if let Some(delta) = payload.get("delta") {
-            if delta.get("type").and_then(Value::as_str) == Some("text_delta") {
-              let text = delta.get("text").and_then(Value::as_str).unwrap_or("");
-              if let Some(AnthropicBlock::Text(current)) = blocks.get_mut(&index) {
-                current.push_str(text);
-              }
-              emit_stream_event(
-                app,
-                session_id,
-                "content_block_delta",
-                Some(json!({ "type": "text_delta", "text": text })),
-              )?;
-            } else if delta.get("type").and_then(Value::as_str) == Some("input_json_delta") {
-              let part = delta.get("partial_json").and_then(Value::as_str).unwrap_or("");
-              if let Some(AnthropicBlock::ToolUse { input_json, .. }) = blocks.get_mut(&index) {
-                input_json.push_str(part);
-              }
-            }
-          }
-        }
-        "content_block_stop" => {
-          emit_stream_event(app, session_id, "content_block_stop", None)?;
-        }
-        _ => {}
-      }
-    }
-  }
-
-  let mut tool_calls = Vec::new();
-  let mut content_blocks = Vec::new();
-  let mut ordered_keys: Vec<u64> = blocks.keys().cloned().collect();
-  ordered_keys.sort_unstable();
-  for key in ordered_keys {
-    if let Some(block) = blocks.remove(&key) {
-      match block {
-        AnthropicBlock::Text(text) => {
-          if !text.is_empty() {
-            content_blocks.push(json!({ "type": "text", "text": text }));
-          }
-        }
-        AnthropicBlock::ToolUse { id, name, input_json, input } => {
-          let parsed = if !input_json.trim().is_empty() {
-            serde_json::from_str(&input_json).unwrap_or(Value::String(input_json))
-          } else {
-            input.unwrap_or(Value::Null)
-          };
-          tool_calls.push(ToolCall { id: id.clone(), name: name.clone(), input: parsed.clone() });
-          content_blocks.push(json!({ "type": "tool_use", "id": id, "name": name, "input": parsed }));
-        }
-      }
+      *forwarded = true;
+      emit_stream_event(app, session_id, event).map_err(|message| AttemptError {
+        message,
+        retryable: false,
+        retry_after: None,
+      })?;
     }
   }
 
-  emit_assistant_message(app, state, session_id, content_blocks)?;
-
-  Ok(ModelResponse { tool_calls })
+  Ok(parser.finish())
 }
 
 async fn handle_tool_calls(
@@ -526,17 +741,74 @@ async fn handle_tool_calls(
   state: &SessionState,
   session_id: &str,
   tool_calls: &[ToolCall],
+  cancel: &CancelToken,
 ) -> Result<(), String> {
   let cwd = state.get_session(session_id).and_then(|session| session.cwd);
-  let permission_mode = state
-    .get_provider(session_id)
-    .map(|settings| settings.permission_mode)
-    .unwrap_or(PermissionMode::Ask);
+  let settings = state.get_provider(session_id);
+  let mut permission_mode = settings.as_ref().map_or(PermissionMode::Ask, |settings| settings.permission_mode.clone());
+  let allowed_tools = settings.map(|settings| settings.allowed_tools).unwrap_or_default();
+  // Re-read for every batch so edits to the settings files apply to a running session.
+  let mut policy = PermissionPolicy::load(cwd.as_deref(), &allowed_tools);
+  // Without a cwd there is no workspace to confine file tools to.
+  let workspace = cwd.as_deref().map(|cwd| Workspace::new(cwd, &config::extra_workspace_roots()));
   for call in tool_calls {
-    let permission = if permission_mode == PermissionMode::Auto && call.name != "AskUserQuestion" {
+    // Every tool_use still needs a tool_result, or the next request is rejected by the provider.
+    if cancel.is_cancelled() {
+      record_tool_error(app, state, session_id, &call.id, INTERRUPTED_MESSAGE)?;
+      continue;
+    }
+    let rule = policy.evaluate(&call.name, &call.input, cwd.as_deref());
+    if let Some(denied) = rule.as_ref().filter(|rule| rule.behavior == RuleBehavior::Deny) {
+      let message = format!("Denied by permission rule {} ({}).", denied.rule, denied.source);
+      record_tool_error(app, state, session_id, &call.id, &message)?;
+      continue;
+    }
+    let read_only = tools::READ_ONLY_TOOLS.contains(&call.name.as_str());
+    if call.name == "ExitPlanMode" {
+      if let Some(mode) = exit_plan_mode(app, state, session_id, call, &permission_mode, cancel).await? {
+        permission_mode = mode;
+      }
+      continue;
+    }
+    if permission_mode == PermissionMode::Plan && !read_only && call.name != "AskUserQuestion" {
+      let message = format!(
+        "Plan mode is active, so {} was not run. Explore with Read, Glob, Grep and WebFetch, then call \
+ExitPlanMode with your plan; changes can be made once the user approves it.",
+        call.name
+      );
+      record_tool_error(app, state, session_id, &call.id, &message)?;
+      continue;
+    }
+    let accessed = tools::accessed_paths(&call.name, &call.input, cwd.as_deref());
+    let outside = workspace.as_ref().map(|workspace| workspace.outside(&accessed)).unwrap_or_default();
+    let reason = (!outside.is_empty()).then(|| {
+      let paths: Vec<String> = outside.iter().map(|path| path.display().to_string()).collect();
+      format!("Outside the workspace: {}", paths.join(", "))
+    });
+    if let Some(reason) = &reason {
+      if config::outside_workspace_access() == OutsideAccess::Deny {
+        let message = format!("{}. Access outside the workspace is not allowed.", reason);
+        record_tool_error(app, state, session_id, &call.id, &message)?;
+        continue;
+      }
+    }
+
+    let prompt = PromptDetails {
+      diff: tools::preview_diff(&call.name, &call.input, cwd.as_deref()),
+      reason,
+      rule,
+      suggestion: permissions::suggest_rule(&call.name, &call.input, cwd.as_deref()),
+    };
+    let auto_allowed = call.name != "AskUserQuestion"
+      && match prompt.rule.as_ref().map(|rule| rule.behavior) {
+        Some(behavior) => behavior == RuleBehavior::Allow,
+        None => permission_mode == PermissionMode::Auto || (permission_mode == PermissionMode::Plan && read_only),
+      };
+    // Leaving the workspace always needs an explicit yes, whatever the mode or rules.
+    let permission = if auto_allowed && prompt.reason.is_none() {
       json!({ "behavior": "allow", "updatedInput": call.input })
     } else {
-      request_permission(app, state, session_id, call).await?
+      request_permission(app, state, session_id, call, prompt.clone(), cancel).await?
     };
     let behavior = permission
       .get("behavior")
@@ -548,53 +820,197 @@ async fn handle_tool_calls(
         .get("message")
         .and_then(Value::as_str)
         .unwrap_or("User denied the request.");
-      let tool_message = build_tool_result_message(&call.id, message, true);
-      state.add_message(session_id, tool_message.clone());
-      emit(
-        app,
-        ServerEvent::StreamMessage {
-          session_id: session_id.to_string(),
-          message: tool_message,
-        },
-      )?;
+      record_tool_error(app, state, session_id, &call.id, message)?;
       continue;
     }
+    remember_rules(app, state, session_id, cwd.as_deref(), &mut policy, &permission)?;
 
     let effective_input = permission
       .get("updatedInput")
       .cloned()
       .unwrap_or_else(|| call.input.clone());
+    // The diff has to be taken before the tool runs; redo it if the input was changed on approval.
+    let diff = if effective_input == call.input {
+      prompt.diff
+    } else {
+      tools::preview_diff(&call.name, &effective_input, cwd.as_deref())
+    };
 
     let execution = if call.name == "AskUserQuestion" {
       ToolExecutionResult {
         content: stringify_value(&effective_input),
         is_error: false,
+        attachments: Vec::new(),
       }
     } else {
-      match tools::execute_tool(&call.name, &effective_input, cwd.as_deref()).await {
-        Ok(result) => result,
-        Err(error) => ToolExecutionResult { content: error, is_error: true },
+      let snapshot = tools::file_target(&call.name, &effective_input, cwd.as_deref()).map(Snapshot::take);
+      // An approved out-of-workspace call runs unconfined; everything else is checked again.
+      let boundary = if prompt.reason.is_some() { None } else { workspace.as_ref() };
+      let running = tools::execute_tool(&call.name, &effective_input, cwd.as_deref(), boundary);
+      let execution = match until_cancelled(cancel, running).await {
+        Some(Ok(result)) => result,
+        Some(Err(error)) => ToolExecutionResult { content: error, is_error: true, attachments: Vec::new() },
+        None => ToolExecutionResult {
+          content: INTERRUPTED_MESSAGE.into(),
+          is_error: true,
+          attachments: Vec::new(),
+        },
+      };
+      if let Some(snapshot) = snapshot {
+        state.record_checkpoint(session_id, &call.id, snapshot);
       }
+      execution
     };
 
-    let tool_message = build_tool_result_message(&call.id, &execution.content, execution.is_error);
-    state.add_message(session_id, tool_message.clone());
-    emit(
-      app,
-      ServerEvent::StreamMessage {
-        session_id: session_id.to_string(),
-        message: tool_message,
-      },
-    )?;
+    let diff = diff.filter(|_| !execution.is_error);
+    let tool_message = build_tool_result_message(&call.id, &execution, diff.as_deref());
+    record_tool_result(app, state, session_id, tool_message)?;
   }
   Ok(())
 }
 
+fn record_tool_error(
+  app: &AppHandle,
+  state: &SessionState,
+  session_id: &str,
+  tool_use_id: &str,
+  message: &str,
+) -> Result<(), String> {
+  let execution = ToolExecutionResult { content: message.to_string(), is_error: true, attachments: Vec::new() };
+  record_tool_result(app, state, session_id, build_tool_result_message(tool_use_id, &execution, None))
+}
+
+fn record_tool_result(
+  app: &AppHandle,
+  state: &SessionState,
+  session_id: &str,
+  tool_message: Value,
+) -> Result<(), String> {
+  state.add_message(session_id, tool_message.clone());
+  emit(
+    app,
+    ServerEvent::StreamMessage {
+      session_id: session_id.to_string(),
+      message: tool_message,
+    },
+  )
+}
+
+fn record_interrupted(app: &AppHandle, state: &SessionState, session_id: &str) -> Result<(), String> {
+  let marker = build_marker_message("interrupted", INTERRUPTED_MESSAGE);
+  state.add_message(session_id, marker.clone());
+  emit(
+    app,
+    ServerEvent::StreamMessage {
+      session_id: session_id.to_string(),
+      message: marker,
+    },
+  )
+}
+
+/// Drives `future` until it completes or the run is cancelled; cancelling drops (and so aborts) it.
+async fn until_cancelled<F: Future>(cancel: &CancelToken, future: F) -> Option<F::Output> {
+  tokio::select! {
+    _ = cancel.cancelled() => None,
+    output = future => Some(output),
+  }
+}
+
+/// Shows the plan for approval. Approving leaves plan mode for the mode picked with it (`Ask` by
+/// default) and returns that mode; rejecting keeps the session planning, with the user's feedback.
+async fn exit_plan_mode(
+  app: &AppHandle,
+  state: &SessionState,
+  session_id: &str,
+  call: &ToolCall,
+  permission_mode: &PermissionMode,
+  cancel: &CancelToken,
+) -> Result<Option<PermissionMode>, String> {
+  if *permission_mode != PermissionMode::Plan {
+    record_tool_error(app, state, session_id, &call.id, "Plan mode is not active; go ahead with the task.")?;
+    return Ok(None);
+  }
+  let permission = request_permission(app, state, session_id, call, PromptDetails::default(), cancel).await?;
+  if cancel.is_cancelled() {
+    record_tool_error(app, state, session_id, &call.id, INTERRUPTED_MESSAGE)?;
+    return Ok(None);
+  }
+  if permission.get("behavior").and_then(Value::as_str) != Some("allow") {
+    let feedback = permission
+      .get("message")
+      .and_then(Value::as_str)
+      .map(str::trim)
+      .filter(|feedback| !feedback.is_empty());
+    let rejected = match feedback {
+      Some(feedback) => format!("User rejected the plan with this feedback: {}", feedback),
+      None => "User rejected the plan.".to_string(),
+    };
+    let message = format!("{}\nPlan mode is still active: revise the plan and call ExitPlanMode again.", rejected);
+    record_tool_error(app, state, session_id, &call.id, &message)?;
+    return Ok(None);
+  }
+
+  let mode = permissions::mode_update(&permission).unwrap_or(PermissionMode::Ask);
+  state.set_permission_mode(session_id, &mode);
+  let note = match mode {
+    PermissionMode::Auto => "tool calls now run without asking",
+    _ => "edits and commands now ask the user first",
+  };
+  let execution = ToolExecutionResult {
+    content: format!("User approved the plan. Plan mode is off and {}; carry out the plan.", note),
+    is_error: false,
+    attachments: Vec::new(),
+  };
+  record_tool_result(app, state, session_id, build_tool_result_message(&call.id, &execution, None))?;
+  Ok(Some(mode))
+}
+
+/// Keeps the allow rules picked with an approval, so later matching calls in this batch and after
+/// skip the prompt.
+fn remember_rules(
+  app: &AppHandle,
+  state: &SessionState,
+  session_id: &str,
+  cwd: Option<&str>,
+  policy: &mut PermissionPolicy,
+  permission: &Value,
+) -> Result<(), String> {
+  for update in permissions::rule_updates(permission) {
+    let saved = match (update.destination, cwd) {
+      (RuleDestination::Project, Some(cwd)) => permissions::save_project_rule(Path::new(cwd), &update.rule)
+        .map(|path| path.display().to_string()),
+      (RuleDestination::Project, None) => Err("会话没有工作目录，无法保存项目权限规则。".to_string()),
+      (RuleDestination::Session, _) => {
+        state.add_allowed_tool(session_id, &update.rule);
+        Ok(permissions::SESSION_SOURCE.to_string())
+      }
+    };
+    match saved {
+      Ok(source) => policy.add(RuleBehavior::Allow, &update.rule, &source),
+      Err(message) => emit(app, ServerEvent::RunnerError { session_id: Some(session_id.to_string()), message })?,
+    }
+  }
+  Ok(())
+}
+
+/// Shown in the permission prompt alongside the tool input.
+#[derive(Clone, Default)]
+struct PromptDetails {
+  diff: Option<String>,
+  /// Why the call needs a prompt even in Auto mode.
+  reason: Option<String>,
+  rule: Option<MatchedRule>,
+  /// A generalized rule the user can allow for the session or the project.
+  suggestion: Option<String>,
+}
+
 async fn request_permission(
   app: &AppHandle,
   state: &SessionState,
   session_id: &str,
   call: &ToolCall,
+  prompt: PromptDetails,
+  cancel: &CancelToken,
 ) -> Result<Value, String> {
   let (sender, receiver) = oneshot::channel();
   state.register_permission(call.id.clone(), sender)?;
@@ -605,12 +1021,24 @@ async fn request_permission(
       tool_use_id: call.id.clone(),
       tool_name: call.name.clone(),
       input: call.input.clone(),
+      diff: prompt.diff,
+      reason: prompt.reason,
+      rule: prompt.rule,
+      suggestion: prompt.suggestion,
     },
   )?;
-  match timeout(Duration::from_secs(PERMISSION_TIMEOUT_SECS), receiver).await {
-    Ok(Ok(result)) => Ok(result),
-    Ok(Err(_)) => Err("Permission channel closed.".into()),
-    Err(_) => Err("Permission request timed out.".into()),
+  let waited = until_cancelled(cancel, timeout(Duration::from_secs(PERMISSION_TIMEOUT_SECS), receiver)).await;
+  match waited {
+    Some(Ok(Ok(result))) => Ok(result),
+    Some(Ok(Err(_))) => Err("Permission channel closed.".into()),
+    Some(Err(_)) => {
+      state.discard_permission(&call.id);
+      Err("Permission request timed out.".into())
+    }
+    None => {
+      state.discard_permission(&call.id);
+      Ok(json!({ "behavior": "deny", "message": INTERRUPTED_MESSAGE }))
+    }
   }
 }
 
@@ -639,17 +1067,60 @@ fn emit_assistant_message(
   )
 }
 
-fn emit_stream_event(
+fn emit_stream_event(app: &AppHandle, session_id: &str, event: StreamEvent) -> Result<(), String> {
+  let event = match event {
+    StreamEvent::ContentStart => json!({ "type": "content_block_start" }),
+    StreamEvent::TextDelta(text) => json!({
+      "type": "content_block_delta",
+      "delta": { "type": "text_delta", "text": text }
+    }),
+    StreamEvent::ThinkingDelta(thinking) => json!({
+      "type": "content_block_delta",
+      "delta": { "type": "thinking_delta", "thinking": thinking }
+    }),
+    StreamEvent::ContentStop => json!({ "type": "content_block_stop" }),
+  };
+  let message = json!({ "type": "stream_event", "event": event });
+  emit(
+    app,
+    ServerEvent::StreamMessage {
+      session_id: session_id.to_string(),
+      message,
+    },
+  )
+}
+
+/// Tells the UI to discard the partial reply of a failed attempt.
+fn emit_reset_event(app: &AppHandle, session_id: &str) -> Result<(), String> {
+  emit(
+    app,
+    ServerEvent::StreamMessage {
+      session_id: session_id.to_string(),
+      message: json!({ "type": "stream_event", "event": { "type": "reset" } }),
+    },
+  )
+}
+
+/// Tells the UI a request failed transiently and will be sent again after `delay`.
+fn emit_retry_event(
   app: &AppHandle,
   session_id: &str,
-  event_type: &str,
-  delta: Option<Value>,
+  error: &str,
+  delay: Duration,
+  attempt: u32,
+  max_attempts: u32,
 ) -> Result<(), String> {
-  let mut event = json!({ "type": event_type });
-  if let Some(delta) = delta {
-    event["delta"] = delta;
-  }
-  let message = json!({ "type": "stream_event", "event": event });
+  let message = json!({
+    "type": "stream_event",
+    "event": {
+      "type": "retry",
+      "message": retry_notice(delay, attempt, max_attempts),
+      "error": error,
+      "attempt": attempt,
+      "maxAttempts": max_attempts,
+      "delayMs": delay.as_millis() as u64
+    }
+  });
   emit(
     app,
     ServerEvent::StreamMessage {
@@ -659,288 +1130,60 @@ fn emit_stream_event(
   )
 }
 
-fn build_tool_result_message(tool_use_id: &str, content: &str, is_error: bool) -> Value {
-  let safe_content = if is_error && content.trim().is_empty() {
+fn retry_notice(delay: Duration, attempt: u32, max_attempts: u32) -> String {
+  format!(
+    "retrying in {}s (attempt {}/{})",
+    delay.as_secs_f64().ceil() as u64,
+    attempt,
+    max_attempts
+  )
+}
+
+/// The stored `tool_result`. `attachments` and `diff` are extra keys: provider adapters turn the
+/// former into content blocks, and the diff is only for display.
+fn build_tool_result_message(tool_use_id: &str, execution: &ToolExecutionResult, diff: Option<&str>) -> Value {
+  let content = execution.content.as_str();
+  let safe_content = if execution.is_error && content.trim().is_empty() {
     "Tool execution failed."
   } else {
     content
   };
+  let mut result = json!({
+    "type": "tool_result",
+    "tool_use_id": tool_use_id,
+    "content": safe_content,
+    "is_error": execution.is_error
+  });
+  if !execution.attachments.is_empty() {
+    result["attachments"] = json!(execution.attachments);
+  }
+  if let Some(diff) = diff.filter(|diff| !diff.is_empty()) {
+    result["diff"] = json!(diff);
+  }
   json!({
     "type": "user",
     "message": {
-      "content": [
-        {
-          "type": "tool_result",
-          "tool_use_id": tool_use_id,
-          "content": safe_content,
-          "is_error": is_error
-        }
-      ]
+      "content": [result]
     }
   })
 }
 
-fn build_content_blocks(text: &str, tool_calls: &[ToolCall]) -> Vec<Value> {
-  let mut blocks = Vec::new();
-  if !text.trim().is_empty() {
-    blocks.push(json!({ "type": "text", "text": text }));
-  }
-  for call in tool_calls {
-    blocks.push(json!({
-      "type": "tool_use",
-      "id": call.id,
-      "name": call.name,
-      "input": call.input
-    }));
-  }
-  blocks
-}
-
-fn build_openai_messages(state: &SessionState, session_id: &str) -> Vec<Value> {
-  let history = state.get_messages(session_id);
-  let mut messages = Vec::new();
-
-  for item in history {
-    let msg_type = item.get("type").and_then(Value::as_str);
-    match msg_type {
-      Some("user_prompt") => {
-        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
-          messages.push(json!({ "role": "user", "content": prompt }));
-        }
-      }
-      Some("assistant") => {
-        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
-          let mut text = String::new();
-          let mut tool_calls = Vec::new();
-          for content in contents {
-            match content.get("type").and_then(Value::as_str) {
-              Some("text") => {
-                if let Some(part) = content.get("text").and_then(Value::as_str) {
-                  text.push_str(part);
-                }
-              }
-              Some("tool_use") => {
-                let id = content.get("id").and_then(Value::as_str).unwrap_or_default();
-                let name = content.get("name").and_then(Value::as_str).unwrap_or_default();
-                let input = content.get("input").cloned().unwrap_or(Value::Null);
-                tool_calls.push(json!({
-                  "id": id,
-                  "type": "function",
-                  "function": {
-                    "name": name,
-                    "arguments": stringify_value(&input)
-                  }
-                }));
-              }
-              _ => {}
-            }
-          }
-          if !tool_calls.is_empty() || !text.trim().is_empty() {
-            let mut message = json!({ "role": "assistant", "content": text });
-            if !tool_calls.is_empty() {
-              message["tool_calls"] = Value::Array(tool_calls);
-            }
-            messages.push(message);
-          }
-        }
-      }
-      Some("user") => {
-        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
-          for content in contents {
-            if content.get("type").and_then(Value::as_str) == Some("tool_result") {
-              let id = content.get("tool_use_id").and_then(Value::as_str).unwrap_or_default();
-              let payload = content.get("content").cloned().unwrap_or(Value::String(String::new()));
-              messages.push(json!({
-                "role": "tool",
-                "tool_call_id": id,
-                "content": stringify_value(&payload)
-              }));
-            }
-          }
-        }
-      }
-      _ => {}
-    }
-  }
-
-  messages
+/// History entry that is shown in the transcript but never sent to the model.
+fn build_marker_message(kind: &str, text: &str) -> Value {
+  json!({ "type": "marker", "kind": kind, "text": text })
 }
 
-fn build_anthropic_messages(state: &SessionState, session_id: &str) -> Vec<Value> {
-  let history = state.get_messages(session_id);
-  let mut messages = Vec::new();
-  let mut pending_tool_results: Vec<Value> = Vec::new();
-
-  let flush_tool_results = |pending: &mut Vec<Value>, output: &mut Vec<Value>| {
-    if pending.is_empty() {
-      return;
-    }
-    output.push(json!({ "role": "user", "content": pending.clone() }));
-    pending.clear();
-  };
-
-  for item in history {
-    let msg_type = item.get("type").and_then(Value::as_str);
-    match msg_type {
-      Some("user_prompt") => {
-        flush_tool_results(&mut pending_tool_results, &mut messages);
-        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
-          messages.push(json!({
-            "role": "user",
-            "content": [{ "type": "text", "text": prompt }]
-          }));
-        }
-      }
-      Some("assistant") => {
-        flush_tool_results(&mut pending_tool_results, &mut messages);
-        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
-          messages.push(json!({ "role": "assistant", "content": contents }));
-        }
-      }
-      Some("user") => {
-        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
-          let only_tool_results = contents
-            .iter()
-            .all(|content| content.get("type").and_then(Value::as_str) == Some("tool_result"));
-          if only_tool_results {
-            pending_tool_results.extend(contents.iter().cloned());
-          } else {
-            flush_tool_results(&mut pending_tool_results, &mut messages);
-            messages.push(json!({ "role": "user", "content": contents }));
-          }
-        }
-      }
-      _ => {}
-    }
+fn record_user_prompt(state: &SessionState, session_id: &str, prompt: &str, attachments: &[Attachment]) {
+  let mut message = json!({ "type": "user_prompt", "prompt": prompt });
+  if !attachments.is_empty() {
+    message["attachments"] = json!(attachments);
   }
-
-  flush_tool_results(&mut pending_tool_results, &mut messages);
-
-  messages
-}
-
-fn openai_tools() -> Vec<Value> {
-  vec![
-    tool_def("Read", "Read a file from disk.", json!({
-      "type": "object",
-      "properties": { "file_path": { "type": "string" } },
-      "required": ["file_path"]
-    })),
-    tool_def("Write", "Write a file to disk.", json!({
-      "type": "object",
-      "properties": {
-        "file_path": { "type": "string" },
-        "content": { "type": "string" }
-      },
-      "required": ["file_path", "content"]
-    })),
-    tool_def("Edit", "Replace a string in a file.", json!({
-      "type": "object",
-      "properties": {
-        "file_path": { "type": "string" },
-        "old_string": { "type": "string" },
-        "new_string": { "type": "string" }
-      },
-      "required": ["file_path", "old_string", "new_string"]
-    })),
-    tool_def("Bash", "Run a shell command.", json!({
-      "type": "object",
-      "properties": { "command": { "type": "string" } },
-      "required": ["command"]
-    })),
-    tool_def("Glob", "Find files matching a glob pattern.", json!({
-      "type": "object",
-      "properties": {
-        "pattern": { "type": "string" },
-        "path": { "type": "string" }
-      },
-      "required": ["pattern"]
-    })),
-    tool_def("Grep", "Search for text within files.", json!({
-      "type": "object",
-      "properties": {
-        "pattern": { "type": "string" },
-        "path": { "type": "string" }
-      },
-      "required": ["pattern"]
-    })),
-    tool_def("WebFetch", "Fetch a URL over HTTP.", json!({
-      "type": "object",
-      "properties": { "url": { "type": "string" } },
-      "required": ["url"]
-    })),
-    tool_def("Task", "Create a sub-task description.", json!({
-      "type": "object",
-      "properties": { "description": { "type": "string" } },
-      "required": ["description"]
-    })),
-    tool_def("AskUserQuestion", "Ask user clarifying questions.", ask_user_schema()),
-  ]
-}
-
-fn anthropic_tools() -> Vec<Value> {
-  let tools = openai_tools();
-  tools
-    .into_iter()
-    .filter_map(|tool| {
-      let name = tool.pointer("/function/name")?.as_str()?.to_string();
-      let description = tool.pointer("/function/description")?.as_str()?.to_string();
-      let schema = tool.pointer("/function/parameters")?.clone();
-      Some(json!({
-        "name": name,
-        "description": description,
-        "input_schema": schema
-      }))
-    })
-    .collect()
-}
-
-fn tool_def(name: &str, description: &str, parameters: Value) -> Value {
-  json!({
-    "type": "function",
-    "function": {
-      "name": name,
-      "description": description,
-      "parameters": parameters
-    }
-  })
-}
-
-fn ask_user_schema() -> Value {
-  json!({
-    "type": "object",
-    "properties": {
-      "questions": {
-        "type": "array",
-        "items": {
-          "type": "object",
-          "properties": {
-            "question": { "type": "string" },
-            "header": { "type": "string" },
-            "options": {
-              "type": "array",
-              "items": {
-                "type": "object",
-                "properties": {
-                  "label": { "type": "string" },
-                  "description": { "type": "string" }
-                },
-                "required": ["label"]
-              }
-            },
-            "multiSelect": { "type": "boolean" }
-          },
-          "required": ["question"]
-        }
-      }
-    },
-    "required": ["questions"]
-  })
+  state.add_message(session_id, message);
 }
 
-fn record_user_prompt(state: &SessionState, session_id: &str, prompt: &str) {
-  let message = json!({ "type": "user_prompt", "prompt": prompt });
-  state.add_message(session_id, message);
+fn check_attachments(adapter: &dyn ProviderAdapter, attachments: &[Attachment]) -> Result<(), String> {
+  attachments::validate(attachments)?;
+  adapter.validate_attachments(attachments)
 }
 
 fn normalize_base_url(base_url: Option<String>) -> Option<String> {
@@ -953,135 +1196,41 @@ fn emit(app: &AppHandle, event: ServerEvent) -> Result<(), String> {
   app.emit("server-event", event).map_err(|e| e.to_string())
 }
 
-fn stringify_value(value: &Value) -> String {
-  match value {
-    Value::String(text) => text.clone(),
-    _ => value.to_string(),
-  }
-}
-
-fn drain_sse_events(buffer: &mut String) -> Vec<String> {
-  let mut events = Vec::new();
-  loop {
-    if let Some(pos) = buffer.find("\n\n") {
-      let event = buffer[..pos].to_string();
-      buffer.drain(..pos + 2);
-      if !event.trim().is_empty() {
-        events.push(event);
-      }
-    } else {
-      break;
-    }
-  }
-  events
-}
-
-#[derive(Debug, Clone)]
-struct ToolCall {
-  id: String,
-  name: String,
-  input: Value,
-}
-
-struct ModelResponse {
-  tool_calls: Vec<ToolCall>,
-}
-
-#[derive(Default)]
-struct ToolCallBuilder {
-  id: Option<String>,
-  name: Option<String>,
-  arguments: String,
-}
-
-fn finalize_tool_calls(builders: Vec<ToolCallBuilder>) -> Vec<ToolCall> {
-  let mut calls = Vec::new();
-  for (idx, builder) in builders.into_iter().enumerate() {
-    let name = builder.name.unwrap_or_else(|| "UnknownTool".into());
-    let id = builder.id.unwrap_or_else(|| format!("tool-{}", idx));
-    let input = if builder.arguments.trim().is_empty() {
-      Value::Null
-    } else {
-      serde_json::from_str(&builder.arguments).unwrap_or(Value::String(builder.arguments))
-    };
-    calls.push(ToolCall { id, name, input });
-  }
-  calls
-}
-
-enum AnthropicBlock {
-  Text(String),
-  ToolUse {
-    id: String,
-    name: String,
-    input_json: String,
-    input: Option<Value>,
-  },
-}
-
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::events::PermissionMode;
-  use crate::providers::registry::ProviderKind;
-  use crate::state::{ProviderSettings, SessionState};
 
   #[test]
-  fn build_anthropic_messages_merges_tool_results() {
-    let state = SessionState::new();
-    let provider = ProviderSettings {
-      provider: ProviderKind::Anthropic,
-      api_key: "test".into(),
-      model: "test".into(),
-      base_url: None,
-      permission_mode: PermissionMode::Ask,
-    };
-    let session = state.create_session("test".into(), None, provider);
-
-    state.add_message(&session.id, json!({ "type": "user_prompt", "prompt": "hi" }));
-    state.add_message(&session.id, json!({
-      "type": "assistant",
-      "message": {
-        "content": [
-          { "type": "tool_use", "id": "tool-1", "name": "Read", "input": { "file_path": "a.txt" } },
-          { "type": "tool_use", "id": "tool-2", "name": "Read", "input": { "file_path": "b.txt" } }
-        ]
-      }
-    }));
-    state.add_message(&session.id, json!({
-      "type": "user",
-      "message": {
-        "content": [
-          { "type": "tool_result", "tool_use_id": "tool-1", "content": "ok", "is_error": false }
-        ]
-      }
-    }));
-    state.add_message(&session.id, json!({
-      "type": "user",
-      "message": {
-        "content": [
-          { "type": "tool_result", "tool_use_id": "tool-2", "content": "ok2", "is_error": false }
-        ]
-      }
-    }));
-
-    let messages = build_anthropic_messages(&state, &session.id);
-    assert_eq!(messages.len(), 3);
-    let last = messages.last().expect("expected tool result message");
-    assert_eq!(last.get("role").and_then(Value::as_str), Some("user"));
-    let contents = last.get("content").and_then(Value::as_array).expect("content array");
-    assert_eq!(contents.len(), 2);
-    assert_eq!(contents[0].get("tool_use_id").and_then(Value::as_str), Some("tool-1"));
-    assert_eq!(contents[1].get("tool_use_id").and_then(Value::as_str), Some("tool-2"));
+  fn truncation_notice_mentions_dropped_calls() {
+    assert_eq!(
+      truncation_notice(Some(1024), true),
+      "Reply hit the max_tokens limit (1024); its unfinished tool calls were not run."
+    );
+    assert_eq!(truncation_notice(None, false), "Reply hit the max_tokens limit and may be incomplete.");
+  }
+
+  #[test]
+  fn retry_notice_rounds_delay_up() {
+    assert_eq!(retry_notice(Duration::from_millis(1500), 1, 4), "retrying in 2s (attempt 1/4)");
+    assert_eq!(retry_notice(Duration::from_secs(8), 3, 4), "retrying in 8s (attempt 3/4)");
   }
 
   #[test]
   fn build_tool_result_message_fills_error_content() {
-    let message = build_tool_result_message("tool-1", "", true);
+    let execution = ToolExecutionResult { content: String::new(), is_error: true, attachments: Vec::new() };
+    let message = build_tool_result_message("tool-1", &execution, None);
     let content = message
       .pointer("/message/content/0/content")
       .and_then(Value::as_str)
       .unwrap_or("");
     assert!(!content.trim().is_empty());
   }
+
+  #[test]
+  fn build_tool_result_message_keeps_the_diff() {
+    let execution = ToolExecutionResult { content: "Updated a.txt".into(), is_error: false, attachments: Vec::new() };
+    let message = build_tool_result_message("tool-1", &execution, Some("--- a/a.txt\n+++ b/a.txt\n"));
+    assert_eq!(message["message"]["content"][0]["diff"], "--- a/a.txt\n+++ b/a.txt\n");
+    assert!(build_tool_result_message("tool-1", &execution, Some(""))["message"]["content"][0].get("diff").is_none());
+  }
 }
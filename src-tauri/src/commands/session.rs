@@ -1,6 +1,7 @@
 use tauri::{AppHandle, Emitter, State};
 
 use crate::events::ServerEvent;
+use crate::providers::ollama;
 use crate::state::SessionState;
 
 #[tauri::command]
@@ -16,3 +17,8 @@ pub fn recent_cwds(state: State<SessionState>, limit: Option<usize>) -> Vec<Stri
   let limit = limit.unwrap_or(8).clamp(1, 20);
   state.list_recent_cwds(limit)
 }
+
+#[tauri::command]
+pub async fn ollama_models(base_url: Option<String>) -> Result<Vec<String>, String> {
+  ollama::list_models(base_url.as_deref()).await
+}
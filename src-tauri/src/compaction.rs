@@ -0,0 +1,391 @@
+use serde_json::{json, Value};
+
+use crate::providers::attachments::{self, Attachment, AttachmentKind};
+use crate::providers::registry::ProviderKind;
+
+/// Tool output older than the kept window is cut to this many characters when it is replayed
+/// or handed to the summariser.
+pub const OLD_TOOL_RESULT_CHARS: usize = 2_000;
+/// Share of the context window kept verbatim after a compaction; the rest becomes a summary.
+const KEEP_RECENT_FRACTION: f64 = 0.3;
+const TOOL_INPUT_CHARS: usize = 500;
+/// Share of the context window the summariser's transcript may take up.
+const TRANSCRIPT_FRACTION: f64 = 0.5;
+
+pub const SUMMARY_SYSTEM: &str =
+  "You summarise coding-agent sessions so that the work can continue in a fresh context window.";
+
+const SUMMARY_REQUEST: &str = "Summarise the conversation below for the agent that will continue it. Keep the \
+user's goals and constraints, decisions made, files read or changed (with paths), commands run and their important \
+results, errors and how they were resolved, and what remains to be done. Be concise, but do not drop details needed \
+to continue the work. Reply with the summary only.";
+
+/// Context window sizes in tokens, by model prefix. Unknown models get the provider's smallest
+/// current window so compaction errs on the early side.
+pub fn context_window(provider: &ProviderKind, model: &str) -> usize {
+  let model = model.trim().trim_start_matches("models/").to_ascii_lowercase();
+  match provider {
+    ProviderKind::Anthropic => 200_000,
+    ProviderKind::OpenAI => {
+      if model.starts_with("gpt-4.1") {
+        1_047_576
+      } else if model.starts_with("gpt-5") {
+        400_000
+      } else if ["o1", "o3", "o4"].iter().any(|prefix| model.starts_with(prefix)) {
+        200_000
+      } else {
+        128_000
+      }
+    }
+    ProviderKind::Gemini => 1_048_576,
+    // The server default `num_ctx`; larger windows need the env override.
+    ProviderKind::Ollama => 8_192,
+  }
+}
+
+/// Rough token count: about four characters per token over every string in the message.
+/// Attachments are costed by kind rather than by the length of their base64 data.
+pub fn estimate_tokens(value: &Value) -> usize {
+  fn chars(value: &Value) -> usize {
+    match value {
+      Value::String(text) => text.chars().count(),
+      Value::Array(items) => items.iter().map(chars).sum(),
+      Value::Object(map) => map
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "attachments" | "diff"))
+        .map(|(key, value)| key.len() + chars(value))
+        .sum(),
+      Value::Null => 0,
+      other => other.to_string().len(),
+    }
+  }
+  fn files(value: &Value) -> usize {
+    match value {
+      Value::Array(items) => items.iter().map(files).sum(),
+      Value::Object(map) => {
+        let own: usize = attachments::from_entry(value).iter().map(attachment_tokens).sum();
+        own + map.iter().filter(|(key, _)| key.as_str() != "attachments").map(|(_, value)| files(value)).sum::<usize>()
+      }
+      _ => 0,
+    }
+  }
+  chars(value).div_ceil(4) + 4 + files(value)
+}
+
+fn attachment_tokens(attachment: &Attachment) -> usize {
+  match attachment.kind() {
+    // Images are scaled to about 1.15 megapixels at most, which costs roughly this much.
+    Some(AttachmentKind::Image) => 1_600,
+    // PDF pages are sent as text plus an image each; about 2k tokens per 100 KB is a fair guess.
+    Some(AttachmentKind::Pdf) => attachment.decoded_len() / 50,
+    Some(AttachmentKind::Text) => attachment.decoded_len() / 4,
+    None => 0,
+  }
+}
+
+pub fn estimate_history(messages: &[Value]) -> usize {
+  messages.iter().filter(|message| !is_marker(message)).map(estimate_tokens).sum()
+}
+
+/// The history a request is built from: the latest compaction summary followed by the messages
+/// it did not cover. `stored_index[i]` is the position of `messages[i]` in the stored history.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Replay {
+  pub messages: Vec<Value>,
+  pub stored_index: Vec<usize>,
+  pub has_summary: bool,
+}
+
+pub fn replay(stored: &[Value]) -> Replay {
+  let latest = stored.iter().enumerate().rev().find_map(|(index, message)| {
+    let through = compaction_point(message)?;
+    Some((index, through.min(index)))
+  });
+  let mut replay = Replay::default();
+  let start = match latest {
+    Some((marker_index, through)) => {
+      let summary = stored[marker_index].get("summary").and_then(Value::as_str).unwrap_or_default();
+      replay.messages.push(summary_prompt(summary));
+      replay.stored_index.push(through);
+      replay.has_summary = true;
+      through
+    }
+    None => 0,
+  };
+  for (index, message) in stored.iter().enumerate().skip(start) {
+    if is_marker(message) {
+      continue;
+    }
+    replay.messages.push(message.clone());
+    replay.stored_index.push(index);
+  }
+  replay
+}
+
+fn compaction_point(message: &Value) -> Option<usize> {
+  if !is_marker(message) || message.get("kind").and_then(Value::as_str) != Some("compaction") {
+    return None;
+  }
+  message.get("compactedThrough").and_then(Value::as_u64).map(|value| value as usize)
+}
+
+fn is_marker(message: &Value) -> bool {
+  message.get("type").and_then(Value::as_str) == Some("marker")
+}
+
+fn summary_prompt(summary: &str) -> Value {
+  json!({
+    "type": "user_prompt",
+    "prompt": format!("This session was compacted. Summary of the earlier conversation:\n\n{}", summary)
+  })
+}
+
+/// Where older messages end and the kept tail begins: the earliest turn boundary whose tail fits
+/// in the keep budget, or the latest boundary when even that is larger. `None` when there is
+/// nothing new to summarise.
+pub fn split_point(replay: &Replay, window: usize) -> Option<usize> {
+  let keep_budget = (window as f64 * KEEP_RECENT_FRACTION) as usize;
+  let first_candidate = if replay.has_summary { 2 } else { 1 };
+  let mut tail_tokens = 0;
+  let mut split = None;
+  for index in (first_candidate..replay.messages.len()).rev() {
+    tail_tokens += estimate_tokens(&replay.messages[index]);
+    if !is_turn_boundary(&replay.messages[index]) {
+      continue;
+    }
+    if split.is_some() && tail_tokens > keep_budget {
+      break;
+    }
+    split = Some(index);
+  }
+  split
+}
+
+/// A request may start at a prompt or an assistant turn, never between a tool call and its result.
+fn is_turn_boundary(message: &Value) -> bool {
+  matches!(message.get("type").and_then(Value::as_str), Some("user_prompt" | "assistant"))
+}
+
+/// Shortens tool results in `messages` and drops the files they returned; used on the part of a
+/// request outside the kept tail.
+pub fn truncate_tool_results(messages: &mut [Value], max_chars: usize) {
+  for message in messages {
+    let Some(blocks) = message.pointer_mut("/message/content").and_then(Value::as_array_mut) else {
+      continue;
+    };
+    for block in blocks {
+      if block.get("type").and_then(Value::as_str) != Some("tool_result") {
+        continue;
+      }
+      if let Some(Value::String(content)) = block.get_mut("content") {
+        if let Some(short) = shorten(content, max_chars) {
+          *content = short;
+        }
+      }
+      if let Some(block) = block.as_object_mut() {
+        if block.remove("attachments").is_some() {
+          if let Some(Value::String(content)) = block.get_mut("content") {
+            content.push_str("\n[attached file omitted from older history]");
+          }
+        }
+      }
+    }
+  }
+}
+
+fn shorten(text: &str, max_chars: usize) -> Option<String> {
+  let total = text.chars().count();
+  if total <= max_chars {
+    return None;
+  }
+  let head: String = text.chars().take(max_chars).collect();
+  Some(format!("{}\n[... {} more characters omitted ...]", head, total - max_chars))
+}
+
+/// The single user prompt sent to the summariser: instructions plus a plain-text transcript. The
+/// oldest lines are dropped when the transcript would not fit in half of `window`.
+pub fn summary_request(messages: &[Value], window: usize) -> String {
+  let mut lines = Vec::new();
+  for message in messages {
+    match message.get("type").and_then(Value::as_str) {
+      Some("user_prompt") => {
+        let prompt = message.get("prompt").and_then(Value::as_str).unwrap_or_default();
+        lines.push(format!("User: {}", prompt));
+        let names: Vec<String> = attachments::from_entry(message).into_iter().map(|file| file.name).collect();
+        if !names.is_empty() {
+          lines.push(format!("User attached: {}", names.join(", ")));
+        }
+      }
+      Some("assistant") | Some("user") => {
+        let blocks = message.pointer("/message/content").and_then(Value::as_array);
+        for block in blocks.into_iter().flatten() {
+          if let Some(line) = transcript_line(block) {
+            lines.push(line);
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  let budget = (window as f64 * TRANSCRIPT_FRACTION) as usize * 4;
+  let mut used = 0;
+  let kept = lines.iter().rev().take_while(|line| {
+    used += line.chars().count() + 2;
+    used <= budget
+  });
+  let mut kept: Vec<&str> = kept.map(String::as_str).collect();
+  if kept.len() < lines.len() {
+    kept.push("[earlier transcript omitted]");
+  }
+  kept.reverse();
+  format!("{}\n\n<transcript>\n{}\n</transcript>", SUMMARY_REQUEST, kept.join("\n\n"))
+}
+
+fn transcript_line(block: &Value) -> Option<String> {
+  let text = |key: &str| block.get(key).and_then(Value::as_str).unwrap_or_default();
+  match block.get("type").and_then(Value::as_str)? {
+    "text" => Some(format!("Assistant: {}", text("text"))),
+    "tool_use" => {
+      let input = block.get("input").map(Value::to_string).unwrap_or_default();
+      let input = shorten(&input, TOOL_INPUT_CHARS).unwrap_or(input);
+      Some(format!("Assistant called {} with {}", text("name"), input))
+    }
+    "tool_result" => {
+      let content = match block.get("content") {
+        Some(Value::String(content)) => content.clone(),
+        Some(other) => other.to_string(),
+        None => String::new(),
+      };
+      let content = shorten(&content, OLD_TOOL_RESULT_CHARS).unwrap_or(content);
+      let is_error = block.get("is_error").and_then(Value::as_bool) == Some(true);
+      Some(format!("{}: {}", if is_error { "Tool error" } else { "Tool result" }, content))
+    }
+    _ => None,
+  }
+}
+
+pub fn compaction_marker(summary: &str, compacted_through: usize, messages: usize, tokens_before: usize) -> Value {
+  json!({
+    "type": "marker",
+    "kind": "compaction",
+    "text": format!(
+      "Compacted {} earlier messages (~{}k tokens) into a summary.",
+      messages,
+      tokens_before.div_ceil(1000)
+    ),
+    "summary": summary,
+    "compactedThrough": compacted_through
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn prompt(text: &str) -> Value {
+    json!({ "type": "user_prompt", "prompt": text })
+  }
+
+  fn assistant(text: &str) -> Value {
+    json!({ "type": "assistant", "message": { "content": [{ "type": "text", "text": text }] } })
+  }
+
+  fn tool_turn(id: &str, output: &str) -> [Value; 2] {
+    [
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "tool_use", "id": id, "name": "Read", "input": { "file_path": "a.txt" } }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": id, "content": output, "is_error": false }
+      ] } }),
+    ]
+  }
+
+  #[test]
+  fn estimate_grows_with_content() {
+    let short = estimate_tokens(&prompt("hi"));
+    let long = estimate_tokens(&prompt(&"word ".repeat(400)));
+    assert!(long >= short + 400);
+    let markers = [json!({ "type": "marker", "kind": "interrupted", "text": "x".repeat(1000) })];
+    assert_eq!(estimate_history(&markers), 0);
+
+    let image = json!({
+      "type": "user_prompt",
+      "prompt": "hi",
+      "attachments": [{ "name": "a.png", "mediaType": "image/png", "data": "A".repeat(400_000) }]
+    });
+    assert_eq!(estimate_tokens(&image), short + 1_600);
+  }
+
+  #[test]
+  fn replay_starts_from_the_latest_summary() {
+    let mut stored = vec![prompt("one"), assistant("a1"), prompt("two"), assistant("a2")];
+    stored.push(compaction_marker("did one", 2, 2, 5_000));
+    stored.push(prompt("three"));
+
+    let replay = replay(&stored);
+    assert!(replay.has_summary);
+    assert!(replay.messages[0]["prompt"].as_str().unwrap().ends_with("did one"));
+    assert_eq!(replay.messages[1], prompt("two"));
+    assert_eq!(replay.stored_index, vec![2, 2, 3, 5]);
+    assert_eq!(replay.messages.len(), 4);
+  }
+
+  #[test]
+  fn split_point_keeps_recent_turns_and_never_splits_tool_pairs() {
+    let big = "x".repeat(40_000);
+    let mut stored = vec![prompt("start")];
+    stored.extend(tool_turn("t1", &big));
+    stored.extend(tool_turn("t2", &big));
+    stored.push(prompt("latest"));
+    stored.extend(tool_turn("t3", "small"));
+
+    let replay = replay(&stored);
+    // A 30k window keeps ~9k tokens: the latest prompt and its small tool turn, not the 10k result.
+    let split = split_point(&replay, 30_000).unwrap();
+    assert_eq!(replay.messages[split], prompt("latest"));
+    assert_eq!(split_point(&replay, 1_000_000), Some(1));
+
+    let single = super::replay(&[prompt("only")]);
+    assert_eq!(split_point(&single, 1_000), None);
+  }
+
+  #[test]
+  fn truncates_long_tool_results_only() {
+    let mut messages = tool_turn("t1", &"y".repeat(5_000)).to_vec();
+    messages.push(assistant(&"z".repeat(5_000)));
+    truncate_tool_results(&mut messages, 100);
+    let content = messages[1]["message"]["content"][0]["content"].as_str().unwrap();
+    assert!(content.ends_with("[... 4900 more characters omitted ...]"));
+    assert_eq!(messages[2]["message"]["content"][0]["text"].as_str().unwrap().len(), 5_000);
+  }
+
+  #[test]
+  fn costs_and_drops_files_returned_by_tools() {
+    let mut messages = tool_turn("t1", "Read a.png (image/png, 1 KB); its contents are attached.").to_vec();
+    let before = estimate_history(&messages);
+    messages[1]["message"]["content"][0]["attachments"] =
+      json!([{ "name": "a.png", "mediaType": "image/png", "data": "iVBORw0KGgo=" }]);
+    assert_eq!(estimate_history(&messages), before + 1_600);
+
+    truncate_tool_results(&mut messages, 2_000);
+    let block = &messages[1]["message"]["content"][0];
+    assert!(block.get("attachments").is_none());
+    assert!(block["content"].as_str().unwrap().ends_with("[attached file omitted from older history]"));
+  }
+
+  #[test]
+  fn summary_request_renders_a_transcript() {
+    let mut messages = vec![prompt("fix the build")];
+    messages.extend(tool_turn("t1", "error: missing semicolon"));
+    let request = summary_request(&messages, 100_000);
+    assert!(request.contains("User: fix the build"));
+    assert!(request.contains("Assistant called Read with {\"file_path\":\"a.txt\"}"));
+    assert!(request.contains("Tool result: error: missing semicolon"));
+
+    let small = summary_request(&messages, 30);
+    assert!(small.contains("[earlier transcript omitted]"));
+    assert!(!small.contains("User: fix the build"));
+    assert!(small.contains("Tool result: error: missing semicolon"));
+  }
+}
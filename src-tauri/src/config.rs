@@ -1,4 +1,18 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::providers::retry::RetryPolicy;
+use crate::tools::workspace::OutsideAccess;
+
 const MAX_TOOL_ITERATIONS_ENV: &str = "OPEN_COWORK_MAX_TOOL_ITERATIONS";
+const MAX_RETRIES_ENV: &str = "OPEN_COWORK_MAX_RETRIES";
+const RETRY_BASE_DELAY_MS_ENV: &str = "OPEN_COWORK_RETRY_BASE_DELAY_MS";
+const RETRY_MAX_DELAY_MS_ENV: &str = "OPEN_COWORK_RETRY_MAX_DELAY_MS";
+const COMPACT_THRESHOLD_ENV: &str = "OPEN_COWORK_COMPACT_THRESHOLD";
+const CONTEXT_WINDOW_ENV: &str = "OPEN_COWORK_CONTEXT_WINDOW";
+const ALLOWED_DIRS_ENV: &str = "OPEN_COWORK_ALLOWED_DIRS";
+const OUTSIDE_WORKSPACE_ENV: &str = "OPEN_COWORK_OUTSIDE_WORKSPACE";
+const DEFAULT_COMPACT_THRESHOLD: u8 = 80;
 
 pub fn max_tool_iterations() -> usize {
   let value = std::env::var(MAX_TOOL_ITERATIONS_ENV).ok();
@@ -18,13 +32,75 @@ fn parse_max_tool_iterations(value: Option<&str>) -> usize {
     .unwrap_or(0)
 }
 
+/// Retry budget for model requests; each knob falls back to `RetryPolicy::default()` when unset.
+pub fn retry_policy() -> RetryPolicy {
+  let env = |name: &str| std::env::var(name).ok();
+  parse_retry_policy(
+    env(MAX_RETRIES_ENV).as_deref(),
+    env(RETRY_BASE_DELAY_MS_ENV).as_deref(),
+    env(RETRY_MAX_DELAY_MS_ENV).as_deref(),
+  )
+}
+
+fn parse_retry_policy(max_retries: Option<&str>, base_ms: Option<&str>, max_ms: Option<&str>) -> RetryPolicy {
+  let defaults = RetryPolicy::default();
+  let parse = |value: Option<&str>| value.and_then(|raw| raw.trim().parse::<u64>().ok());
+  RetryPolicy {
+    max_retries: parse(max_retries).map_or(defaults.max_retries, |value| value.min(u32::MAX as u64) as u32),
+    base_delay: parse(base_ms).map_or(defaults.base_delay, Duration::from_millis),
+    max_delay: parse(max_ms).map_or(defaults.max_delay, Duration::from_millis),
+  }
+}
+
+/// Percentage of the context window at which history is compacted before a request; 0 disables it.
+pub fn compaction_threshold() -> u8 {
+  parse_compaction_threshold(std::env::var(COMPACT_THRESHOLD_ENV).ok().as_deref())
+}
+
+fn parse_compaction_threshold(value: Option<&str>) -> u8 {
+  value
+    .and_then(|raw| raw.trim().parse::<u8>().ok())
+    .map_or(DEFAULT_COMPACT_THRESHOLD, |percent| percent.min(100))
+}
+
+/// Overrides the built-in context window size, e.g. for an Ollama model run with a larger `num_ctx`.
+pub fn context_window_override() -> Option<usize> {
+  parse_context_window(std::env::var(CONTEXT_WINDOW_ENV).ok().as_deref())
+}
+
+fn parse_context_window(value: Option<&str>) -> Option<usize> {
+  value.and_then(|raw| raw.trim().parse::<usize>().ok()).filter(|tokens| *tokens > 0)
+}
+
+/// Directories file tools may use besides the session cwd, as a `PATH`-style list.
+pub fn extra_workspace_roots() -> Vec<PathBuf> {
+  std::env::var_os(ALLOWED_DIRS_ENV)
+    .map(|value| std::env::split_paths(&value).filter(|path| !path.as_os_str().is_empty()).collect())
+    .unwrap_or_default()
+}
+
+/// `deny` refuses file access outside the workspace; anything else asks the user.
+pub fn outside_workspace_access() -> OutsideAccess {
+  parse_outside_access(std::env::var(OUTSIDE_WORKSPACE_ENV).ok().as_deref())
+}
+
+fn parse_outside_access(value: Option<&str>) -> OutsideAccess {
+  match value.map(|raw| raw.trim().to_ascii_lowercase()).as_deref() {
+    Some("deny") => OutsideAccess::Deny,
+    _ => OutsideAccess::Ask,
+  }
+}
+
 pub fn should_stop_tool_loop(iterations: usize, max_iterations: usize) -> bool {
   max_iterations > 0 && iterations >= max_iterations
 }
 
 #[cfg(test)]
 mod tests {
-  use super::{parse_max_tool_iterations, should_stop_tool_loop};
+  use super::{
+    parse_compaction_threshold, parse_context_window, parse_max_tool_iterations, parse_outside_access,
+    parse_retry_policy, should_stop_tool_loop, Duration, OutsideAccess, RetryPolicy,
+  };
 
   #[test]
   fn parse_max_tool_iterations_defaults_to_zero() {
@@ -52,4 +128,32 @@ mod tests {
     assert!(!should_stop_tool_loop(0, 0));
     assert!(!should_stop_tool_loop(100, 0));
   }
+
+  #[test]
+  fn parse_retry_policy_overrides_defaults() {
+    assert_eq!(parse_retry_policy(None, None, None), RetryPolicy::default());
+    assert_eq!(parse_retry_policy(Some("x"), Some(""), None), RetryPolicy::default());
+    let policy = parse_retry_policy(Some("0"), Some("250"), Some(" 5000 "));
+    assert_eq!(policy.max_retries, 0);
+    assert_eq!(policy.base_delay, Duration::from_millis(250));
+    assert_eq!(policy.max_delay, Duration::from_secs(5));
+  }
+
+  #[test]
+  fn parse_compaction_settings() {
+    assert_eq!(parse_compaction_threshold(None), 80);
+    assert_eq!(parse_compaction_threshold(Some("junk")), 80);
+    assert_eq!(parse_compaction_threshold(Some(" 0 ")), 0);
+    assert_eq!(parse_compaction_threshold(Some("150")), 100);
+    assert_eq!(parse_context_window(Some("32768")), Some(32_768));
+    assert_eq!(parse_context_window(Some("0")), None);
+    assert_eq!(parse_context_window(None), None);
+  }
+
+  #[test]
+  fn parse_outside_access_defaults_to_ask() {
+    assert_eq!(parse_outside_access(None), OutsideAccess::Ask);
+    assert_eq!(parse_outside_access(Some("bogus")), OutsideAccess::Ask);
+    assert_eq!(parse_outside_access(Some(" Deny ")), OutsideAccess::Deny);
+  }
 }
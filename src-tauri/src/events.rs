@@ -1,13 +1,18 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::providers::registry::ProviderKind;
+use crate::permissions::MatchedRule;
+use crate::providers::attachments::Attachment;
+use crate::providers::params::GenerationParams;
+use crate::providers::registry::{OpenAIApi, ProviderKind};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum PermissionMode {
   Ask,
   Auto,
+  /// Read-only tools only, until the user approves a plan presented with `ExitPlanMode`.
+  Plan,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -33,6 +38,51 @@ pub struct SessionInfo {
   pub model: Option<String>,
   pub created_at: i64,
   pub updated_at: i64,
+  #[serde(default)]
+  pub usage: UsageTotals,
+}
+
+/// Token counts and estimated cost; `unpriced_calls` counts calls to models without a known price.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+  pub calls: u64,
+  pub input_tokens: u64,
+  pub output_tokens: u64,
+  pub cache_read_tokens: u64,
+  pub cache_write_tokens: u64,
+  pub cost_usd: f64,
+  pub unpriced_calls: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyUsage {
+  /// Local calendar day, `YYYY-MM-DD`.
+  pub date: String,
+  #[serde(flatten)]
+  pub usage: UsageTotals,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+  pub message_index: usize,
+  pub kind: String,
+  /// Plain text; `highlights` holds the matched ranges.
+  pub snippet: String,
+  /// `[start, end)` ranges in `snippet`, in UTF-16 code units.
+  #[serde(default)]
+  pub highlights: Vec<[usize; 2]>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSearchResult {
+  pub session_id: String,
+  pub title: String,
+  pub updated_at: i64,
+  pub matches: Vec<SearchMatch>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -64,6 +114,19 @@ pub enum ServerEvent {
     #[serde(rename = "sessionId")]
     session_id: String,
   },
+  #[serde(rename = "session.search_results")]
+  SearchResults {
+    query: String,
+    results: Vec<SessionSearchResult>,
+  },
+  #[serde(rename = "session.usage")]
+  SessionUsage {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    usage: UsageTotals,
+  },
+  #[serde(rename = "usage.report")]
+  UsageReport { days: Vec<DailyUsage> },
   #[serde(rename = "stream.message")]
   StreamMessage {
     #[serde(rename = "sessionId")]
@@ -75,6 +138,8 @@ pub enum ServerEvent {
     #[serde(rename = "sessionId")]
     session_id: String,
     prompt: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
   },
   #[serde(rename = "permission.request")]
   PermissionRequest {
@@ -85,6 +150,29 @@ pub enum ServerEvent {
     #[serde(rename = "toolName")]
     tool_name: String,
     input: Value,
+    /// Unified diff of the change, for file-writing tools.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+    /// Why this call was not auto-approved, e.g. it reaches outside the workspace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// The permission rule that matched the call, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<MatchedRule>,
+    /// A generalized rule covering this call, offered as "allow for session" or "always allow in
+    /// project"; the choice comes back as `updatedPermissions` in `permission.response`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<String>,
+  },
+  /// Sent after `session.rewind`, followed by the shortened `session.history`; `prompt` is the
+  /// rewound prompt, so it can be edited and sent again.
+  #[serde(rename = "session.rewound")]
+  SessionRewound {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    prompt: String,
+    #[serde(rename = "restoredFiles")]
+    restored_files: Vec<String>,
   },
   #[serde(rename = "runner.error")]
   RunnerError {
@@ -118,14 +206,27 @@ pub enum ClientEvent {
     base_url: Option<String>,
     #[serde(rename = "permissionMode", skip_serializing_if = "Option::is_none")]
     permission_mode: Option<PermissionMode>,
+    /// Comma-separated permission rules allowed without asking, e.g. `Read,Bash(git status:*)`.
     #[serde(rename = "allowedTools", skip_serializing_if = "Option::is_none")]
     allowed_tools: Option<String>,
+    /// Only read for the OpenAI provider; defaults to Chat Completions.
+    #[serde(rename = "openaiApi", skip_serializing_if = "Option::is_none")]
+    openai_api: Option<OpenAIApi>,
+    #[serde(rename = "generationParams", skip_serializing_if = "Option::is_none")]
+    generation_params: Option<GenerationParams>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
   },
   #[serde(rename = "session.continue")]
   SessionContinue {
     #[serde(rename = "sessionId")]
     session_id: String,
     prompt: String,
+    /// Keys are kept in memory only, so a session restored after a restart needs it again.
+    #[serde(rename = "apiKey", default, skip_serializing_if = "Option::is_none")]
+    api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
   },
   #[serde(rename = "session.stop")]
   SessionStop {
@@ -137,6 +238,31 @@ pub enum ClientEvent {
     #[serde(rename = "sessionId")]
     session_id: String,
   },
+  #[serde(rename = "session.search")]
+  SessionSearch { query: String },
+  /// Summarises the older part of an idle session's history now instead of waiting for the threshold.
+  #[serde(rename = "session.compact")]
+  SessionCompact {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(rename = "apiKey", default, skip_serializing_if = "Option::is_none")]
+    api_key: Option<String>,
+  },
+  /// Restores files changed since message `to_message_index` (a user prompt) and drops the history
+  /// from that message on.
+  #[serde(rename = "session.rewind")]
+  SessionRewind {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(rename = "toMessageIndex")]
+    to_message_index: usize,
+  },
+  /// Requests daily totals for the last `days` days (default 30).
+  #[serde(rename = "usage.report")]
+  UsageReport {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    days: Option<u32>,
+  },
   #[serde(rename = "permission.response")]
   PermissionResponse {
     #[serde(rename = "sessionId")]
@@ -157,4 +283,63 @@ mod tests {
     let json = serde_json::to_string(&event).unwrap();
     assert!(json.contains("\"type\":\"session.list\""));
   }
+
+  #[test]
+  fn session_search_round_trips() {
+    let event: ClientEvent =
+      serde_json::from_str(r#"{"type":"session.search","payload":{"query":"migration"}}"#).unwrap();
+    assert_eq!(event, ClientEvent::SessionSearch { query: "migration".into() });
+
+    let results = ServerEvent::SearchResults {
+      query: "migration".into(),
+      results: vec![SessionSearchResult {
+        session_id: "s1".into(),
+        title: "t".into(),
+        updated_at: 1,
+        matches: vec![SearchMatch {
+          message_index: 3,
+          kind: "assistant".into(),
+          snippet: "x".into(),
+          highlights: vec![[0, 1]],
+        }],
+      }],
+    };
+    let json = serde_json::to_value(&results).unwrap();
+    assert_eq!(json["type"], "session.search_results");
+    assert_eq!(json["payload"]["results"][0]["matches"][0]["messageIndex"], 3);
+    assert_eq!(json["payload"]["results"][0]["matches"][0]["highlights"], serde_json::json!([[0, 1]]));
+  }
+
+  #[test]
+  fn usage_report_flattens_daily_totals() {
+    let event: ClientEvent = serde_json::from_str(r#"{"type":"usage.report","payload":{}}"#).unwrap();
+    assert_eq!(event, ClientEvent::UsageReport { days: None });
+
+    let report = ServerEvent::UsageReport {
+      days: vec![DailyUsage {
+        date: "2026-10-17".into(),
+        usage: UsageTotals { calls: 2, cost_usd: 0.5, ..UsageTotals::default() },
+      }],
+    };
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["payload"]["days"][0]["date"], "2026-10-17");
+    assert_eq!(json["payload"]["days"][0]["calls"], 2);
+    assert_eq!(json["payload"]["days"][0]["costUsd"], 0.5);
+  }
+
+  #[test]
+  fn rewind_events_use_camel_case() {
+    let event: ClientEvent =
+      serde_json::from_str(r#"{"type":"session.rewind","payload":{"sessionId":"s1","toMessageIndex":4}}"#).unwrap();
+    assert_eq!(event, ClientEvent::SessionRewind { session_id: "s1".into(), to_message_index: 4 });
+
+    let rewound = ServerEvent::SessionRewound {
+      session_id: "s1".into(),
+      prompt: "fix it".into(),
+      restored_files: vec!["/w/a.txt".into()],
+    };
+    let json = serde_json::to_value(&rewound).unwrap();
+    assert_eq!(json["type"], "session.rewound");
+    assert_eq!(json["payload"]["restoredFiles"][0], "/w/a.txt");
+  }
 }
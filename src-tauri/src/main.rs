@@ -1,13 +1,20 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod checkpoints;
 mod commands;
+mod compaction;
 mod config;
 mod events;
+mod permissions;
 mod providers;
 mod state;
+mod storage;
+mod system_prompt;
 mod tools;
 
+use tauri::Manager;
+
 #[tauri::command]
 fn ping_cmd() -> &'static str {
   app_lib::ping()
@@ -15,12 +22,24 @@ fn ping_cmd() -> &'static str {
 
 fn main() {
   tauri::Builder::default()
-    .manage(state::SessionState::new())
+    .setup(|app| {
+      let state = match app.path().app_data_dir() {
+        Ok(dir) => state::SessionState::open(&dir),
+        Err(error) => Err(error.to_string()),
+      }
+      .unwrap_or_else(|error| {
+        log::error!("session database unavailable, history will not be saved: {}", error);
+        state::SessionState::new()
+      });
+      app.manage(state);
+      Ok(())
+    })
     .plugin(tauri_plugin_dialog::init())
     .invoke_handler(tauri::generate_handler![
       ping_cmd,
       commands::session::session_list,
       commands::session::recent_cwds,
+      commands::session::ollama_models,
       commands::client_event::client_event
     ])
     .run(tauri::generate_context!())
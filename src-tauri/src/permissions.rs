@@ -0,0 +1,597 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::events::PermissionMode;
+use crate::tools;
+use crate::tools::workspace::real_path;
+
+/// Read from the user's home directory and from the session cwd, in that order.
+pub const SETTINGS_FILE: &str = ".open-cowork/settings.json";
+/// Where rules from `session.start` (`allowedTools`) are reported as coming from.
+pub const SESSION_SOURCE: &str = "session";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleBehavior {
+  Allow,
+  Deny,
+  Ask,
+}
+
+/// `Tool` or `Tool(specifier)`. `Edit` rules also cover `Write` and `MultiEdit`, `Read` rules also
+/// cover `Glob` and `Grep`. Specifiers are a command (`git status`, or `git status:*` for a prefix)
+/// for `Bash`, a path glob for file tools and `domain:<host>` for `WebFetch`. Grep prints file
+/// contents, so a deny or ask `Read` rule also catches a search whose root could reach its files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+  tool: String,
+  specifier: Option<String>,
+}
+
+impl Rule {
+  pub fn parse(raw: &str) -> Result<Self, String> {
+    let raw = raw.trim();
+    let (tool, specifier) = match raw.split_once('(') {
+      Some((tool, rest)) => {
+        let specifier = rest
+          .strip_suffix(')')
+          .ok_or_else(|| format!("Invalid permission rule `{}`: missing `)`.", raw))?;
+        if specifier.trim().is_empty() {
+          return Err(format!("Invalid permission rule `{}`: empty specifier.", raw));
+        }
+        (tool.trim(), Some(specifier.trim().to_string()))
+      }
+      None => (raw, None),
+    };
+    if tool.is_empty() || !tool.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+      return Err(format!("Invalid permission rule `{}`: bad tool name.", raw));
+    }
+    Ok(Self { tool: tool.to_string(), specifier })
+  }
+
+  /// With `strict`, a `Bash` rule only matches a single plain command, so an allow rule for
+  /// `git status:*` does not let `git status; rm -rf ~` through.
+  fn matches(&self, call: &Call, strict: bool) -> bool {
+    if !covers(&self.tool, call.name) {
+      return false;
+    }
+    let specifier = match self.specifier.as_deref() {
+      None | Some("*") => return true,
+      Some(specifier) => specifier,
+    };
+    match call.name {
+      "Bash" => call.string("command").is_some_and(|command| command_matches(specifier, &command, strict)),
+      "WebFetch" => specifier
+        .strip_prefix("domain:")
+        .zip(call.string("url"))
+        .is_some_and(|(domain, url)| host_matches(domain, &url)),
+      _ => {
+        let paths = tools::accessed_paths(call.name, call.input, call.cwd);
+        let Some(pattern) = path_pattern(specifier, call.cwd) else {
+          return false;
+        };
+        paths_match(&pattern, &paths)
+          || (!strict && call.name == "Grep" && paths.iter().any(|root| search_reaches(&pattern, root)))
+      }
+    }
+  }
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.specifier {
+      Some(specifier) => write!(f, "{}({})", self.tool, specifier),
+      None => write!(f, "{}", self.tool),
+    }
+  }
+}
+
+/// The rule that decided a tool call and the settings file (or `session`) it came from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchedRule {
+  pub behavior: RuleBehavior,
+  pub rule: String,
+  pub source: String,
+}
+
+#[derive(Debug, Clone)]
+struct SourcedRule {
+  behavior: RuleBehavior,
+  rule: Rule,
+  source: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SettingsFile {
+  #[serde(default)]
+  permissions: PermissionSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PermissionSettings {
+  #[serde(default)]
+  allow: Vec<String>,
+  #[serde(default)]
+  deny: Vec<String>,
+  #[serde(default)]
+  ask: Vec<String>,
+}
+
+/// Rules from the settings files plus the session's allowed tools. Deny rules win over ask rules,
+/// which win over allow rules, wherever they were defined.
+#[derive(Debug, Clone, Default)]
+pub struct PermissionPolicy {
+  rules: Vec<SourcedRule>,
+}
+
+impl PermissionPolicy {
+  pub fn load(cwd: Option<&str>, allowed_tools: &[String]) -> Self {
+    let mut policy = Self::default();
+    let mut files: Vec<PathBuf> = home_dir().map(|home| home.join(SETTINGS_FILE)).into_iter().collect();
+    if let Some(cwd) = cwd {
+      let project = Path::new(cwd).join(SETTINGS_FILE);
+      if !files.contains(&project) {
+        files.push(project);
+      }
+    }
+    for path in files.iter().filter(|path| path.is_file()) {
+      if let Err(error) = policy.add_settings_file(path) {
+        log::warn!("ignoring {}: {}", path.display(), error);
+      }
+    }
+    for raw in allowed_tools {
+      policy.add(RuleBehavior::Allow, raw, SESSION_SOURCE);
+    }
+    policy
+  }
+
+  fn add_settings_file(&mut self, path: &Path) -> Result<(), String> {
+    let raw = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let settings: SettingsFile = serde_json::from_str(&raw).map_err(|error| error.to_string())?;
+    let source = path.display().to_string();
+    let PermissionSettings { allow, deny, ask } = settings.permissions;
+    for (behavior, rules) in [(RuleBehavior::Deny, deny), (RuleBehavior::Ask, ask), (RuleBehavior::Allow, allow)] {
+      for raw in &rules {
+        self.add(behavior, raw, &source);
+      }
+    }
+    Ok(())
+  }
+
+  pub fn add(&mut self, behavior: RuleBehavior, raw: &str, source: &str) {
+    match Rule::parse(raw) {
+      Ok(rule) => self.rules.push(SourcedRule { behavior, rule, source: source.to_string() }),
+      Err(error) => log::warn!("{} ({})", error, source),
+    }
+  }
+
+  /// The strongest rule matching the call, or `None` to fall back to the permission mode.
+  pub fn evaluate(&self, name: &str, input: &Value, cwd: Option<&str>) -> Option<MatchedRule> {
+    let call = Call { name, input, cwd };
+    [RuleBehavior::Deny, RuleBehavior::Ask, RuleBehavior::Allow]
+      .into_iter()
+      .find_map(|behavior| {
+        let strict = behavior == RuleBehavior::Allow;
+        self
+          .rules
+          .iter()
+          .find(|sourced| sourced.behavior == behavior && sourced.rule.matches(&call, strict))
+      })
+      .map(|sourced| MatchedRule {
+        behavior: sourced.behavior,
+        rule: sourced.rule.to_string(),
+        source: sourced.source.clone(),
+      })
+  }
+}
+
+/// Where a rule picked in the permission prompt is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleDestination {
+  /// The session's allowed tools, stored with the session.
+  Session,
+  /// The project settings file under the session cwd.
+  Project,
+}
+
+/// An allow rule chosen together with a permission decision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleUpdate {
+  pub destination: RuleDestination,
+  pub rule: String,
+}
+
+/// Reads the `updatedPermissions` of a `permission.response` result, in the SDK's shape:
+/// `{ "type": "addRules", "behavior": "allow", "destination": "session" | "projectSettings",
+/// "rules": [{ "toolName": "Bash", "ruleContent": "git status:*" }] }`. Invalid entries are skipped.
+pub fn rule_updates(result: &Value) -> Vec<RuleUpdate> {
+  let Some(updates) = result.get("updatedPermissions").and_then(Value::as_array) else {
+    return Vec::new();
+  };
+  let mut rules = Vec::new();
+  for update in updates {
+    let field = |key: &str| update.get(key).and_then(Value::as_str);
+    if field("type") != Some("addRules") || field("behavior") != Some("allow") {
+      continue;
+    }
+    let destination = match field("destination") {
+      Some("session") => RuleDestination::Session,
+      Some("projectSettings") => RuleDestination::Project,
+      _ => continue,
+    };
+    for value in update.get("rules").and_then(Value::as_array).into_iter().flatten() {
+      let Some(tool) = value.get("toolName").and_then(Value::as_str) else {
+        continue;
+      };
+      let raw = match value.get("ruleContent").and_then(Value::as_str) {
+        Some(content) => format!("{}({})", tool, content),
+        None => tool.to_string(),
+      };
+      match Rule::parse(&raw) {
+        Ok(rule) => rules.push(RuleUpdate { destination, rule: rule.to_string() }),
+        Err(error) => log::warn!("{}", error),
+      }
+    }
+  }
+  rules
+}
+
+/// The mode picked when approving a plan: a `setMode` entry in `updatedPermissions`, with the SDK's
+/// `default` as `Ask` and `acceptEdits` or `bypassPermissions` as `Auto`.
+pub fn mode_update(result: &Value) -> Option<PermissionMode> {
+  result
+    .get("updatedPermissions")?
+    .as_array()?
+    .iter()
+    .filter(|update| update.get("type").and_then(Value::as_str) == Some("setMode"))
+    .find_map(|update| match update.get("mode").and_then(Value::as_str)? {
+      "default" => Some(PermissionMode::Ask),
+      "acceptEdits" | "bypassPermissions" => Some(PermissionMode::Auto),
+      _ => None,
+    })
+}
+
+/// Appends an allow rule to `<cwd>/.open-cowork/settings.json`, keeping whatever else the file holds.
+pub fn save_project_rule(cwd: &Path, rule: &str) -> Result<PathBuf, String> {
+  let path = cwd.join(SETTINGS_FILE);
+  let mut settings = match std::fs::read_to_string(&path) {
+    Ok(raw) => serde_json::from_str::<Value>(&raw).map_err(|error| format!("{}: {}", path.display(), error))?,
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => json!({}),
+    Err(error) => return Err(format!("{}: {}", path.display(), error)),
+  };
+  let Some(root) = settings.as_object_mut() else {
+    return Err(format!("{}: expected a JSON object", path.display()));
+  };
+  let permissions = root.entry("permissions").or_insert_with(|| json!({}));
+  let Some(allow) = permissions
+    .as_object_mut()
+    .map(|permissions| permissions.entry("allow").or_insert_with(|| json!([])))
+    .and_then(Value::as_array_mut)
+  else {
+    return Err(format!("{}: `permissions.allow` must be an array", path.display()));
+  };
+  if !allow.iter().any(|existing| existing.as_str() == Some(rule)) {
+    allow.push(Value::String(rule.to_string()));
+  }
+
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent).map_err(|error| format!("{}: {}", parent.display(), error))?;
+  }
+  let encoded = serde_json::to_string_pretty(&settings).map_err(|error| error.to_string())?;
+  std::fs::write(&path, encoded + "\n").map_err(|error| format!("{}: {}", path.display(), error))?;
+  Ok(path)
+}
+
+/// A rule covering this call and similar ones, offered when the user approves it: the command
+/// and its subcommand for `Bash`, the directory for file tools, the host for `WebFetch`.
+pub fn suggest_rule(name: &str, input: &Value, cwd: Option<&str>) -> Option<String> {
+  let call = Call { name, input, cwd };
+  match name {
+    "AskUserQuestion" => None,
+    "Bash" => {
+      let command = call.string("command")?;
+      let segments = command_segments(&command);
+      if segments.len() != 1 || ["$(", "`", ">", "<("].iter().any(|token| command.contains(token)) {
+        return None;
+      }
+      let words: Vec<&str> = segments[0].split_whitespace().collect();
+      let take = if words.len() > 1 && SUBCOMMAND_TOOLS.contains(&words[0]) && !words[1].starts_with('-') {
+        2
+      } else {
+        1
+      };
+      Some(format!("Bash({}:*)", words[..take].join(" ")))
+    }
+    "WebFetch" => {
+      let url = reqwest::Url::parse(&call.string("url")?).ok()?;
+      Some(format!("WebFetch(domain:{})", url.host_str()?))
+    }
+    "Read" | "Write" | "Edit" | "MultiEdit" | "Glob" | "Grep" => {
+      let family = if matches!(name, "Write" | "Edit" | "MultiEdit") { "Edit" } else { "Read" };
+      let path = tools::accessed_paths(name, input, cwd).into_iter().next()?;
+      let dir = if matches!(name, "Glob" | "Grep") { path.as_path() } else { path.parent()? };
+      let relative = cwd.and_then(|cwd| dir.strip_prefix(cwd).ok());
+      let pattern = match relative {
+        Some(relative) if relative.as_os_str().is_empty() => "**".to_string(),
+        Some(relative) => format!("{}/**", relative.display()),
+        None => format!("{}/**", dir.display()),
+      };
+      Some(format!("{}({})", family, pattern))
+    }
+    _ => Some(name.to_string()),
+  }
+}
+
+/// Commands whose first argument picks what they do, so `git push` is suggested apart from `git status`.
+const SUBCOMMAND_TOOLS: &[&str] = &["cargo", "docker", "git", "go", "kubectl", "npm", "pnpm", "yarn"];
+
+/// Splits `allowedTools` (`Read,Bash(git diff:*)`) on the commas outside parentheses.
+pub fn split_rule_list(raw: &str) -> Vec<String> {
+  let mut rules = Vec::new();
+  let mut current = String::new();
+  let mut depth = 0usize;
+  for ch in raw.chars() {
+    match ch {
+      '(' => depth += 1,
+      ')' => depth = depth.saturating_sub(1),
+      ',' if depth == 0 => {
+        rules.push(std::mem::take(&mut current));
+        continue;
+      }
+      _ => {}
+    }
+    current.push(ch);
+  }
+  rules.push(current);
+  rules.into_iter().map(|rule| rule.trim().to_string()).filter(|rule| !rule.is_empty()).collect()
+}
+
+struct Call<'a> {
+  name: &'a str,
+  input: &'a Value,
+  cwd: Option<&'a str>,
+}
+
+impl Call<'_> {
+  fn string(&self, key: &str) -> Option<String> {
+    self.input.get(key).and_then(Value::as_str).map(str::to_string)
+  }
+}
+
+fn covers(rule_tool: &str, name: &str) -> bool {
+  rule_tool == name
+    || match rule_tool {
+      "Edit" => matches!(name, "Write" | "MultiEdit"),
+      "Read" => matches!(name, "Glob" | "Grep"),
+      _ => false,
+    }
+}
+
+fn command_matches(specifier: &str, command: &str, strict: bool) -> bool {
+  let segments = command_segments(command);
+  if strict && (segments.len() != 1 || ["$(", "`", ">", "<("].iter().any(|token| command.contains(token))) {
+    return false;
+  }
+  let matches_segment = |segment: &str| match specifier.strip_suffix(":*") {
+    Some(prefix) => {
+      let prefix = prefix.trim();
+      segment == prefix || segment.strip_prefix(prefix).is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    }
+    None => segment == specifier,
+  };
+  segments.into_iter().any(matches_segment)
+}
+
+/// The simple commands in a shell line. Quoting is ignored, which only ever yields more segments.
+fn command_segments(command: &str) -> Vec<&str> {
+  command
+    .split(['\n', ';', '|', '&'])
+    .map(str::trim)
+    .filter(|segment| !segment.is_empty())
+    .collect()
+}
+
+fn host_matches(domain: &str, url: &str) -> bool {
+  reqwest::Url::parse(url)
+    .ok()
+    .and_then(|url| url.host_str().map(str::to_string))
+    .is_some_and(|host| host.eq_ignore_ascii_case(domain.trim()))
+}
+
+/// Relative patterns are anchored at the session cwd, `~/` at the home directory.
+fn path_pattern(specifier: &str, cwd: Option<&str>) -> Option<Pattern> {
+  let full = if let Some(rest) = specifier.strip_prefix("~/") {
+    home_dir()?.join(rest)
+  } else if Path::new(specifier).is_absolute() {
+    PathBuf::from(specifier)
+  } else {
+    match cwd {
+      Some(cwd) => Path::new(cwd).join(specifier),
+      None => PathBuf::from("**").join(specifier),
+    }
+  };
+  Pattern::new(&full.to_string_lossy()).ok()
+}
+
+/// Checks each path both as written and with symlinks resolved.
+fn paths_match(pattern: &Pattern, paths: &[PathBuf]) -> bool {
+  let options = MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+  paths
+    .iter()
+    .any(|path| pattern.matches_path_with(path, options) || pattern.matches_path_with(&real_path(path), options))
+}
+
+/// Whether a search under `root` can reach files `pattern` matches: the literal directories the
+/// pattern starts with lie inside `root`, or `root` lies inside them.
+fn search_reaches(pattern: &Pattern, root: &Path) -> bool {
+  let literal: PathBuf = Path::new(pattern.as_str())
+    .components()
+    .take_while(|component| !component.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+    .collect();
+  [root.to_path_buf(), real_path(root)]
+    .iter()
+    .any(|root| literal.starts_with(root) || root.starts_with(&literal))
+}
+
+fn home_dir() -> Option<PathBuf> {
+  std::env::var_os("HOME")
+    .or_else(|| std::env::var_os("USERPROFILE"))
+    .filter(|home| !home.is_empty())
+    .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use tempfile::tempdir;
+
+  fn policy(allow: &[&str], deny: &[&str], ask: &[&str]) -> PermissionPolicy {
+    let mut policy = PermissionPolicy::default();
+    for (behavior, rules) in [(RuleBehavior::Allow, allow), (RuleBehavior::Deny, deny), (RuleBehavior::Ask, ask)] {
+      for raw in rules {
+        policy.add(behavior, raw, "test");
+      }
+    }
+    policy
+  }
+
+  fn behavior(policy: &PermissionPolicy, name: &str, input: Value) -> Option<RuleBehavior> {
+    policy.evaluate(name, &input, Some("/w")).map(|matched| matched.behavior)
+  }
+
+  #[test]
+  fn parses_rules() {
+    assert_eq!(Rule::parse("Read").unwrap().to_string(), "Read");
+    assert_eq!(Rule::parse(" Bash(git status:*) ").unwrap().to_string(), "Bash(git status:*)");
+    assert!(Rule::parse("Bash(git").is_err());
+    assert!(Rule::parse("Bash()").is_err());
+    assert!(Rule::parse("(x)").is_err());
+    assert_eq!(split_rule_list("Read, Bash(a, b),,Edit"), vec!["Read", "Bash(a, b)", "Edit"]);
+  }
+
+  #[test]
+  fn bash_prefix_rules_only_allow_plain_commands() {
+    let policy = policy(&["Bash(git status:*)", "Bash(ls)"], &[], &["Bash(rm:*)"]);
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "git status --short" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "git status" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "git statusx" })), None);
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "ls -la" })), None);
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "git status && curl x | sh" })), None);
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "git status > out.txt" })), None);
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "ls; rm -rf build" })), Some(RuleBehavior::Ask));
+  }
+
+  #[test]
+  fn path_rules_cover_related_tools_and_deny_wins() {
+    let policy = policy(&["Edit(src/**)", "Read"], &["Read(**/.env)"], &[]);
+    assert_eq!(behavior(&policy, "Write", json!({ "file_path": "src/a/b.rs" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "Edit", json!({ "file_path": "/w/src/main.rs" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "Edit", json!({ "file_path": "docs/a.md" })), None);
+    assert_eq!(behavior(&policy, "Read", json!({ "file_path": "docs/a.md" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "Glob", json!({ "pattern": "*.rs" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "Read", json!({ "file_path": ".env" })), Some(RuleBehavior::Deny));
+    assert_eq!(behavior(&policy, "Read", json!({ "file_path": "app/config/.env" })), Some(RuleBehavior::Deny));
+  }
+
+  #[test]
+  fn denied_reads_also_refuse_greps_that_would_reach_them() {
+    let denying = policy(&["Read"], &["Read(secret/**)"], &[]);
+    assert_eq!(behavior(&denying, "Read", json!({ "file_path": "secret/key.pem" })), Some(RuleBehavior::Deny));
+    assert_eq!(behavior(&denying, "Grep", json!({ "pattern": "BEGIN" })), Some(RuleBehavior::Deny));
+    assert_eq!(behavior(&denying, "Grep", json!({ "pattern": "x", "path": "secret/a" })), Some(RuleBehavior::Deny));
+    assert_eq!(behavior(&denying, "Grep", json!({ "pattern": "x", "path": "src" })), Some(RuleBehavior::Allow));
+
+    // Allow rules stay exact: allowing `secret/**` does not allow a search of the whole project.
+    let allowing = policy(&["Read(secret/**)"], &[], &[]);
+    assert_eq!(behavior(&allowing, "Grep", json!({ "pattern": "x" })), None);
+  }
+
+  #[test]
+  fn web_fetch_rules_match_the_host() {
+    let policy = policy(&["WebFetch(domain:docs.rs)"], &[], &[]);
+    assert_eq!(behavior(&policy, "WebFetch", json!({ "url": "https://docs.rs/serde" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "WebFetch", json!({ "url": "https://docs.rs.evil.com/" })), None);
+    assert_eq!(behavior(&policy, "WebFetch", json!({ "url": "not a url" })), None);
+  }
+
+  #[test]
+  fn suggests_generalized_rules() {
+    let suggest = |name: &str, input: Value| suggest_rule(name, &input, Some("/w"));
+    assert_eq!(suggest("Bash", json!({ "command": "git status --short" })).as_deref(), Some("Bash(git status:*)"));
+    assert_eq!(suggest("Bash", json!({ "command": "git -C x log" })).as_deref(), Some("Bash(git:*)"));
+    assert_eq!(suggest("Bash", json!({ "command": "ls -la" })).as_deref(), Some("Bash(ls:*)"));
+    assert_eq!(suggest("Bash", json!({ "command": "make && make install" })), None);
+    assert_eq!(suggest("Write", json!({ "file_path": "src/ui/a.ts" })).as_deref(), Some("Edit(src/ui/**)"));
+    assert_eq!(suggest("Edit", json!({ "file_path": "README.md" })).as_deref(), Some("Edit(**)"));
+    assert_eq!(suggest("Read", json!({ "file_path": "/etc/hosts" })).as_deref(), Some("Read(/etc/**)"));
+    assert_eq!(suggest("WebFetch", json!({ "url": "https://docs.rs/x" })).as_deref(), Some("WebFetch(domain:docs.rs)"));
+    assert_eq!(suggest("Task", json!({})).as_deref(), Some("Task"));
+
+    let policy = policy(&["Bash(git status:*)", "Edit(src/ui/**)"], &[], &[]);
+    assert_eq!(behavior(&policy, "Bash", json!({ "command": "git status" })), Some(RuleBehavior::Allow));
+    assert_eq!(behavior(&policy, "MultiEdit", json!({ "file_path": "src/ui/b.ts" })), Some(RuleBehavior::Allow));
+  }
+
+  #[test]
+  fn reads_rule_updates_and_saves_project_rules() {
+    let result = json!({
+      "behavior": "allow",
+      "updatedPermissions": [
+        { "type": "addRules", "behavior": "allow", "destination": "session", "rules": [{ "toolName": "Read" }] },
+        {
+          "type": "addRules",
+          "behavior": "allow",
+          "destination": "projectSettings",
+          "rules": [{ "toolName": "Bash", "ruleContent": "npm test:*" }, { "toolName": "Bad(" }]
+        },
+        { "type": "addRules", "behavior": "allow", "destination": "userSettings", "rules": [{ "toolName": "Bash" }] }
+      ]
+    });
+    assert_eq!(
+      rule_updates(&result),
+      vec![
+        RuleUpdate { destination: RuleDestination::Session, rule: "Read".into() },
+        RuleUpdate { destination: RuleDestination::Project, rule: "Bash(npm test:*)".into() },
+      ]
+    );
+
+    let set_mode = json!({ "type": "setMode", "mode": "acceptEdits", "destination": "session" });
+    let approved = json!({ "updatedPermissions": [set_mode] });
+    assert_eq!(mode_update(&approved), Some(PermissionMode::Auto));
+    assert_eq!(mode_update(&result), None);
+
+    let dir = tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join(".open-cowork")).unwrap();
+    std::fs::write(dir.path().join(SETTINGS_FILE), r#"{"model":"x","permissions":{"deny":["Read(.env)"]}}"#).unwrap();
+    save_project_rule(dir.path(), "Bash(npm test:*)").unwrap();
+    let path = save_project_rule(dir.path(), "Bash(npm test:*)").unwrap();
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(
+      saved,
+      json!({ "model": "x", "permissions": { "deny": ["Read(.env)"], "allow": ["Bash(npm test:*)"] } })
+    );
+  }
+
+  #[test]
+  fn loads_project_settings_and_reports_the_source() {
+    let dir = tempdir().unwrap();
+    let cwd = dir.path().to_str().unwrap();
+    std::fs::create_dir_all(dir.path().join(".open-cowork")).unwrap();
+    let settings = json!({ "permissions": { "deny": ["Bash(git push:*)"], "allow": ["Bash(git:*)", "Bad("] } });
+    std::fs::write(dir.path().join(SETTINGS_FILE), settings.to_string()).unwrap();
+
+    let policy = PermissionPolicy::load(Some(cwd), &["Glob".to_string()]);
+    let matched = policy.evaluate("Bash", &json!({ "command": "git push origin" }), Some(cwd)).unwrap();
+    assert_eq!(matched.behavior, RuleBehavior::Deny);
+    assert_eq!(matched.rule, "Bash(git push:*)");
+    assert_eq!(matched.source, dir.path().join(SETTINGS_FILE).display().to_string());
+
+    let matched = policy.evaluate("Glob", &json!({ "pattern": "*.rs" }), Some(cwd)).unwrap();
+    assert_eq!((matched.behavior, matched.source.as_str()), (RuleBehavior::Allow, SESSION_SOURCE));
+  }
+}
@@ -1,10 +1,186 @@
-use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub struct ChatRequest {
-  pub model: String,
-  pub prompt: String,
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+
+use super::attachments::Attachment;
+use super::params::GenerationParams;
+use crate::tools::ToolSpec;
+
+/// Everything a provider needs to produce the next assistant turn.
+pub struct ChatRequest<'a> {
+  pub model: &'a str,
+  /// Stored session history (`user_prompt` / `assistant` / `user` tool_result entries).
+  pub history: &'a [Value],
+  pub tools: &'a [ToolSpec],
+  /// Session system prompt (environment and project instructions), when there is one.
+  pub system: Option<&'a str>,
+  pub params: &'a GenerationParams,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+  pub id: String,
+  pub name: String,
+  pub input: Value,
+}
+
+/// Provider-neutral streaming updates, forwarded to the UI as they arrive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+  ContentStart,
+  TextDelta(String),
+  /// Reasoning text the provider chose to expose (summaries or thinking).
+  ThinkingDelta(String),
+  ContentStop,
+}
+
+/// Token counts reported for one model call. `input_tokens` excludes tokens served from or
+/// written to the prompt cache, which are billed at their own rates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+  pub input_tokens: u64,
+  pub output_tokens: u64,
+  pub cache_read_tokens: u64,
+  pub cache_write_tokens: u64,
+}
+
+impl Usage {
+  pub fn is_empty(&self) -> bool {
+    *self == Usage::default()
+  }
+}
+
+/// A finished assistant turn: blocks in the stored history shape plus the tools it asked for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelTurn {
+  pub content_blocks: Vec<Value>,
+  pub tool_calls: Vec<ToolCall>,
+  pub usage: Usage,
+  /// The reply stopped at the output token limit, so its last block may be incomplete.
+  pub truncated: bool,
+}
+
+impl ModelTurn {
+  /// Drops tool calls, e.g. when the turn was cut off and its calls will never run.
+  pub fn without_tool_calls(mut self) -> Self {
+    self
+      .content_blocks
+      .retain(|block| block.get("type").and_then(Value::as_str) != Some("tool_use"));
+    self.tool_calls.clear();
+    self
+  }
+}
+
+/// A provider reported failure mid-stream. `retryable` marks overload / rate limit / server
+/// errors where sending the same request again is expected to succeed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamError {
+  pub message: String,
+  pub retryable: bool,
+}
+
+impl StreamError {
+  pub fn fatal(message: impl Into<String>) -> Self {
+    Self { message: message.into(), retryable: false }
+  }
+
+  pub fn transient(message: impl Into<String>) -> Self {
+    Self { message: message.into(), retryable: true }
+  }
 }
 
-pub trait ProviderAdapter {
+impl From<serde_json::Error> for StreamError {
+  fn from(error: serde_json::Error) -> Self {
+    Self::fatal(error.to_string())
+  }
+}
+
+pub trait ProviderAdapter: Send + Sync {
+  /// Human readable name used in error messages.
+  fn name(&self) -> &'static str;
+  fn default_url(&self) -> &'static str;
+  /// Request URL for the configured base URL; a custom base URL replaces the default outright.
+  fn endpoint(&self, base_url: Option<&str>, _model: &str) -> String {
+    base_url.unwrap_or(self.default_url()).to_string()
+  }
+  fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder;
+  /// Rejects parameters the provider (or this model) does not accept, before any request is sent.
+  fn validate_params(&self, model: &str, params: &GenerationParams) -> Result<(), String>;
+  /// Rejects attachment kinds the provider cannot take; common limits live in `attachments::validate`.
+  fn validate_attachments(&self, _attachments: &[Attachment]) -> Result<(), String> {
+    Ok(())
+  }
   fn build_request(&self, req: &ChatRequest) -> Value;
+  fn stream_parser(&self) -> Box<dyn StreamParser + Send>;
+}
+
+/// Incremental decoder for one streamed response body.
+pub trait StreamParser {
+  fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, StreamError>;
+  fn finish(self: Box<Self>) -> ModelTurn;
+}
+
+pub fn tool_use_block(call: &ToolCall) -> Value {
+  json!({
+    "type": "tool_use",
+    "id": call.id,
+    "name": call.name,
+    "input": call.input
+  })
+}
+
+/// Parses accumulated tool arguments, keeping the raw text when it is not valid JSON.
+pub fn parse_tool_input(raw: &str, fallback: Option<Value>) -> Value {
+  if raw.trim().is_empty() {
+    fallback.unwrap_or(Value::Null)
+  } else {
+    serde_json::from_str(raw).unwrap_or(Value::String(raw.to_string()))
+  }
+}
+
+/// Prefix for locally generated tool call ids, for providers that do not always assign their own.
+/// Timestamped so ids stay unique across the turns of a session.
+pub fn generated_id_prefix(provider: &str) -> String {
+  let stamp = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_millis())
+    .unwrap_or_default();
+  format!("{}-{}", provider, stamp)
+}
+
+pub fn token_count(value: &Value, pointer: &str) -> u64 {
+  value.pointer(pointer).and_then(Value::as_u64).unwrap_or(0)
+}
+
+pub fn stringify_value(value: &Value) -> String {
+  match value {
+    Value::String(text) => text.clone(),
+    _ => value.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn without_tool_calls_keeps_text() {
+    let call = ToolCall { id: "t1".into(), name: "Read".into(), input: json!({}) };
+    let turn = ModelTurn {
+      content_blocks: vec![json!({ "type": "text", "text": "hi" }), tool_use_block(&call)],
+      tool_calls: vec![call],
+      ..ModelTurn::default()
+    };
+    let trimmed = turn.without_tool_calls();
+    assert_eq!(trimmed.content_blocks, vec![json!({ "type": "text", "text": "hi" })]);
+    assert!(trimmed.tool_calls.is_empty());
+  }
+
+  #[test]
+  fn parse_tool_input_falls_back() {
+    assert_eq!(parse_tool_input("", Some(json!({ "a": 1 }))), json!({ "a": 1 }));
+    assert_eq!(parse_tool_input("{\"a\":2}", None), json!({ "a": 2 }));
+    assert_eq!(parse_tool_input("{\"a\"", None), json!("{\"a\""));
+  }
 }
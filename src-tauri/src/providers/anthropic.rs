@@ -1,16 +1,414 @@
-use serde_json::Value;
+use std::collections::BTreeMap;
 
-use super::adapter::{ChatRequest, ProviderAdapter};
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+
+use super::adapter::{
+  parse_tool_input, ChatRequest, ModelTurn, ProviderAdapter, StreamEvent, StreamError, StreamParser, ToolCall,
+  Usage,
+};
+use super::attachments::{self, AttachmentKind};
+use super::params::{GenerationParams, ParamKeys};
+use super::sse::{SseDecoder, SseEvent};
+use crate::tools::ToolSpec;
+
+const API_VERSION: &str = "2023-06-01";
+const PARAM_KEYS: ParamKeys = ParamKeys {
+  max_tokens: None,
+  temperature: "temperature",
+  top_p: "top_p",
+  stop: Some("stop_sequences"),
+};
+
+/// Smallest `budget_tokens` the API accepts for extended thinking.
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// The Messages API requires `max_tokens`; Claude 3 models cap their output at 4096.
+pub fn default_max_tokens(model: &str) -> u32 {
+  let legacy = ["claude-3-opus", "claude-3-sonnet", "claude-3-haiku"];
+  if legacy.iter().any(|prefix| model.starts_with(prefix)) {
+    4096
+  } else {
+    8192
+  }
+}
 
 pub struct AnthropicAdapter;
 
 impl ProviderAdapter for AnthropicAdapter {
+  fn name(&self) -> &'static str {
+    "Anthropic"
+  }
+
+  fn default_url(&self) -> &'static str {
+    "https://api.anthropic.com/v1/messages"
+  }
+
+  fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+    request
+      .header("x-api-key", api_key)
+      .header("anthropic-version", API_VERSION)
+  }
+
+  fn validate_params(&self, _model: &str, params: &GenerationParams) -> Result<(), String> {
+    if params.reasoning_effort.is_some() {
+      return Err("Anthropic 不支持 reasoning effort 参数。".into());
+    }
+    if let Some(budget) = params.thinking_budget {
+      if budget < MIN_THINKING_BUDGET {
+        return Err(format!("thinking budget 不能小于 {}。", MIN_THINKING_BUDGET));
+      }
+      if params.max_tokens.is_some_and(|max_tokens| budget >= max_tokens) {
+        return Err("thinking budget 必须小于 max_tokens。".into());
+      }
+      if params.temperature.is_some() {
+        return Err("启用 extended thinking 时不能设置 temperature。".into());
+      }
+      if params.top_p.is_some_and(|top_p| top_p < 0.95) {
+        return Err("启用 extended thinking 时 top_p 必须在 0.95 到 1 之间。".into());
+      }
+    }
+    params.check(1.0, usize::MAX)
+  }
+
   fn build_request(&self, req: &ChatRequest) -> Value {
-    serde_json::json!({
+    let mut messages = build_messages(req.history);
+    mark_cache_breakpoint(&mut messages);
+    let mut tools = tool_definitions(req.tools);
+    if let Some(last) = tools.last_mut() {
+      last["cache_control"] = cache_control();
+    }
+    let mut body = json!({
       "model": req.model,
-      "messages": [{ "role": "user", "content": req.prompt }],
-      "max_tokens": 1024
+      "messages": messages,
+      "stream": true,
+      "max_tokens": max_tokens(req.model, req.params),
+      "tools": tools
+    });
+    if let Some(system) = req.system {
+      body["system"] = json!([{ "type": "text", "text": system, "cache_control": cache_control() }]);
+    }
+    if let Some(budget) = req.params.thinking_budget {
+      body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+    }
+    if let Some(body) = body.as_object_mut() {
+      req.params.write_into(body, PARAM_KEYS);
+    }
+    body
+  }
+
+  fn stream_parser(&self) -> Box<dyn StreamParser + Send> {
+    Box::new(AnthropicStreamParser::default())
+  }
+}
+
+fn cache_control() -> Value {
+  json!({ "type": "ephemeral" })
+}
+
+/// Caches the conversation up to its last block. The next request in the agent loop extends this
+/// prefix, and the API looks back from the new breakpoint to find it, so earlier turns are read
+/// from the cache instead of being billed again. Together with the tools and system prompt this
+/// stays within the four breakpoints a request may carry.
+fn mark_cache_breakpoint(messages: &mut [Value]) {
+  let Some(block) = messages
+    .last_mut()
+    .and_then(|message| message.get_mut("content"))
+    .and_then(Value::as_array_mut)
+    .and_then(|content| content.last_mut())
+  else {
+    return;
+  };
+  // Thinking blocks cannot carry cache_control; they are cached as part of the prefix anyway.
+  if matches!(block.get("type").and_then(Value::as_str), Some("thinking" | "redacted_thinking")) {
+    return;
+  }
+  block["cache_control"] = cache_control();
+}
+
+/// The thinking budget counts towards `max_tokens`, so the default leaves the usual room on top of it.
+fn max_tokens(model: &str, params: &GenerationParams) -> u32 {
+  params
+    .max_tokens
+    .unwrap_or_else(|| default_max_tokens(model) + params.thinking_budget.unwrap_or(0))
+}
+
+pub fn tool_definitions(tools: &[ToolSpec]) -> Vec<Value> {
+  tools
+    .iter()
+    .map(|tool| {
+      json!({
+        "name": tool.name,
+        "description": tool.description,
+        "input_schema": tool.parameters
+      })
     })
+    .collect()
+}
+
+/// Replays stored history as Messages API turns, merging consecutive tool results into one user turn.
+pub fn build_messages(history: &[Value]) -> Vec<Value> {
+  let mut messages = Vec::new();
+  let mut pending_tool_results: Vec<Value> = Vec::new();
+
+  let flush_tool_results = |pending: &mut Vec<Value>, output: &mut Vec<Value>| {
+    if pending.is_empty() {
+      return;
+    }
+    output.push(json!({ "role": "user", "content": pending.clone() }));
+    pending.clear();
+  };
+
+  for item in history {
+    let msg_type = item.get("type").and_then(Value::as_str);
+    match msg_type {
+      Some("user_prompt") => {
+        flush_tool_results(&mut pending_tool_results, &mut messages);
+        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
+          // Files go first: the API documents better results with images placed before the question.
+          let mut content: Vec<Value> = attachments::from_entry(item).iter().filter_map(attachment_block).collect();
+          content.push(json!({ "type": "text", "text": prompt }));
+          messages.push(json!({ "role": "user", "content": content }));
+        }
+      }
+      Some("assistant") => {
+        flush_tool_results(&mut pending_tool_results, &mut messages);
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          let contents: Vec<&Value> = contents.iter().filter(|block| is_replayable(block)).collect();
+          if !contents.is_empty() {
+            messages.push(json!({ "role": "assistant", "content": contents }));
+          }
+        }
+      }
+      Some("user") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          let only_tool_results = contents
+            .iter()
+            .all(|content| content.get("type").and_then(Value::as_str) == Some("tool_result"));
+          let contents: Vec<Value> = contents.iter().map(tool_result_block).collect();
+          if only_tool_results {
+            pending_tool_results.extend(contents);
+          } else {
+            flush_tool_results(&mut pending_tool_results, &mut messages);
+            messages.push(json!({ "role": "user", "content": contents }));
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  flush_tool_results(&mut pending_tool_results, &mut messages);
+
+  messages
+}
+
+/// Files returned by a tool (e.g. an image from Read) become image/document blocks inside the
+/// `tool_result`, after its text. The display-only `diff` is dropped.
+fn tool_result_block(block: &Value) -> Value {
+  let files = attachments::from_entry(block);
+  let mut block = block.clone();
+  if let Some(object) = block.as_object_mut() {
+    object.remove("attachments");
+    object.remove("diff");
+  }
+  if !files.is_empty() {
+    let text = block.get("content").and_then(Value::as_str).unwrap_or_default().to_string();
+    let mut content = vec![json!({ "type": "text", "text": text })];
+    content.extend(files.iter().filter_map(attachment_block));
+    block["content"] = Value::Array(content);
+  }
+  block
+}
+
+fn attachment_block(attachment: &attachments::Attachment) -> Option<Value> {
+  let base64_source = || json!({ "type": "base64", "media_type": attachment.media_type, "data": attachment.data });
+  match attachment.kind()? {
+    AttachmentKind::Image => Some(json!({ "type": "image", "source": base64_source() })),
+    AttachmentKind::Pdf => Some(json!({ "type": "document", "source": base64_source(), "title": attachment.name })),
+    AttachmentKind::Text => Some(json!({
+      "type": "document",
+      "source": { "type": "text", "media_type": "text/plain", "data": attachment.text()? },
+      "title": attachment.name
+    })),
+  }
+}
+
+/// Thinking blocks go back to the API verbatim, signature included; one without a signature (cut
+/// off by a stop, or from another provider) would be rejected.
+fn is_replayable(block: &Value) -> bool {
+  match block.get("type").and_then(Value::as_str) {
+    Some("thinking") => block
+      .get("signature")
+      .and_then(Value::as_str)
+      .is_some_and(|signature| !signature.is_empty()),
+    _ => true,
+  }
+}
+
+enum AnthropicBlock {
+  Text(String),
+  Thinking { thinking: String, signature: String },
+  RedactedThinking(String),
+  ToolUse {
+    id: String,
+    name: String,
+    input_json: String,
+    input: Option<Value>,
+  },
+}
+
+#[derive(Default)]
+pub struct AnthropicStreamParser {
+  decoder: SseDecoder,
+  blocks: BTreeMap<u64, AnthropicBlock>,
+  usage: Usage,
+  truncated: bool,
+}
+
+impl AnthropicStreamParser {
+  fn read_usage(&mut self, usage: &Value) {
+    let read = |key: &str, slot: &mut u64| {
+      if let Some(count) = usage.get(key).and_then(Value::as_u64) {
+        *slot = count;
+      }
+    };
+    read("input_tokens", &mut self.usage.input_tokens);
+    read("output_tokens", &mut self.usage.output_tokens);
+    read("cache_read_input_tokens", &mut self.usage.cache_read_tokens);
+    read("cache_creation_input_tokens", &mut self.usage.cache_write_tokens);
+  }
+
+  fn handle(&mut self, event: &SseEvent, output: &mut Vec<StreamEvent>) -> Result<(), StreamError> {
+    let payload: Value = serde_json::from_str(&event.data)?;
+    let index = payload.get("index").and_then(Value::as_u64).unwrap_or(0);
+    match event.event.as_deref() {
+      Some("message_start") => {
+        if let Some(usage) = payload.pointer("/message/usage") {
+          self.read_usage(usage);
+        }
+      }
+      // Counts here are cumulative for the message, so they replace what message_start reported.
+      Some("message_delta") => {
+        if let Some(usage) = payload.get("usage") {
+          self.read_usage(usage);
+        }
+        if payload.pointer("/delta/stop_reason").and_then(Value::as_str) == Some("max_tokens") {
+          self.truncated = true;
+        }
+      }
+      Some("content_block_start") => {
+        if let Some(block) = payload.get("content_block") {
+          match block.get("type").and_then(Value::as_str) {
+            Some("text") => {
+              self.blocks.insert(index, AnthropicBlock::Text(String::new()));
+              output.push(StreamEvent::ContentStart);
+            }
+            Some("thinking") => {
+              let thinking = String::new();
+              self.blocks.insert(index, AnthropicBlock::Thinking { thinking, signature: String::new() });
+              output.push(StreamEvent::ContentStart);
+            }
+            Some("redacted_thinking") => {
+              let data = block.get("data").and_then(Value::as_str).unwrap_or_default().to_string();
+              self.blocks.insert(index, AnthropicBlock::RedactedThinking(data));
+            }
+            Some("tool_use") => {
+              let id = block.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+              let name = block.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+              let input = block.get("input").cloned();
+              self.blocks.insert(index, AnthropicBlock::ToolUse { id, name, input_json: String::new(), input });
+            }
+            _ => {}
+          }
+        }
+      }
+      Some("content_block_delta") => {
+        if let Some(delta) = payload.get("delta") {
+          match delta.get("type").and_then(Value::as_str) {
+            Some("text_delta") => {
+              let text = delta.get("text").and_then(Value::as_str).unwrap_or("");
+              if let Some(AnthropicBlock::Text(current)) = self.blocks.get_mut(&index) {
+                current.push_str(text);
+              }
+              output.push(StreamEvent::TextDelta(text.to_string()));
+            }
+            Some("thinking_delta") => {
+              let text = delta.get("thinking").and_then(Value::as_str).unwrap_or("");
+              if let Some(AnthropicBlock::Thinking { thinking, .. }) = self.blocks.get_mut(&index) {
+                thinking.push_str(text);
+              }
+              output.push(StreamEvent::ThinkingDelta(text.to_string()));
+            }
+            Some("signature_delta") => {
+              let part = delta.get("signature").and_then(Value::as_str).unwrap_or("");
+              if let Some(AnthropicBlock::Thinking { signature, .. }) = self.blocks.get_mut(&index) {
+                signature.push_str(part);
+              }
+            }
+            Some("input_json_delta") => {
+              let part = delta.get("partial_json").and_then(Value::as_str).unwrap_or("");
+              if let Some(AnthropicBlock::ToolUse { input_json, .. }) = self.blocks.get_mut(&index) {
+                input_json.push_str(part);
+              }
+            }
+            _ => {}
+          }
+        }
+      }
+      Some("content_block_stop") => output.push(StreamEvent::ContentStop),
+      Some("error") => {
+        let message = payload
+          .pointer("/error/message")
+          .and_then(Value::as_str)
+          .unwrap_or("unknown stream error");
+        let message = format!("Anthropic stream error: {}", message);
+        let kind = payload.pointer("/error/type").and_then(Value::as_str).unwrap_or_default();
+        return Err(match kind {
+          "overloaded_error" | "api_error" | "rate_limit_error" => StreamError::transient(message),
+          _ => StreamError::fatal(message),
+        });
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+}
+
+impl StreamParser for AnthropicStreamParser {
+  fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, StreamError> {
+    let mut output = Vec::new();
+    for event in self.decoder.push(chunk) {
+      self.handle(&event, &mut output)?;
+    }
+    Ok(output)
+  }
+
+  fn finish(self: Box<Self>) -> ModelTurn {
+    let mut turn = ModelTurn { usage: self.usage, truncated: self.truncated, ..ModelTurn::default() };
+    for block in self.blocks.into_values() {
+      match block {
+        AnthropicBlock::Text(text) => {
+          if !text.is_empty() {
+            turn.content_blocks.push(json!({ "type": "text", "text": text }));
+          }
+        }
+        AnthropicBlock::Thinking { thinking, signature } => {
+          turn.content_blocks.push(json!({ "type": "thinking", "thinking": thinking, "signature": signature }));
+        }
+        AnthropicBlock::RedactedThinking(data) => {
+          turn.content_blocks.push(json!({ "type": "redacted_thinking", "data": data }));
+        }
+        AnthropicBlock::ToolUse { id, name, input_json, input } => {
+          let parsed = parse_tool_input(&input_json, input);
+          turn
+            .content_blocks
+            .push(json!({ "type": "tool_use", "id": id, "name": name, "input": parsed }));
+          turn.tool_calls.push(ToolCall { id, name, input: parsed });
+        }
+      }
+    }
+    turn
   }
 }
 
@@ -18,13 +416,283 @@ impl ProviderAdapter for AnthropicAdapter {
 mod tests {
   use super::*;
 
+  fn parse(stream: &str) -> (Vec<StreamEvent>, ModelTurn) {
+    let mut parser = AnthropicAdapter.stream_parser();
+    let events = parser.push(stream.as_bytes()).unwrap();
+    (events, parser.finish())
+  }
+
   #[test]
   fn build_request_contains_model() {
-    let adapter = AnthropicAdapter;
-    let value = adapter.build_request(&ChatRequest {
-      model: "claude-test".into(),
-      prompt: "hi".into(),
+    let history = vec![json!({ "type": "user_prompt", "prompt": "hi" })];
+    let value = AnthropicAdapter.build_request(&ChatRequest {
+      model: "claude-test",
+      history: &history,
+      tools: &[],
+      system: Some("Be brief."),
+      params: &GenerationParams::default(),
     });
     assert_eq!(value["model"], "claude-test");
+    assert_eq!(value["system"][0]["text"], "Be brief.");
+    assert_eq!(value["messages"][0]["content"][0]["text"], "hi");
+    assert_eq!(value["max_tokens"], 8192);
+    assert!(value.get("temperature").is_none());
+  }
+
+  #[test]
+  fn build_request_applies_generation_params() {
+    let params = GenerationParams {
+      max_tokens: Some(32000),
+      temperature: Some(0.2),
+      stop: vec!["</done>".into()],
+      ..GenerationParams::default()
+    };
+    let value = AnthropicAdapter.build_request(&ChatRequest {
+      model: "claude-3-haiku-20240307",
+      history: &[],
+      tools: &[],
+      system: None,
+      params: &params,
+    });
+    assert_eq!(value["max_tokens"], 32000);
+    assert_eq!(value["temperature"], 0.2);
+    assert_eq!(value["stop_sequences"], json!(["</done>"]));
+    assert_eq!(default_max_tokens("claude-3-haiku-20240307"), 4096);
+
+    let hot = GenerationParams { temperature: Some(1.5), ..GenerationParams::default() };
+    assert!(AnthropicAdapter.validate_params("claude-test", &hot).is_err());
+  }
+
+  #[test]
+  fn parser_flags_max_tokens_stop() {
+    let stream = concat!(
+      "event: content_block_start\ndata: {\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"id\":\"tu_1\",\"name\":\"Write\",\"input\":{}}}\n\n",
+      "event: content_block_delta\ndata: {\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"file_path\\\":\"}}\n\n",
+      "event: content_block_stop\ndata: {\"index\":0}\n\n",
+      "event: message_delta\ndata: {\"delta\":{\"stop_reason\":\"max_tokens\"},\"usage\":{\"output_tokens\":8192}}\n\n",
+    );
+    let (_, turn) = parse(stream);
+    assert!(turn.truncated);
+    assert_eq!(turn.tool_calls.len(), 1);
+  }
+
+  #[test]
+  fn build_request_marks_cache_breakpoints() {
+    let history = vec![
+      json!({ "type": "user_prompt", "prompt": "hi" }),
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "tool_use", "id": "tool-1", "name": "Read", "input": { "file_path": "a.txt" } }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": "tool-1", "content": "ok", "is_error": false }
+      ] } }),
+    ];
+    let tools = vec![
+      ToolSpec { name: "Read", description: "Read a file.", parameters: json!({}) },
+      ToolSpec { name: "Write", description: "Write a file.", parameters: json!({}) },
+    ];
+    let value = AnthropicAdapter.build_request(&ChatRequest {
+      model: "claude-test",
+      history: &history,
+      tools: &tools,
+      system: Some("Be brief."),
+      params: &GenerationParams::default(),
+    });
+    let ephemeral = json!({ "type": "ephemeral" });
+    assert!(value["tools"][0].get("cache_control").is_none());
+    assert_eq!(value["tools"][1]["cache_control"], ephemeral);
+    assert_eq!(value["system"][0]["cache_control"], ephemeral);
+    assert!(value["messages"][0]["content"][0].get("cache_control").is_none());
+    assert_eq!(value["messages"][2]["content"][0]["cache_control"], ephemeral);
+  }
+
+  #[test]
+  fn build_request_enables_thinking() {
+    let params = GenerationParams { thinking_budget: Some(4000), ..GenerationParams::default() };
+    let value = AnthropicAdapter.build_request(&ChatRequest {
+      model: "claude-sonnet-4-5",
+      history: &[],
+      tools: &[],
+      system: None,
+      params: &params,
+    });
+    assert_eq!(value["thinking"], json!({ "type": "enabled", "budget_tokens": 4000 }));
+    assert_eq!(value["max_tokens"], 8192 + 4000);
+
+    assert!(AnthropicAdapter.validate_params("claude-test", &params).is_ok());
+    let small = GenerationParams { thinking_budget: Some(512), ..GenerationParams::default() };
+    assert!(AnthropicAdapter.validate_params("claude-test", &small).is_err());
+    let over = GenerationParams { max_tokens: Some(2048), ..params.clone() };
+    assert!(AnthropicAdapter.validate_params("claude-test", &over).is_err());
+    let hot = GenerationParams { temperature: Some(0.5), ..params };
+    assert!(AnthropicAdapter.validate_params("claude-test", &hot).is_err());
+  }
+
+  #[test]
+  fn parser_keeps_thinking_with_signature() {
+    let stream = concat!(
+      "event: content_block_start\ndata: {\"index\":0,\"content_block\":{\"type\":\"thinking\",\"thinking\":\"\"}}\n\n",
+      "event: content_block_delta\ndata: {\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"Check the file.\"}}\n\n",
+      "event: content_block_delta\ndata: {\"index\":0,\"delta\":{\"type\":\"signature_delta\",\"signature\":\"sig-1\"}}\n\n",
+      "event: content_block_stop\ndata: {\"index\":0}\n\n",
+      "event: content_block_start\ndata: {\"index\":1,\"content_block\":{\"type\":\"redacted_thinking\",\"data\":\"opaque\"}}\n\n",
+      "event: content_block_stop\ndata: {\"index\":1}\n\n",
+    );
+    let (events, turn) = parse(stream);
+    assert_eq!(events[..2], [StreamEvent::ContentStart, StreamEvent::ThinkingDelta("Check the file.".into())]);
+    assert_eq!(
+      turn.content_blocks,
+      vec![
+        json!({ "type": "thinking", "thinking": "Check the file.", "signature": "sig-1" }),
+        json!({ "type": "redacted_thinking", "data": "opaque" }),
+      ]
+    );
+  }
+
+  #[test]
+  fn build_messages_sends_attachments_before_the_prompt() {
+    let history = vec![json!({
+      "type": "user_prompt",
+      "prompt": "What is in these?",
+      "attachments": [
+        { "name": "shot.png", "mediaType": "image/png", "data": "iVBORw0KGgo=" },
+        { "name": "spec.pdf", "mediaType": "application/pdf", "data": "JVBERi0=" },
+        { "name": "notes.txt", "mediaType": "text/plain", "data": "aGk=" }
+      ]
+    })];
+    let content = &build_messages(&history)[0]["content"];
+    assert_eq!(
+      content[0],
+      json!({ "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo=" } })
+    );
+    assert_eq!(content[1]["source"]["media_type"], "application/pdf");
+    assert_eq!(content[1]["title"], "spec.pdf");
+    assert_eq!(content[2]["source"], json!({ "type": "text", "media_type": "text/plain", "data": "hi" }));
+    assert_eq!(content[3], json!({ "type": "text", "text": "What is in these?" }));
+  }
+
+  #[test]
+  fn build_messages_drops_unsigned_thinking() {
+    let history = vec![
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "thinking", "thinking": "kept", "signature": "sig" },
+        { "type": "thinking", "thinking": "cut off", "signature": "" },
+        { "type": "text", "text": "Done." }
+      ] } }),
+      json!({ "type": "assistant", "message": { "content": [{ "type": "thinking", "thinking": "summary only" }] } }),
+    ];
+    let messages = build_messages(&history);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+      messages[0]["content"],
+      json!([{ "type": "thinking", "thinking": "kept", "signature": "sig" }, { "type": "text", "text": "Done." }])
+    );
+  }
+
+  #[test]
+  fn build_messages_merges_tool_results() {
+    let history = vec![
+      json!({ "type": "user_prompt", "prompt": "hi" }),
+      json!({
+        "type": "assistant",
+        "message": {
+          "content": [
+            { "type": "tool_use", "id": "tool-1", "name": "Read", "input": { "file_path": "a.txt" } },
+            { "type": "tool_use", "id": "tool-2", "name": "Read", "input": { "file_path": "b.txt" } }
+          ]
+        }
+      }),
+      json!({
+        "type": "user",
+        "message": {
+          "content": [
+            { "type": "tool_result", "tool_use_id": "tool-1", "content": "ok", "is_error": false }
+          ]
+        }
+      }),
+      json!({
+        "type": "user",
+        "message": {
+          "content": [
+            { "type": "tool_result", "tool_use_id": "tool-2", "content": "ok2", "is_error": false }
+          ]
+        }
+      }),
+      json!({ "type": "marker", "kind": "interrupted", "text": "Interrupted by user." }),
+    ];
+
+    let messages = build_messages(&history);
+    assert_eq!(messages.len(), 3);
+    let last = messages.last().expect("expected tool result message");
+    assert_eq!(last.get("role").and_then(Value::as_str), Some("user"));
+    let contents = last.get("content").and_then(Value::as_array).expect("content array");
+    assert_eq!(contents.len(), 2);
+    assert_eq!(contents[0].get("tool_use_id").and_then(Value::as_str), Some("tool-1"));
+    assert_eq!(contents[1].get("tool_use_id").and_then(Value::as_str), Some("tool-2"));
+  }
+
+  #[test]
+  fn build_messages_puts_tool_files_inside_the_result() {
+    let history = vec![json!({
+      "type": "user",
+      "message": { "content": [{
+        "type": "tool_result",
+        "tool_use_id": "tool-1",
+        "content": "Read a.png",
+        "is_error": false,
+        "attachments": [{ "name": "a.png", "mediaType": "image/png", "data": "iVBORw0KGgo=" }],
+        "diff": "--- a/a.png\n"
+      }] }
+    })];
+    let result = &build_messages(&history)[0]["content"][0];
+    assert!(result.get("attachments").is_none());
+    assert!(result.get("diff").is_none());
+    assert_eq!(result["content"][0], json!({ "type": "text", "text": "Read a.png" }));
+    assert_eq!(result["content"][1]["type"], "image");
+    assert_eq!(result["content"][1]["source"]["media_type"], "image/png");
+  }
+
+  #[test]
+  fn parser_collects_text_and_tool_use() {
+    let stream = concat!(
+      "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":120,\"cache_read_input_tokens\":900,\"cache_creation_input_tokens\":0,\"output_tokens\":1}}}\n\n",
+      "event: content_block_start\ndata: {\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+      "event: content_block_delta\ndata: {\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Let me look.\"}}\n\n",
+      "event: content_block_stop\ndata: {\"index\":0}\n\n",
+      "event: content_block_start\ndata: {\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"tu_1\",\"name\":\"Read\",\"input\":{}}}\n\n",
+      "event: content_block_delta\ndata: {\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"file_path\\\":\"}}\n\n",
+      "event: content_block_delta\ndata: {\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"\\\"a.txt\\\"}\"}}\n\n",
+      "event: content_block_stop\ndata: {\"index\":1}\n\n",
+      "event: message_delta\ndata: {\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":42}}\n\n",
+    );
+    let (events, turn) = parse(stream);
+    assert_eq!(
+      events,
+      vec![
+        StreamEvent::ContentStart,
+        StreamEvent::TextDelta("Let me look.".into()),
+        StreamEvent::ContentStop,
+        StreamEvent::ContentStop,
+      ]
+    );
+    assert_eq!(turn.content_blocks[0], json!({ "type": "text", "text": "Let me look." }));
+    assert_eq!(
+      turn.usage,
+      Usage { input_tokens: 120, output_tokens: 42, cache_read_tokens: 900, cache_write_tokens: 0 }
+    );
+    assert_eq!(
+      turn.tool_calls,
+      vec![ToolCall { id: "tu_1".into(), name: "Read".into(), input: json!({ "file_path": "a.txt" }) }]
+    );
+  }
+
+  #[test]
+  fn parser_surfaces_stream_errors() {
+    let mut parser = AnthropicAdapter.stream_parser();
+    let error = parser
+      .push(b"event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n")
+      .unwrap_err();
+    assert!(error.message.contains("Overloaded"));
+    assert!(error.retryable);
   }
 }
@@ -0,0 +1,187 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Images are downscaled by the UI before sending; this is the hard per-image limit of the APIs.
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_DOCUMENT_BYTES: usize = 10 * 1024 * 1024;
+pub const MAX_TEXT_BYTES: usize = 1024 * 1024;
+pub const MAX_TOTAL_BYTES: usize = 20 * 1024 * 1024;
+pub const MAX_ATTACHMENTS: usize = 10;
+
+/// Introduces tool-returned files on APIs whose tool messages cannot carry them.
+pub const TOOL_FILES_PROMPT: &str = "Files returned by the tool calls above:";
+
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp", "image/gif"];
+
+/// A file sent along with a prompt, stored inline on the `user_prompt` history entry.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+  pub name: String,
+  pub media_type: String,
+  /// File contents, base64-encoded.
+  pub data: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttachmentKind {
+  Image,
+  Pdf,
+  Text,
+}
+
+impl Attachment {
+  pub fn kind(&self) -> Option<AttachmentKind> {
+    let media_type = self.media_type.as_str();
+    if IMAGE_TYPES.contains(&media_type) {
+      Some(AttachmentKind::Image)
+    } else if media_type == "application/pdf" {
+      Some(AttachmentKind::Pdf)
+    } else if media_type.starts_with("text/") || media_type == "application/json" {
+      Some(AttachmentKind::Text)
+    } else {
+      None
+    }
+  }
+
+  /// Size of the decoded file, computed from the base64 length.
+  pub fn decoded_len(&self) -> usize {
+    let padding = self.data.bytes().rev().take_while(|byte| *byte == b'=').count();
+    (self.data.len() / 4 * 3).saturating_sub(padding)
+  }
+
+  /// Contents of a text attachment; `None` for binary kinds or invalid UTF-8.
+  pub fn text(&self) -> Option<String> {
+    if self.kind() != Some(AttachmentKind::Text) {
+      return None;
+    }
+    String::from_utf8(STANDARD.decode(&self.data).ok()?).ok()
+  }
+
+  pub fn data_url(&self) -> String {
+    format!("data:{};base64,{}", self.media_type, self.data)
+  }
+
+  /// Text attachments are sent as plain text parts, labelled with the file name.
+  pub fn text_part(&self) -> Option<String> {
+    self.text().map(|text| format!("<file name=\"{}\">\n{}\n</file>", self.name, text))
+  }
+}
+
+/// Attachments recorded on a `user_prompt` history entry.
+pub fn from_entry(message: &Value) -> Vec<Attachment> {
+  message
+    .get("attachments")
+    .cloned()
+    .and_then(|value| serde_json::from_value(value).ok())
+    .unwrap_or_default()
+}
+
+/// Checks count, type, size and that the bytes match the declared type.
+pub fn validate(attachments: &[Attachment]) -> Result<(), String> {
+  if attachments.len() > MAX_ATTACHMENTS {
+    return Err(format!("每条消息最多只能附加 {} 个文件。", MAX_ATTACHMENTS));
+  }
+  let mut total = 0;
+  for attachment in attachments {
+    let name = &attachment.name;
+    let Some(kind) = attachment.kind() else {
+      return Err(format!("不支持的附件类型：{}（{}）。", name, attachment.media_type));
+    };
+    let size = attachment.decoded_len();
+    let limit = match kind {
+      AttachmentKind::Image => MAX_IMAGE_BYTES,
+      AttachmentKind::Pdf => MAX_DOCUMENT_BYTES,
+      AttachmentKind::Text => MAX_TEXT_BYTES,
+    };
+    if size > limit {
+      return Err(format!("附件 {} 过大（{} KB），上限为 {} KB。", name, size / 1024, limit / 1024));
+    }
+    total += size;
+
+    let head = decode_head(&attachment.data).ok_or_else(|| format!("附件 {} 不是有效的 base64 数据。", name))?;
+    let matches = match kind {
+      AttachmentKind::Image | AttachmentKind::Pdf => sniff_media_type(&head) == Some(attachment.media_type.as_str()),
+      AttachmentKind::Text => attachment.text().is_some(),
+    };
+    if !matches {
+      return Err(format!("附件 {} 的内容与类型 {} 不符。", name, attachment.media_type));
+    }
+  }
+  if total > MAX_TOTAL_BYTES {
+    return Err(format!("附件总大小不能超过 {} MB。", MAX_TOTAL_BYTES / 1024 / 1024));
+  }
+  Ok(())
+}
+
+/// The first bytes of the file, enough to recognise its format.
+fn decode_head(data: &str) -> Option<Vec<u8>> {
+  STANDARD.decode(data.get(..24).unwrap_or(data)).ok()
+}
+
+/// Recognises the binary formats models accept (images and PDF) by their signature.
+pub fn sniff_media_type(head: &[u8]) -> Option<&'static str> {
+  if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+    Some("image/png")
+  } else if head.starts_with(&[0xff, 0xd8, 0xff]) {
+    Some("image/jpeg")
+  } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+    Some("image/gif")
+  } else if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+    Some("image/webp")
+  } else if head.starts_with(b"%PDF-") {
+    Some("application/pdf")
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn attachment(name: &str, media_type: &str, bytes: &[u8]) -> Attachment {
+    Attachment { name: name.into(), media_type: media_type.into(), data: STANDARD.encode(bytes) }
+  }
+
+  #[test]
+  fn validate_checks_type_and_contents() {
+    let png = attachment("a.png", "image/png", b"\x89PNG\r\n\x1a\n0000000000");
+    let pdf = attachment("a.pdf", "application/pdf", b"%PDF-1.7\n...");
+    assert_eq!(sniff_media_type(b"%PDF-1.7"), Some("application/pdf"));
+    let notes = attachment("notes.md", "text/markdown", "# 笔记".as_bytes());
+    assert!(validate(&[png.clone(), pdf, notes.clone()]).is_ok());
+    assert_eq!(notes.text_part().unwrap(), "<file name=\"notes.md\">\n# 笔记\n</file>");
+
+    let renamed = Attachment { media_type: "image/jpeg".into(), ..png };
+    assert!(validate(&[renamed]).unwrap_err().contains("不符"));
+    assert!(validate(&[attachment("a.exe", "application/octet-stream", b"MZ")]).is_err());
+    assert!(validate(&[attachment("bin.txt", "text/plain", &[0xff, 0xfe, 0x00])]).is_err());
+  }
+
+  #[test]
+  fn validate_enforces_size_limits() {
+    let mut big = vec![0u8; MAX_IMAGE_BYTES + 1];
+    big[..3].copy_from_slice(&[0xff, 0xd8, 0xff]);
+    let image = attachment("big.jpg", "image/jpeg", &big);
+    assert_eq!(image.decoded_len(), MAX_IMAGE_BYTES + 1);
+    assert!(validate(&[image]).unwrap_err().contains("过大"));
+
+    let many = vec![attachment("a.txt", "text/plain", b"a"); MAX_ATTACHMENTS + 1];
+    assert!(validate(&many).is_err());
+  }
+
+  #[test]
+  fn from_entry_reads_stored_attachments() {
+    let message = serde_json::json!({
+      "type": "user_prompt",
+      "prompt": "see file",
+      "attachments": [{ "name": "a.txt", "mediaType": "text/plain", "data": "aGk=" }]
+    });
+    let attachments = from_entry(&message);
+    assert_eq!(attachments[0].text().as_deref(), Some("hi"));
+    assert!(from_entry(&serde_json::json!({ "type": "user_prompt", "prompt": "x" })).is_empty());
+  }
+}
@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use reqwest::RequestBuilder;
+use serde_json::{json, Map, Value};
+
+use super::adapter::{
+  generated_id_prefix, stringify_value, token_count, ChatRequest, ModelTurn, ProviderAdapter, StreamError,
+  StreamEvent, StreamParser, ToolCall, Usage,
+};
+use super::attachments::{self, Attachment, AttachmentKind};
+use super::params::{GenerationParams, ParamKeys, ReasoningEffort};
+use super::sse::SseDecoder;
+use crate::tools::ToolSpec;
+
+/// JSON Schema keywords outside the OpenAPI subset Gemini accepts for function parameters.
+const UNSUPPORTED_SCHEMA_KEYS: &[&str] = &["$schema", "additionalProperties"];
+
+const PARAM_KEYS: ParamKeys = ParamKeys {
+  max_tokens: Some("maxOutputTokens"),
+  temperature: "temperature",
+  top_p: "topP",
+  stop: Some("stopSequences"),
+};
+
+pub struct GeminiAdapter;
+
+impl ProviderAdapter for GeminiAdapter {
+  fn name(&self) -> &'static str {
+    "Gemini"
+  }
+
+  fn default_url(&self) -> &'static str {
+    "https://generativelanguage.googleapis.com/v1beta"
+  }
+
+  /// The model is part of the path, so a base URL is the API root unless it already names the method.
+  fn endpoint(&self, base_url: Option<&str>, model: &str) -> String {
+    let base = base_url.unwrap_or(self.default_url()).trim_end_matches('/');
+    if base.contains(":streamGenerateContent") {
+      return base.to_string();
+    }
+    let model = model.trim_start_matches("models/");
+    format!("{}/models/{}:streamGenerateContent?alt=sse", base, model)
+  }
+
+  fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+    request.header("x-goog-api-key", api_key)
+  }
+
+  fn validate_params(&self, _model: &str, params: &GenerationParams) -> Result<(), String> {
+    params.check(2.0, 5)
+  }
+
+  fn build_request(&self, req: &ChatRequest) -> Value {
+    let mut body = json!({ "contents": build_contents(req.history) });
+    if let Some(system) = req.system {
+      body["systemInstruction"] = json!({ "parts": [{ "text": system }] });
+    }
+    if !req.tools.is_empty() {
+      body["tools"] = json!([{ "functionDeclarations": function_declarations(req.tools) }]);
+    }
+    let mut config = Map::new();
+    req.params.write_into(&mut config, PARAM_KEYS);
+    // An explicit budget wins over the one derived from the effort.
+    let budget = req.params.thinking_budget.or(req.params.reasoning_effort.map(thinking_budget));
+    if let Some(budget) = budget {
+      config.insert("thinkingConfig".into(), json!({ "thinkingBudget": budget }));
+    }
+    if !config.is_empty() {
+      body["generationConfig"] = Value::Object(config);
+    }
+    body
+  }
+
+  fn stream_parser(&self) -> Box<dyn StreamParser + Send> {
+    Box::new(GeminiStreamParser::new())
+  }
+}
+
+/// Gemini 2.5 sizes its thinking by a token budget rather than a named effort.
+fn thinking_budget(effort: ReasoningEffort) -> u32 {
+  match effort {
+    ReasoningEffort::Minimal => 512,
+    ReasoningEffort::Low => 1024,
+    ReasoningEffort::Medium => 8192,
+    ReasoningEffort::High => 24576,
+  }
+}
+
+pub fn function_declarations(tools: &[ToolSpec]) -> Vec<Value> {
+  tools
+    .iter()
+    .map(|tool| {
+      json!({
+        "name": tool.name,
+        "description": tool.description,
+        "parameters": sanitize_schema(&tool.parameters)
+      })
+    })
+    .collect()
+}
+
+fn sanitize_schema(schema: &Value) -> Value {
+  match schema {
+    Value::Object(map) => Value::Object(
+      map
+        .iter()
+        .filter(|(key, _)| !UNSUPPORTED_SCHEMA_KEYS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), sanitize_schema(value)))
+        .collect(),
+    ),
+    Value::Array(items) => Value::Array(items.iter().map(sanitize_schema).collect()),
+    other => other.clone(),
+  }
+}
+
+/// Replays stored history as Gemini `contents`. Consecutive entries with the same role are
+/// merged, so parallel tool results land in one turn; results are matched to their call by name.
+pub fn build_contents(history: &[Value]) -> Vec<Value> {
+  let mut contents: Vec<Value> = Vec::new();
+  let mut tool_names: HashMap<String, String> = HashMap::new();
+
+  for item in history {
+    let msg_type = item.get("type").and_then(Value::as_str);
+    match msg_type {
+      Some("user_prompt") => {
+        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
+          let mut parts = vec![json!({ "text": prompt })];
+          parts.extend(attachments::from_entry(item).iter().filter_map(attachment_part));
+          push_parts(&mut contents, "user", parts);
+        }
+      }
+      Some("assistant") => {
+        let Some(blocks) = item.pointer("/message/content").and_then(Value::as_array) else {
+          continue;
+        };
+        let mut parts = Vec::new();
+        for block in blocks {
+          match block.get("type").and_then(Value::as_str) {
+            Some("text") => {
+              if let Some(text) = block.get("text").and_then(Value::as_str) {
+                if !text.is_empty() {
+                  parts.push(json!({ "text": text }));
+                }
+              }
+            }
+            Some("tool_use") => {
+              let id = block.get("id").and_then(Value::as_str).unwrap_or_default();
+              let name = block.get("name").and_then(Value::as_str).unwrap_or_default();
+              let args = block
+                .get("input")
+                .filter(|input| input.is_object())
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+              tool_names.insert(id.to_string(), name.to_string());
+              let mut part = json!({ "functionCall": { "name": name, "args": args } });
+              if let Some(signature) = block.get("thought_signature") {
+                part["thoughtSignature"] = signature.clone();
+              }
+              parts.push(part);
+            }
+            _ => {}
+          }
+        }
+        push_parts(&mut contents, "model", parts);
+      }
+      Some("user") => {
+        let Some(blocks) = item.pointer("/message/content").and_then(Value::as_array) else {
+          continue;
+        };
+        let mut parts = Vec::new();
+        for block in blocks {
+          if block.get("type").and_then(Value::as_str) != Some("tool_result") {
+            continue;
+          }
+          let id = block.get("tool_use_id").and_then(Value::as_str).unwrap_or_default();
+          let name = tool_names.get(id).cloned().unwrap_or_default();
+          let output = stringify_value(&block.get("content").cloned().unwrap_or(Value::Null));
+          let is_error = block.get("is_error").and_then(Value::as_bool).unwrap_or(false);
+          let response = if is_error { json!({ "error": output }) } else { json!({ "content": output }) };
+          parts.push(json!({ "functionResponse": { "name": name, "response": response } }));
+          parts.extend(attachments::from_entry(block).iter().filter_map(attachment_part));
+        }
+        push_parts(&mut contents, "user", parts);
+      }
+      _ => {}
+    }
+  }
+
+  contents
+}
+
+/// Images and PDFs go inline; text files become a labelled text part.
+fn attachment_part(attachment: &Attachment) -> Option<Value> {
+  match attachment.kind()? {
+    AttachmentKind::Image | AttachmentKind::Pdf => Some(json!({
+      "inlineData": { "mimeType": attachment.media_type, "data": attachment.data }
+    })),
+    AttachmentKind::Text => attachment.text_part().map(|text| json!({ "text": text })),
+  }
+}
+
+fn push_parts(contents: &mut Vec<Value>, role: &str, parts: Vec<Value>) {
+  if parts.is_empty() {
+    return;
+  }
+  if let Some(last) = contents.last_mut() {
+    if last.get("role").and_then(Value::as_str) == Some(role) {
+      if let Some(existing) = last.get_mut("parts").and_then(Value::as_array_mut) {
+        existing.extend(parts);
+        return;
+      }
+    }
+  }
+  contents.push(json!({ "role": role, "parts": parts }));
+}
+
+/// Decodes `streamGenerateContent?alt=sse`; every event carries whole parts, including complete
+/// function calls, so no argument buffering is needed.
+pub struct GeminiStreamParser {
+  decoder: SseDecoder,
+  text: String,
+  calls: Vec<(ToolCall, Option<Value>)>,
+  started: bool,
+  stopped: bool,
+  id_prefix: String,
+  usage: Usage,
+  truncated: bool,
+}
+
+impl GeminiStreamParser {
+  fn new() -> Self {
+    Self {
+      decoder: SseDecoder::default(),
+      text: String::new(),
+      calls: Vec::new(),
+      started: false,
+      stopped: false,
+      id_prefix: generated_id_prefix("gemini"),
+      usage: Usage::default(),
+      truncated: false,
+    }
+  }
+
+  fn stop(&mut self, output: &mut Vec<StreamEvent>) {
+    if self.started && !self.stopped {
+      self.stopped = true;
+      output.push(StreamEvent::ContentStop);
+    }
+  }
+
+  fn handle(&mut self, payload: &Value, output: &mut Vec<StreamEvent>) -> Result<(), StreamError> {
+    if let Some(message) = payload.pointer("/error/message").and_then(Value::as_str) {
+      let message = format!("Gemini stream error: {}", message);
+      let code = payload.pointer("/error/code").and_then(Value::as_u64).unwrap_or(0);
+      return Err(if code == 429 || code >= 500 {
+        StreamError::transient(message)
+      } else {
+        StreamError::fatal(message)
+      });
+    }
+    if let Some(reason) = payload.pointer("/promptFeedback/blockReason").and_then(Value::as_str) {
+      return Err(StreamError::fatal(format!("Gemini blocked the prompt: {}", reason)));
+    }
+    // Running totals; the last chunk carries the final counts. Thinking tokens bill as output.
+    if let Some(usage) = payload.get("usageMetadata") {
+      let cached = token_count(usage, "/cachedContentTokenCount");
+      self.usage = Usage {
+        input_tokens: token_count(usage, "/promptTokenCount").saturating_sub(cached),
+        output_tokens: token_count(usage, "/candidatesTokenCount") + token_count(usage, "/thoughtsTokenCount"),
+        cache_read_tokens: cached,
+        cache_write_tokens: 0,
+      };
+    }
+    let Some(candidate) = payload.pointer("/candidates/0") else {
+      return Ok(());
+    };
+    let parts = candidate.pointer("/content/parts").and_then(Value::as_array);
+    for part in parts.into_iter().flatten() {
+      // Thought summaries are not part of the visible reply.
+      if part.get("thought").and_then(Value::as_bool) == Some(true) {
+        continue;
+      }
+      if let Some(text) = part.get("text").and_then(Value::as_str) {
+        if !self.started {
+          self.started = true;
+          output.push(StreamEvent::ContentStart);
+        }
+        self.text.push_str(text);
+        output.push(StreamEvent::TextDelta(text.to_string()));
+      }
+      if let Some(call) = part.get("functionCall") {
+        let id = call
+          .get("id")
+          .and_then(Value::as_str)
+          .map(str::to_string)
+          .unwrap_or_else(|| format!("{}-{}", self.id_prefix, self.calls.len()));
+        let name = call.get("name").and_then(Value::as_str).unwrap_or("UnknownTool").to_string();
+        let input = call.get("args").cloned().unwrap_or_else(|| json!({}));
+        self.calls.push((ToolCall { id, name, input }, part.get("thoughtSignature").cloned()));
+      }
+    }
+    if let Some(reason) = candidate.get("finishReason").filter(|reason| !reason.is_null()) {
+      self.truncated = reason == "MAX_TOKENS";
+      self.stop(output);
+    }
+    Ok(())
+  }
+}
+
+impl StreamParser for GeminiStreamParser {
+  fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, StreamError> {
+    let mut output = Vec::new();
+    for event in self.decoder.push(chunk) {
+      let payload: Value = serde_json::from_str(&event.data)?;
+      self.handle(&payload, &mut output)?;
+    }
+    Ok(output)
+  }
+
+  fn finish(self: Box<Self>) -> ModelTurn {
+    let mut turn = ModelTurn { usage: self.usage, truncated: self.truncated, ..ModelTurn::default() };
+    if !self.text.trim().is_empty() {
+      turn.content_blocks.push(json!({ "type": "text", "text": self.text }));
+    }
+    for (call, signature) in self.calls {
+      let mut block = Map::new();
+      block.insert("type".into(), json!("tool_use"));
+      block.insert("id".into(), json!(call.id));
+      block.insert("name".into(), json!(call.name));
+      block.insert("input".into(), call.input.clone());
+      // Thinking models reject follow-up turns whose function calls lost their signature.
+      if let Some(signature) = signature {
+        block.insert("thought_signature".into(), signature);
+      }
+      turn.content_blocks.push(Value::Object(block));
+      turn.tool_calls.push(call);
+    }
+    turn
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn endpoint_puts_model_in_path() {
+    assert_eq!(
+      GeminiAdapter.endpoint(None, "models/gemini-2.5-flash"),
+      "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+    );
+    let custom = "https://proxy.example/v1beta/models/x:streamGenerateContent?alt=sse";
+    assert_eq!(GeminiAdapter.endpoint(Some(custom), "gemini-2.5-pro"), custom);
+  }
+
+  #[test]
+  fn function_declarations_drop_unsupported_keywords() {
+    let tools = vec![ToolSpec {
+      name: "Read",
+      description: "Read a file from disk.",
+      parameters: json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": { "file_path": { "type": "string" } },
+        "required": ["file_path"]
+      }),
+    }];
+    let declarations = function_declarations(&tools);
+    assert_eq!(declarations[0]["name"], "Read");
+    assert!(declarations[0]["parameters"].get("additionalProperties").is_none());
+    assert_eq!(declarations[0]["parameters"]["required"], json!(["file_path"]));
+  }
+
+  #[test]
+  fn build_contents_maps_calls_and_merges_results() {
+    let history = vec![
+      json!({ "type": "user_prompt", "prompt": "read both" }),
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "text", "text": "Reading." },
+        { "type": "tool_use", "id": "g-1", "name": "Read", "input": { "file_path": "a.txt" }, "thought_signature": "sig" },
+        { "type": "tool_use", "id": "g-2", "name": "Grep", "input": { "pattern": "x" } }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": "g-1", "content": "A", "is_error": false }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": "g-2", "content": "boom", "is_error": true }
+      ] } }),
+      json!({ "type": "marker", "kind": "interrupted", "text": "Interrupted by user." }),
+    ];
+    let contents = build_contents(&history);
+    assert_eq!(contents.len(), 3);
+    assert_eq!(contents[1]["role"], "model");
+    assert_eq!(contents[1]["parts"][1]["functionCall"]["args"], json!({ "file_path": "a.txt" }));
+    assert_eq!(contents[1]["parts"][1]["thoughtSignature"], "sig");
+    assert_eq!(
+      contents[2]["parts"],
+      json!([
+        { "functionResponse": { "name": "Read", "response": { "content": "A" } } },
+        { "functionResponse": { "name": "Grep", "response": { "error": "boom" } } }
+      ])
+    );
+  }
+
+  #[test]
+  fn parser_collects_text_and_function_calls() {
+    let stream = concat!(
+      "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Let me \"}]}}]}\r\n\r\n",
+      "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"check.\"},",
+      "{\"functionCall\":{\"name\":\"Bash\",\"args\":{\"command\":\"ls\"}},\"thoughtSignature\":\"abc\"}]},",
+      "\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":70,\"candidatesTokenCount\":12,\"thoughtsTokenCount\":30}}\r\n\r\n",
+    );
+    let mut parser = GeminiAdapter.stream_parser();
+    let events = parser.push(stream.as_bytes()).unwrap();
+    assert_eq!(
+      events,
+      vec![
+        StreamEvent::ContentStart,
+        StreamEvent::TextDelta("Let me ".into()),
+        StreamEvent::TextDelta("check.".into()),
+        StreamEvent::ContentStop,
+      ]
+    );
+    let turn = parser.finish();
+    assert_eq!(turn.content_blocks[0]["text"], "Let me check.");
+    assert_eq!(turn.content_blocks[1]["thought_signature"], "abc");
+    assert_eq!(turn.usage, Usage { input_tokens: 70, output_tokens: 42, ..Usage::default() });
+    assert_eq!(turn.tool_calls[0].name, "Bash");
+    assert_eq!(turn.tool_calls[0].input, json!({ "command": "ls" }));
+    assert!(turn.tool_calls[0].id.starts_with("gemini-"));
+  }
+
+  #[test]
+  fn build_request_sets_generation_config() {
+    let params = GenerationParams {
+      max_tokens: Some(4096),
+      top_p: Some(0.9),
+      reasoning_effort: Some(ReasoningEffort::Low),
+      ..GenerationParams::default()
+    };
+    let body = GeminiAdapter.build_request(&ChatRequest {
+      model: "gemini-2.5-flash",
+      history: &[],
+      tools: &[],
+      system: Some("Be brief."),
+      params: &params,
+    });
+    assert_eq!(body["systemInstruction"], json!({ "parts": [{ "text": "Be brief." }] }));
+    assert_eq!(
+      body["generationConfig"],
+      json!({ "maxOutputTokens": 4096, "topP": 0.9, "thinkingConfig": { "thinkingBudget": 1024 } })
+    );
+    let plain = GeminiAdapter.build_request(&ChatRequest {
+      model: "gemini-2.5-flash",
+      history: &[],
+      tools: &[],
+      system: None,
+      params: &GenerationParams::default(),
+    });
+    assert!(plain.get("generationConfig").is_none());
+  }
+
+  #[test]
+  fn parser_surfaces_errors() {
+    let mut parser = GeminiAdapter.stream_parser();
+    let error = parser
+      .push(b"data: {\"error\":{\"code\":429,\"message\":\"Quota exceeded\"}}\n\n")
+      .unwrap_err();
+    assert!(error.message.contains("Quota exceeded"));
+    assert!(error.retryable);
+  }
+}
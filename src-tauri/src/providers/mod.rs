@@ -1,4 +1,12 @@
 pub mod adapter;
 pub mod anthropic;
+pub mod attachments;
+pub mod gemini;
+pub mod ollama;
 pub mod openai;
+pub mod openai_responses;
+pub mod params;
+pub mod pricing;
 pub mod registry;
+pub mod retry;
+pub mod sse;
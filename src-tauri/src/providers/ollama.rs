@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+
+use super::adapter::{
+  generated_id_prefix, parse_tool_input, stringify_value, token_count, tool_use_block, ChatRequest, ModelTurn,
+  ProviderAdapter, StreamError, StreamEvent, StreamParser, ToolCall, Usage,
+};
+use super::attachments::{self, Attachment, AttachmentKind};
+use super::openai;
+use super::params::{GenerationParams, ParamKeys};
+
+pub const DEFAULT_SERVER: &str = "http://localhost:11434";
+/// Sampling settings go into the request's `options` object under llama.cpp's names.
+const PARAM_KEYS: ParamKeys = ParamKeys {
+  max_tokens: Some("num_predict"),
+  temperature: "temperature",
+  top_p: "top_p",
+  stop: Some("stop"),
+};
+
+pub struct OllamaAdapter;
+
+impl ProviderAdapter for OllamaAdapter {
+  fn name(&self) -> &'static str {
+    "Ollama"
+  }
+
+  fn default_url(&self) -> &'static str {
+    "http://localhost:11434/api/chat"
+  }
+
+  /// Accepts either the server root (`http://host:11434`) or the full `/api/chat` URL.
+  fn endpoint(&self, base_url: Option<&str>, _model: &str) -> String {
+    format!("{}/api/chat", server_root(base_url))
+  }
+
+  fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+    // Local servers need no key; one is only sent when a proxy in front of Ollama expects it.
+    if api_key.is_empty() {
+      request
+    } else {
+      request.bearer_auth(api_key)
+    }
+  }
+
+  fn validate_params(&self, _model: &str, params: &GenerationParams) -> Result<(), String> {
+    if params.reasoning_effort.is_some() {
+      return Err("Ollama 不支持 reasoning effort 参数。".into());
+    }
+    if params.thinking_budget.is_some() {
+      return Err("Ollama 不支持 thinking budget 参数。".into());
+    }
+    params.check(2.0, usize::MAX)
+  }
+
+  fn validate_attachments(&self, attachments: &[Attachment]) -> Result<(), String> {
+    if let Some(pdf) = attachments.iter().find(|attachment| attachment.kind() == Some(AttachmentKind::Pdf)) {
+      return Err(format!("Ollama 不支持 PDF 附件：{}。", pdf.name));
+    }
+    Ok(())
+  }
+
+  fn build_request(&self, req: &ChatRequest) -> Value {
+    let mut body = json!({
+      "model": req.model,
+      "messages": openai::with_system_message(req.system, build_messages(req.history)),
+      "stream": true,
+      "tools": openai::tool_definitions(req.tools)
+    });
+    let mut options = serde_json::Map::new();
+    req.params.write_into(&mut options, PARAM_KEYS);
+    if !options.is_empty() {
+      body["options"] = Value::Object(options);
+    }
+    body
+  }
+
+  fn stream_parser(&self) -> Box<dyn StreamParser + Send> {
+    Box::new(OllamaStreamParser::new())
+  }
+}
+
+/// Strips any API path so `/api/chat` and `/api/tags` can be derived from one setting.
+pub fn server_root(base_url: Option<&str>) -> String {
+  let base = base_url.map(str::trim).filter(|value| !value.is_empty()).unwrap_or(DEFAULT_SERVER);
+  let base = base.trim_end_matches('/');
+  base
+    .strip_suffix("/api/chat")
+    .or_else(|| base.strip_suffix("/api/tags"))
+    .unwrap_or(base)
+    .to_string()
+}
+
+/// Lists the models installed on the server via `GET /api/tags`.
+pub async fn list_models(base_url: Option<&str>) -> Result<Vec<String>, String> {
+  let url = format!("{}/api/tags", server_root(base_url));
+  let response = reqwest::get(&url).await.map_err(|e| e.to_string())?;
+  if !response.status().is_success() {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    return Err(format!("Ollama 请求失败({}): {}", status, text));
+  }
+  let payload: Value = response.json().await.map_err(|e| e.to_string())?;
+  Ok(parse_model_names(&payload))
+}
+
+pub fn parse_model_names(payload: &Value) -> Vec<String> {
+  payload
+    .get("models")
+    .and_then(Value::as_array)
+    .map(|models| {
+      models
+        .iter()
+        .filter_map(|model| model.get("name").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Images ride along base64-encoded in `images`; text files are appended to the prompt.
+fn user_message(prompt: &str, attachments: &[Attachment]) -> Value {
+  let mut content = prompt.to_string();
+  let mut images = Vec::new();
+  for attachment in attachments {
+    match attachment.kind() {
+      Some(AttachmentKind::Image) => images.push(attachment.data.clone()),
+      Some(AttachmentKind::Text) => {
+        if let Some(text) = attachment.text_part() {
+          content.push_str("\n\n");
+          content.push_str(&text);
+        }
+      }
+      _ => {}
+    }
+  }
+  let mut message = json!({ "role": "user", "content": content });
+  if !images.is_empty() {
+    message["images"] = json!(images);
+  }
+  message
+}
+
+/// Replays stored history as `/api/chat` messages. Tool results are matched back to the
+/// tool name because Ollama identifies tool output by name rather than by call id.
+pub fn build_messages(history: &[Value]) -> Vec<Value> {
+  let mut messages = Vec::new();
+  let mut tool_names: HashMap<String, String> = HashMap::new();
+
+  for item in history {
+    let msg_type = item.get("type").and_then(Value::as_str);
+    match msg_type {
+      Some("user_prompt") => {
+        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
+          messages.push(user_message(prompt, &attachments::from_entry(item)));
+        }
+      }
+      Some("assistant") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          let mut text = String::new();
+          let mut tool_calls = Vec::new();
+          for content in contents {
+            match content.get("type").and_then(Value::as_str) {
+              Some("text") => {
+                if let Some(part) = content.get("text").and_then(Value::as_str) {
+                  text.push_str(part);
+                }
+              }
+              Some("tool_use") => {
+                let id = content.get("id").and_then(Value::as_str).unwrap_or_default();
+                let name = content.get("name").and_then(Value::as_str).unwrap_or_default();
+                let input = content.get("input").cloned().unwrap_or_else(|| json!({}));
+                tool_names.insert(id.to_string(), name.to_string());
+                tool_calls.push(json!({ "function": { "name": name, "arguments": input } }));
+              }
+              _ => {}
+            }
+          }
+          if !tool_calls.is_empty() || !text.trim().is_empty() {
+            let mut message = json!({ "role": "assistant", "content": text });
+            if !tool_calls.is_empty() {
+              message["tool_calls"] = Value::Array(tool_calls);
+            }
+            messages.push(message);
+          }
+        }
+      }
+      Some("user") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          for content in contents {
+            if content.get("type").and_then(Value::as_str) == Some("tool_result") {
+              let id = content.get("tool_use_id").and_then(Value::as_str).unwrap_or_default();
+              let payload = content.get("content").cloned().unwrap_or(Value::String(String::new()));
+              let mut message = json!({ "role": "tool", "content": stringify_value(&payload) });
+              if let Some(name) = tool_names.get(id) {
+                message["tool_name"] = Value::String(name.clone());
+              }
+              let images: Vec<String> = attachments::from_entry(content)
+                .into_iter()
+                .filter(|file| file.kind() == Some(AttachmentKind::Image))
+                .map(|file| file.data)
+                .collect();
+              if !images.is_empty() {
+                message["images"] = json!(images);
+              }
+              messages.push(message);
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  messages
+}
+
+/// Decodes the newline-delimited JSON body of a streaming `/api/chat` response.
+pub struct OllamaStreamParser {
+  buffer: Vec<u8>,
+  text: String,
+  tool_calls: Vec<ToolCall>,
+  started: bool,
+  stopped: bool,
+  id_prefix: String,
+  usage: Usage,
+  truncated: bool,
+}
+
+impl OllamaStreamParser {
+  fn new() -> Self {
+    Self {
+      buffer: Vec::new(),
+      text: String::new(),
+      tool_calls: Vec::new(),
+      started: false,
+      stopped: false,
+      id_prefix: generated_id_prefix("ollama"),
+      usage: Usage::default(),
+      truncated: false,
+    }
+  }
+
+  fn stop(&mut self, output: &mut Vec<StreamEvent>) {
+    if self.started && !self.stopped {
+      self.stopped = true;
+      output.push(StreamEvent::ContentStop);
+    }
+  }
+
+  fn handle(&mut self, payload: &Value, output: &mut Vec<StreamEvent>) -> Result<(), StreamError> {
+    if let Some(message) = payload.get("error").and_then(Value::as_str) {
+      return Err(StreamError::fatal(format!("Ollama stream error: {}", message)));
+    }
+    if let Some(content) = payload.pointer("/message/content").and_then(Value::as_str) {
+      if !content.is_empty() {
+        if !self.started {
+          self.started = true;
+          output.push(StreamEvent::ContentStart);
+        }
+        self.text.push_str(content);
+        output.push(StreamEvent::TextDelta(content.to_string()));
+      }
+    }
+    if let Some(calls) = payload.pointer("/message/tool_calls").and_then(Value::as_array) {
+      for call in calls {
+        let id = call
+          .get("id")
+          .and_then(Value::as_str)
+          .map(str::to_string)
+          .unwrap_or_else(|| format!("{}-{}", self.id_prefix, self.tool_calls.len()));
+        let name = call
+          .pointer("/function/name")
+          .and_then(Value::as_str)
+          .unwrap_or("UnknownTool")
+          .to_string();
+        let input = match call.pointer("/function/arguments") {
+          Some(Value::String(raw)) => parse_tool_input(raw, None),
+          Some(arguments) => arguments.clone(),
+          None => json!({}),
+        };
+        self.tool_calls.push(ToolCall { id, name, input });
+      }
+    }
+    if payload.get("done").and_then(Value::as_bool) == Some(true) {
+      self.usage = Usage {
+        input_tokens: token_count(payload, "/prompt_eval_count"),
+        output_tokens: token_count(payload, "/eval_count"),
+        ..Usage::default()
+      };
+      self.truncated = payload.get("done_reason").and_then(Value::as_str) == Some("length");
+      self.stop(output);
+    }
+    Ok(())
+  }
+}
+
+impl StreamParser for OllamaStreamParser {
+  fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, StreamError> {
+    self.buffer.extend_from_slice(chunk);
+    let mut output = Vec::new();
+    while let Some(pos) = self.buffer.iter().position(|byte| *byte == b'\n') {
+      let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+      let line = String::from_utf8_lossy(&line);
+      let line = line.trim();
+      if line.is_empty() {
+        continue;
+      }
+      let payload: Value = serde_json::from_str(line)?;
+      self.handle(&payload, &mut output)?;
+    }
+    Ok(output)
+  }
+
+  fn finish(self: Box<Self>) -> ModelTurn {
+    let mut turn = ModelTurn { usage: self.usage, truncated: self.truncated, ..ModelTurn::default() };
+    if !self.text.trim().is_empty() {
+      turn.content_blocks.push(json!({ "type": "text", "text": self.text }));
+    }
+    turn.content_blocks.extend(self.tool_calls.iter().map(tool_use_block));
+    turn.tool_calls = self.tool_calls;
+    turn
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn endpoint_accepts_root_or_full_url() {
+    assert_eq!(OllamaAdapter.endpoint(None, "llama3.1"), "http://localhost:11434/api/chat");
+    assert_eq!(
+      OllamaAdapter.endpoint(Some("http://gpu-box:11434/"), "llama3.1"),
+      "http://gpu-box:11434/api/chat"
+    );
+    assert_eq!(
+      OllamaAdapter.endpoint(Some("http://gpu-box:11434/api/chat"), "llama3.1"),
+      "http://gpu-box:11434/api/chat"
+    );
+    assert_eq!(server_root(Some("  ")), DEFAULT_SERVER);
+  }
+
+  #[test]
+  fn build_messages_names_tool_results() {
+    let history = vec![
+      json!({ "type": "user_prompt", "prompt": "list files" }),
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "tool_use", "id": "ollama-1-0", "name": "Bash", "input": { "command": "ls" } }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": "ollama-1-0", "content": "a.txt", "is_error": false }
+      ] } }),
+      json!({ "type": "marker", "kind": "interrupted", "text": "Interrupted by user." }),
+    ];
+    let messages = build_messages(&history);
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[1]["tool_calls"][0]["function"]["arguments"], json!({ "command": "ls" }));
+    assert_eq!(messages[2], json!({ "role": "tool", "content": "a.txt", "tool_name": "Bash" }));
+  }
+
+  #[test]
+  fn parser_reads_ndjson_across_chunks() {
+    let body = concat!(
+      "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n",
+      "{\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":false}\n",
+      "{\"message\":{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"function\":{\"name\":\"Read\",\"arguments\":{\"file_path\":\"a.txt\"}}}]},\"done\":false}\n",
+      "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":26,\"eval_count\":9}\n",
+    );
+    let mut parser = OllamaAdapter.stream_parser();
+    let (head, tail) = body.as_bytes().split_at(30);
+    let mut events = parser.push(head).unwrap();
+    events.extend(parser.push(tail).unwrap());
+    assert_eq!(
+      events,
+      vec![
+        StreamEvent::ContentStart,
+        StreamEvent::TextDelta("Hel".into()),
+        StreamEvent::TextDelta("lo".into()),
+        StreamEvent::ContentStop,
+      ]
+    );
+    let turn = parser.finish();
+    assert_eq!(turn.content_blocks[0]["text"], "Hello");
+    assert_eq!(turn.usage, Usage { input_tokens: 26, output_tokens: 9, ..Usage::default() });
+    assert_eq!(turn.tool_calls.len(), 1);
+    assert_eq!(turn.tool_calls[0].name, "Read");
+    assert_eq!(turn.tool_calls[0].input, json!({ "file_path": "a.txt" }));
+    assert!(turn.tool_calls[0].id.starts_with("ollama-"));
+    assert_eq!(turn.content_blocks[1]["id"], turn.tool_calls[0].id.as_str());
+  }
+
+  #[test]
+  fn build_request_puts_params_in_options() {
+    let params = GenerationParams { max_tokens: Some(512), temperature: Some(0.1), ..GenerationParams::default() };
+    let body = OllamaAdapter.build_request(&ChatRequest {
+      model: "llama3.1",
+      history: &[],
+      tools: &[],
+      system: None,
+      params: &params,
+    });
+    assert_eq!(body["options"], json!({ "num_predict": 512, "temperature": 0.1 }));
+
+    let mut parser = OllamaAdapter.stream_parser();
+    parser.push(b"{\"message\":{\"content\":\"cut\"},\"done\":true,\"done_reason\":\"length\"}\n").unwrap();
+    assert!(parser.finish().truncated);
+  }
+
+  #[test]
+  fn parser_surfaces_errors() {
+    let mut parser = OllamaAdapter.stream_parser();
+    let error = parser.push(b"{\"error\":\"model 'llama9' not found\"}\n").unwrap_err();
+    assert!(error.message.contains("not found"));
+  }
+
+  #[test]
+  fn parse_model_names_reads_tags() {
+    let payload = json!({ "models": [{ "name": "llama3.1:8b" }, { "name": "qwen2.5-coder" }] });
+    assert_eq!(parse_model_names(&payload), vec!["llama3.1:8b", "qwen2.5-coder"]);
+    assert!(parse_model_names(&json!({})).is_empty());
+  }
+}
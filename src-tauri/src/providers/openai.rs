@@ -1,15 +1,317 @@
-use serde_json::Value;
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
 
-use super::adapter::{ChatRequest, ProviderAdapter};
+use super::adapter::{
+  generated_id_prefix, parse_tool_input, stringify_value, token_count, tool_use_block, ChatRequest, ModelTurn,
+  ProviderAdapter, StreamError, StreamEvent, StreamParser, ToolCall, Usage,
+};
+use super::attachments::{self, Attachment, AttachmentKind};
+use super::openai_responses::{check_reasoning_params, is_reasoning_model};
+use super::params::{GenerationParams, ParamKeys};
+use super::sse::SseDecoder;
+use crate::tools::ToolSpec;
 
 pub struct OpenAIAdapter;
 
+const PARAM_KEYS: ParamKeys = ParamKeys {
+  max_tokens: Some("max_tokens"),
+  temperature: "temperature",
+  top_p: "top_p",
+  stop: Some("stop"),
+};
+
 impl ProviderAdapter for OpenAIAdapter {
+  fn name(&self) -> &'static str {
+    "OpenAI"
+  }
+
+  fn default_url(&self) -> &'static str {
+    "https://api.openai.com/v1/chat/completions"
+  }
+
+  fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+    request.bearer_auth(api_key)
+  }
+
+  fn validate_params(&self, model: &str, params: &GenerationParams) -> Result<(), String> {
+    check_reasoning_params(model, params)?;
+    params.check(2.0, 4)
+  }
+
   fn build_request(&self, req: &ChatRequest) -> Value {
-    serde_json::json!({
+    let mut body = json!({
       "model": req.model,
-      "messages": [{ "role": "user", "content": req.prompt }]
+      "messages": with_system_message(req.system, build_messages(req.history)),
+      "stream": true,
+      "stream_options": { "include_usage": true }
+    });
+    // An empty `tools` array is rejected, so tool-less requests (e.g. summaries) leave both keys out.
+    if !req.tools.is_empty() {
+      body["tools"] = json!(tool_definitions(req.tools));
+      body["tool_choice"] = json!("auto");
+    }
+    // Reasoning models only accept `max_completion_tokens`; compatible servers mostly know `max_tokens`.
+    let keys = if is_reasoning_model(req.model) {
+      ParamKeys { max_tokens: Some("max_completion_tokens"), ..PARAM_KEYS }
+    } else {
+      PARAM_KEYS
+    };
+    if let Some(body) = body.as_object_mut() {
+      req.params.write_into(body, keys);
+    }
+    if let Some(effort) = req.params.reasoning_effort {
+      body["reasoning_effort"] = json!(effort.as_str());
+    }
+    body
+  }
+
+  fn stream_parser(&self) -> Box<dyn StreamParser + Send> {
+    Box::new(OpenAIStreamParser { id_prefix: generated_id_prefix("openai"), ..OpenAIStreamParser::default() })
+  }
+}
+
+/// Chat-style APIs take the system prompt as a leading `system` message.
+pub fn with_system_message(system: Option<&str>, mut messages: Vec<Value>) -> Vec<Value> {
+  if let Some(system) = system {
+    messages.insert(0, json!({ "role": "system", "content": system }));
+  }
+  messages
+}
+
+pub fn tool_definitions(tools: &[ToolSpec]) -> Vec<Value> {
+  tools
+    .iter()
+    .map(|tool| {
+      json!({
+        "type": "function",
+        "function": {
+          "name": tool.name,
+          "description": tool.description,
+          "parameters": tool.parameters
+        }
+      })
     })
+    .collect()
+}
+
+/// A plain string, or content parts when files are attached: images as `image_url`, PDFs as
+/// `file` parts and text files inline.
+pub fn user_content(prompt: &str, attachments: &[Attachment]) -> Value {
+  if attachments.is_empty() {
+    return json!(prompt);
+  }
+  let mut parts = vec![json!({ "type": "text", "text": prompt })];
+  for attachment in attachments {
+    let part = match attachment.kind() {
+      Some(AttachmentKind::Image) => json!({ "type": "image_url", "image_url": { "url": attachment.data_url() } }),
+      Some(AttachmentKind::Pdf) => json!({
+        "type": "file",
+        "file": { "filename": attachment.name, "file_data": attachment.data_url() }
+      }),
+      Some(AttachmentKind::Text) => match attachment.text_part() {
+        Some(text) => json!({ "type": "text", "text": text }),
+        None => continue,
+      },
+      None => continue,
+    };
+    parts.push(part);
+  }
+  Value::Array(parts)
+}
+
+/// Replays stored history as Chat Completions messages; each tool result becomes a `tool` message.
+/// Tool messages carry text only, so files returned by tools follow them in a user message.
+pub fn build_messages(history: &[Value]) -> Vec<Value> {
+  let mut messages = Vec::new();
+  let mut tool_files: Vec<Attachment> = Vec::new();
+
+  for item in history {
+    let msg_type = item.get("type").and_then(Value::as_str);
+    if msg_type != Some("user") {
+      flush_tool_files(&mut tool_files, &mut messages);
+    }
+    match msg_type {
+      Some("user_prompt") => {
+        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
+          messages.push(json!({ "role": "user", "content": user_content(prompt, &attachments::from_entry(item)) }));
+        }
+      }
+      Some("assistant") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          let mut text = String::new();
+          let mut tool_calls = Vec::new();
+          for content in contents {
+            match content.get("type").and_then(Value::as_str) {
+              Some("text") => {
+                if let Some(part) = content.get("text").and_then(Value::as_str) {
+                  text.push_str(part);
+                }
+              }
+              Some("tool_use") => {
+                let id = content.get("id").and_then(Value::as_str).unwrap_or_default();
+                let name = content.get("name").and_then(Value::as_str).unwrap_or_default();
+                let input = content.get("input").cloned().unwrap_or(Value::Null);
+                tool_calls.push(json!({
+                  "id": id,
+                  "type": "function",
+                  "function": {
+                    "name": name,
+                    "arguments": stringify_value(&input)
+                  }
+                }));
+              }
+              _ => {}
+            }
+          }
+          if !tool_calls.is_empty() || !text.trim().is_empty() {
+            let mut message = json!({ "role": "assistant", "content": text });
+            if !tool_calls.is_empty() {
+              message["tool_calls"] = Value::Array(tool_calls);
+            }
+            messages.push(message);
+          }
+        }
+      }
+      Some("user") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          for content in contents {
+            if content.get("type").and_then(Value::as_str) == Some("tool_result") {
+              let id = content.get("tool_use_id").and_then(Value::as_str).unwrap_or_default();
+              let payload = content.get("content").cloned().unwrap_or(Value::String(String::new()));
+              messages.push(json!({
+                "role": "tool",
+                "tool_call_id": id,
+                "content": stringify_value(&payload)
+              }));
+              tool_files.extend(attachments::from_entry(content));
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  flush_tool_files(&mut tool_files, &mut messages);
+
+  messages
+}
+
+fn flush_tool_files(files: &mut Vec<Attachment>, messages: &mut Vec<Value>) {
+  if !files.is_empty() {
+    messages.push(json!({ "role": "user", "content": user_content(attachments::TOOL_FILES_PROMPT, files) }));
+    files.clear();
+  }
+}
+
+#[derive(Default)]
+struct ToolCallBuilder {
+  id: Option<String>,
+  name: Option<String>,
+  arguments: String,
+}
+
+#[derive(Default)]
+pub struct OpenAIStreamParser {
+  decoder: SseDecoder,
+  text: String,
+  tool_calls: Vec<ToolCallBuilder>,
+  started: bool,
+  stopped: bool,
+  /// Used for tool calls that arrive without an id, which some OpenAI-compatible servers do.
+  id_prefix: String,
+  usage: Usage,
+  truncated: bool,
+}
+
+impl OpenAIStreamParser {
+  fn stop(&mut self, output: &mut Vec<StreamEvent>) {
+    if self.started && !self.stopped {
+      self.stopped = true;
+      output.push(StreamEvent::ContentStop);
+    }
+  }
+
+  fn handle(&mut self, payload: &Value, output: &mut Vec<StreamEvent>) {
+    if let Some(delta) = payload.pointer("/choices/0/delta") {
+      if let Some(content) = delta.get("content").and_then(Value::as_str) {
+        if !self.started {
+          self.started = true;
+          output.push(StreamEvent::ContentStart);
+        }
+        self.text.push_str(content);
+        output.push(StreamEvent::TextDelta(content.to_string()));
+      }
+      if let Some(calls) = delta.get("tool_calls").and_then(Value::as_array) {
+        for call in calls {
+          let index = call.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
+          if self.tool_calls.len() <= index {
+            self.tool_calls.resize_with(index + 1, ToolCallBuilder::default);
+          }
+          let entry = &mut self.tool_calls[index];
+          if let Some(id) = call.get("id").and_then(Value::as_str) {
+            entry.id = Some(id.to_string());
+          }
+          if let Some(name) = call.pointer("/function/name").and_then(Value::as_str) {
+            entry.name = Some(name.to_string());
+          }
+          if let Some(args) = call.pointer("/function/arguments").and_then(Value::as_str) {
+            entry.arguments.push_str(args);
+          }
+        }
+      }
+    }
+    if let Some(reason) = payload.pointer("/choices/0/finish_reason").filter(|reason| !reason.is_null()) {
+      self.truncated = reason == "length";
+      self.stop(output);
+    }
+    // Sent in a final chunk with no choices when `stream_options.include_usage` is set.
+    if let Some(usage) = payload.get("usage").filter(|usage| usage.is_object()) {
+      let cached = token_count(usage, "/prompt_tokens_details/cached_tokens");
+      self.usage = Usage {
+        input_tokens: token_count(usage, "/prompt_tokens").saturating_sub(cached),
+        output_tokens: token_count(usage, "/completion_tokens"),
+        cache_read_tokens: cached,
+        cache_write_tokens: 0,
+      };
+    }
+  }
+}
+
+impl StreamParser for OpenAIStreamParser {
+  fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, StreamError> {
+    let mut output = Vec::new();
+    for event in self.decoder.push(chunk) {
+      if event.data == "[DONE]" {
+        self.stop(&mut output);
+        continue;
+      }
+      let payload: Value = serde_json::from_str(&event.data)?;
+      if let Some(message) = payload.pointer("/error/message").and_then(Value::as_str) {
+        let message = format!("OpenAI stream error: {}", message);
+        return Err(match payload.pointer("/error/type").and_then(Value::as_str) {
+          Some("server_error") => StreamError::transient(message),
+          _ => StreamError::fatal(message),
+        });
+      }
+      self.handle(&payload, &mut output);
+    }
+    Ok(output)
+  }
+
+  fn finish(self: Box<Self>) -> ModelTurn {
+    let mut turn = ModelTurn { usage: self.usage, truncated: self.truncated, ..ModelTurn::default() };
+    for (idx, builder) in self.tool_calls.into_iter().enumerate() {
+      turn.tool_calls.push(ToolCall {
+        id: builder.id.unwrap_or_else(|| format!("{}-{}", self.id_prefix, idx)),
+        name: builder.name.unwrap_or_else(|| "UnknownTool".into()),
+        input: parse_tool_input(&builder.arguments, None),
+      });
+    }
+    if !self.text.trim().is_empty() {
+      turn.content_blocks.push(json!({ "type": "text", "text": self.text }));
+    }
+    turn.content_blocks.extend(turn.tool_calls.iter().map(tool_use_block));
+    turn
   }
 }
 
@@ -19,11 +321,143 @@ mod tests {
 
   #[test]
   fn build_request_contains_input() {
-    let adapter = OpenAIAdapter;
-    let value = adapter.build_request(&ChatRequest {
-      model: "gpt-test".into(),
-      prompt: "hi".into(),
+    let history = vec![json!({ "type": "user_prompt", "prompt": "hi" })];
+    let value = OpenAIAdapter.build_request(&ChatRequest {
+      model: "gpt-test",
+      history: &history,
+      tools: &[],
+      system: Some("Be brief."),
+      params: &GenerationParams::default(),
     });
-    assert_eq!(value["messages"][0]["role"], "user");
+    assert_eq!(value["messages"][0], json!({ "role": "system", "content": "Be brief." }));
+    assert_eq!(value["messages"][1]["role"], "user");
+    assert!(value.get("max_tokens").is_none());
+    assert!(value.get("tools").is_none() && value.get("tool_choice").is_none());
+  }
+
+  #[test]
+  fn build_request_names_max_tokens_per_model_family() {
+    let params = GenerationParams {
+      max_tokens: Some(1000),
+      stop: vec!["END".into()],
+      ..GenerationParams::default()
+    };
+    let request = |model| {
+      OpenAIAdapter.build_request(&ChatRequest { model, history: &[], tools: &[], system: None, params: &params })
+    };
+    assert_eq!(request("gpt-4o")["max_tokens"], 1000);
+    assert_eq!(request("gpt-4o")["stop"], json!(["END"]));
+    assert_eq!(request("o4-mini")["max_completion_tokens"], 1000);
+    assert!(request("o4-mini").get("max_tokens").is_none());
+
+    let many = GenerationParams { stop: vec!["a".into(); 5], ..GenerationParams::default() };
+    assert!(OpenAIAdapter.validate_params("gpt-4o", &many).is_err());
+  }
+
+  #[test]
+  fn user_content_adds_attachment_parts() {
+    let attachments = vec![
+      Attachment { name: "a.png".into(), media_type: "image/png".into(), data: "iVBORw0KGgo=".into() },
+      Attachment { name: "a.pdf".into(), media_type: "application/pdf".into(), data: "JVBERi0=".into() },
+      Attachment { name: "a.txt".into(), media_type: "text/plain".into(), data: "aGk=".into() },
+    ];
+    assert_eq!(user_content("hi", &[]), json!("hi"));
+    assert_eq!(
+      user_content("Describe.", &attachments),
+      json!([
+        { "type": "text", "text": "Describe." },
+        { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+        { "type": "file", "file": { "filename": "a.pdf", "file_data": "data:application/pdf;base64,JVBERi0=" } },
+        { "type": "text", "text": "<file name=\"a.txt\">\nhi\n</file>" }
+      ])
+    );
+  }
+
+  #[test]
+  fn build_messages_maps_tool_use_and_results() {
+    let history = vec![
+      json!({ "type": "user_prompt", "prompt": "hi" }),
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "text", "text": "Reading." },
+        { "type": "tool_use", "id": "call_1", "name": "Read", "input": { "file_path": "a.txt" } }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": "call_1", "content": "ok", "is_error": false }
+      ] } }),
+      json!({ "type": "marker", "kind": "interrupted", "text": "Interrupted by user." }),
+    ];
+    let messages = build_messages(&history);
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[1]["tool_calls"][0]["function"]["arguments"], "{\"file_path\":\"a.txt\"}");
+    assert_eq!(messages[2], json!({ "role": "tool", "tool_call_id": "call_1", "content": "ok" }));
+  }
+
+  #[test]
+  fn build_messages_sends_tool_files_after_all_tool_messages() {
+    let result = |id: &str, attachments: Value| {
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": id, "content": "Read", "is_error": false, "attachments": attachments }
+      ] } })
+    };
+    let history = vec![
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "tool_use", "id": "call_1", "name": "Read", "input": { "file_path": "a.png" } },
+        { "type": "tool_use", "id": "call_2", "name": "Read", "input": { "file_path": "b.txt" } }
+      ] } }),
+      result("call_1", json!([{ "name": "a.png", "mediaType": "image/png", "data": "iVBORw0KGgo=" }])),
+      result("call_2", json!([])),
+      json!({ "type": "assistant", "message": { "content": [{ "type": "text", "text": "A logo." }] } }),
+    ];
+    let messages = build_messages(&history);
+    let roles: Vec<&str> = messages.iter().map(|message| message["role"].as_str().unwrap()).collect();
+    assert_eq!(roles, ["assistant", "tool", "tool", "user", "assistant"]);
+    assert_eq!(messages[3]["content"][1]["image_url"]["url"], "data:image/png;base64,iVBORw0KGgo=");
+  }
+
+  #[test]
+  fn parser_collects_text_and_tool_calls() {
+    let stream = concat!(
+      "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+      "data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+      "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"Bash\",\"arguments\":\"{\\\"command\\\":\"}}]}}]}\n\n",
+      "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"ls\\\"}\"}}]}}]}\n\n",
+      "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+      "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":300,\"completion_tokens\":25,\"prompt_tokens_details\":{\"cached_tokens\":256}}}\n\n",
+      "data: [DONE]\n\n",
+    );
+    let mut parser = OpenAIAdapter.stream_parser();
+    let events = parser.push(stream.as_bytes()).unwrap();
+    assert_eq!(
+      events,
+      vec![
+        StreamEvent::ContentStart,
+        StreamEvent::TextDelta("Hel".into()),
+        StreamEvent::TextDelta("lo".into()),
+        StreamEvent::ContentStop,
+      ]
+    );
+    let turn = parser.finish();
+    assert_eq!(turn.content_blocks.len(), 2);
+    assert_eq!(turn.content_blocks[0]["text"], "Hello");
+    assert_eq!(
+      turn.usage,
+      Usage { input_tokens: 44, output_tokens: 25, cache_read_tokens: 256, cache_write_tokens: 0 }
+    );
+    assert_eq!(
+      turn.tool_calls,
+      vec![ToolCall { id: "call_1".into(), name: "Bash".into(), input: json!({ "command": "ls" }) }]
+    );
+  }
+
+  #[test]
+  fn tool_calls_without_an_id_get_a_generated_one() {
+    let stream = concat!(
+      "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"name\":\"Bash\",\"arguments\":\"{}\"}}]}}]}\n\n",
+      "data: [DONE]\n\n",
+    );
+    let mut parser = OpenAIAdapter.stream_parser();
+    parser.push(stream.as_bytes()).unwrap();
+    let id = &parser.finish().tool_calls[0].id;
+    assert!(id.starts_with("openai-") && id.ends_with("-0"), "{}", id);
   }
 }
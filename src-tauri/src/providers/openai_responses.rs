@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+
+use super::adapter::{
+  parse_tool_input, stringify_value, token_count, tool_use_block, ChatRequest, ModelTurn, ProviderAdapter,
+  StreamError, StreamEvent, StreamParser, ToolCall, Usage,
+};
+use super::attachments::{self, Attachment, AttachmentKind};
+use super::params::{GenerationParams, ParamKeys};
+use super::sse::{SseDecoder, SseEvent};
+use crate::tools::ToolSpec;
+
+/// OpenAI through `/v1/responses`, required for newer models and reasoning summaries.
+pub struct OpenAIResponsesAdapter;
+
+impl ProviderAdapter for OpenAIResponsesAdapter {
+  fn name(&self) -> &'static str {
+    "OpenAI"
+  }
+
+  fn default_url(&self) -> &'static str {
+    "https://api.openai.com/v1/responses"
+  }
+
+  fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+    request.bearer_auth(api_key)
+  }
+
+  fn validate_params(&self, model: &str, params: &GenerationParams) -> Result<(), String> {
+    if !params.stop.is_empty() {
+      return Err("OpenAI Responses API 不支持 stop 序列。".into());
+    }
+    check_reasoning_params(model, params)?;
+    params.check(2.0, 0)
+  }
+
+  fn build_request(&self, req: &ChatRequest) -> Value {
+    let mut body = json!({
+      "model": req.model,
+      "input": build_input(req.history),
+      "stream": true,
+      "tools": tool_definitions(req.tools)
+    });
+    if let Some(system) = req.system {
+      body["instructions"] = json!(system);
+    }
+    if let Some(body) = body.as_object_mut() {
+      req.params.write_into(body, PARAM_KEYS);
+    }
+    if is_reasoning_model(req.model) {
+      body["reasoning"] = json!({ "summary": "auto" });
+      if let Some(effort) = req.params.reasoning_effort {
+        body["reasoning"]["effort"] = json!(effort.as_str());
+      }
+    }
+    body
+  }
+
+  fn stream_parser(&self) -> Box<dyn StreamParser + Send> {
+    Box::new(ResponsesStreamParser::default())
+  }
+}
+
+const PARAM_KEYS: ParamKeys = ParamKeys {
+  max_tokens: Some("max_output_tokens"),
+  temperature: "temperature",
+  top_p: "top_p",
+  stop: None,
+};
+
+/// Non-reasoning models reject the `reasoning` parameter outright.
+pub fn is_reasoning_model(model: &str) -> bool {
+  let model = model.trim().to_ascii_lowercase();
+  model.starts_with("gpt-5") || ["o1", "o3", "o4"].iter().any(|prefix| model.starts_with(prefix))
+}
+
+/// Responses API content parts: `input_text`, `input_image` and `input_file`.
+fn user_content(prompt: &str, attachments: &[Attachment]) -> Value {
+  if attachments.is_empty() {
+    return json!(prompt);
+  }
+  let mut parts = vec![json!({ "type": "input_text", "text": prompt })];
+  for attachment in attachments {
+    let part = match attachment.kind() {
+      Some(AttachmentKind::Image) => json!({ "type": "input_image", "image_url": attachment.data_url() }),
+      Some(AttachmentKind::Pdf) => json!({
+        "type": "input_file",
+        "filename": attachment.name,
+        "file_data": attachment.data_url()
+      }),
+      Some(AttachmentKind::Text) => match attachment.text_part() {
+        Some(text) => json!({ "type": "input_text", "text": text }),
+        None => continue,
+      },
+      None => continue,
+    };
+    parts.push(part);
+  }
+  Value::Array(parts)
+}
+
+/// Reasoning models take an effort but no sampling parameters; other models the reverse.
+pub fn check_reasoning_params(model: &str, params: &GenerationParams) -> Result<(), String> {
+  if params.thinking_budget.is_some() {
+    return Err("OpenAI 不支持 thinking budget，请改用 reasoning effort。".into());
+  }
+  if is_reasoning_model(model) {
+    if params.temperature.is_some() || params.top_p.is_some() {
+      return Err(format!("{} 是推理模型，不支持 temperature / top_p。", model));
+    }
+  } else if params.reasoning_effort.is_some() {
+    return Err(format!("{} 不是推理模型，不支持 reasoning effort。", model));
+  }
+  Ok(())
+}
+
+pub fn tool_definitions(tools: &[ToolSpec]) -> Vec<Value> {
+  tools
+    .iter()
+    .map(|tool| {
+      json!({
+        "type": "function",
+        "name": tool.name,
+        "description": tool.description,
+        "parameters": tool.parameters
+      })
+    })
+    .collect()
+}
+
+/// Replays stored history as Responses input items: tool uses become `function_call` items and
+/// tool results `function_call_output` items, linked by `call_id`.
+pub fn build_input(history: &[Value]) -> Vec<Value> {
+  let mut items = Vec::new();
+  let mut tool_files: Vec<Attachment> = Vec::new();
+
+  for item in history {
+    let msg_type = item.get("type").and_then(Value::as_str);
+    if msg_type != Some("user") {
+      flush_tool_files(&mut tool_files, &mut items);
+    }
+    match msg_type {
+      Some("user_prompt") => {
+        if let Some(prompt) = item.get("prompt").and_then(Value::as_str) {
+          items.push(json!({ "role": "user", "content": user_content(prompt, &attachments::from_entry(item)) }));
+        }
+      }
+      Some("assistant") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          let mut text = String::new();
+          let mut calls = Vec::new();
+          for content in contents {
+            match content.get("type").and_then(Value::as_str) {
+              Some("text") => {
+                if let Some(part) = content.get("text").and_then(Value::as_str) {
+                  text.push_str(part);
+                }
+              }
+              Some("tool_use") => {
+                let input = content.get("input").cloned().unwrap_or(Value::Null);
+                calls.push(json!({
+                  "type": "function_call",
+                  "call_id": content.get("id").and_then(Value::as_str).unwrap_or_default(),
+                  "name": content.get("name").and_then(Value::as_str).unwrap_or_default(),
+                  "arguments": stringify_value(&input)
+                }));
+              }
+              _ => {}
+            }
+          }
+          if !text.trim().is_empty() {
+            items.push(json!({ "role": "assistant", "content": text }));
+          }
+          items.extend(calls);
+        }
+      }
+      Some("user") => {
+        if let Some(contents) = item.pointer("/message/content").and_then(Value::as_array) {
+          for content in contents {
+            if content.get("type").and_then(Value::as_str) == Some("tool_result") {
+              let payload = content.get("content").cloned().unwrap_or(Value::String(String::new()));
+              items.push(json!({
+                "type": "function_call_output",
+                "call_id": content.get("tool_use_id").and_then(Value::as_str).unwrap_or_default(),
+                "output": stringify_value(&payload)
+              }));
+              tool_files.extend(attachments::from_entry(content));
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  flush_tool_files(&mut tool_files, &mut items);
+
+  items
+}
+
+/// Function call outputs carry text only, so files returned by tools follow them as user input.
+fn flush_tool_files(files: &mut Vec<Attachment>, items: &mut Vec<Value>) {
+  if !files.is_empty() {
+    items.push(json!({ "role": "user", "content": user_content(attachments::TOOL_FILES_PROMPT, files) }));
+    files.clear();
+  }
+}
+
+enum OutputItem {
+  Message { text: String, started: bool },
+  Reasoning { summary: String, started: bool },
+  FunctionCall { call_id: String, name: String, arguments: String },
+}
+
+#[derive(Default)]
+pub struct ResponsesStreamParser {
+  decoder: SseDecoder,
+  items: BTreeMap<u64, OutputItem>,
+  usage: Usage,
+  truncated: bool,
+}
+
+impl ResponsesStreamParser {
+  fn handle(&mut self, event: &SseEvent, output: &mut Vec<StreamEvent>) -> Result<(), StreamError> {
+    let payload: Value = serde_json::from_str(&event.data)?;
+    let kind = event
+      .event
+      .as_deref()
+      .or_else(|| payload.get("type").and_then(Value::as_str))
+      .unwrap_or_default();
+    let index = payload.get("output_index").and_then(Value::as_u64).unwrap_or(0);
+    let delta = payload.get("delta").and_then(Value::as_str).unwrap_or("");
+
+    match kind {
+      "response.output_item.added" => {
+        let item = payload.get("item").cloned().unwrap_or(Value::Null);
+        let entry = match item.get("type").and_then(Value::as_str) {
+          Some("message") => OutputItem::Message { text: String::new(), started: false },
+          Some("reasoning") => OutputItem::Reasoning { summary: String::new(), started: false },
+          Some("function_call") => OutputItem::FunctionCall {
+            call_id: item.get("call_id").and_then(Value::as_str).unwrap_or_default().to_string(),
+            name: item.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+            arguments: item.get("arguments").and_then(Value::as_str).unwrap_or_default().to_string(),
+          },
+          _ => return Ok(()),
+        };
+        self.items.insert(index, entry);
+      }
+      "response.output_text.delta" => {
+        if let Some(OutputItem::Message { text, started }) = self.items.get_mut(&index) {
+          if !*started {
+            *started = true;
+            output.push(StreamEvent::ContentStart);
+          }
+          text.push_str(delta);
+          output.push(StreamEvent::TextDelta(delta.to_string()));
+        }
+      }
+      "response.reasoning_summary_text.delta" => {
+        if let Some(OutputItem::Reasoning { summary, started }) = self.items.get_mut(&index) {
+          if !*started {
+            *started = true;
+            output.push(StreamEvent::ContentStart);
+          }
+          summary.push_str(delta);
+          output.push(StreamEvent::ThinkingDelta(delta.to_string()));
+        }
+      }
+      "response.reasoning_summary_part.done" => {
+        // Separate summary parts the way the API presents them as paragraphs.
+        if let Some(OutputItem::Reasoning { summary, .. }) = self.items.get_mut(&index) {
+          summary.push_str("\n\n");
+        }
+      }
+      "response.function_call_arguments.delta" => {
+        if let Some(OutputItem::FunctionCall { arguments, .. }) = self.items.get_mut(&index) {
+          arguments.push_str(delta);
+        }
+      }
+      "response.function_call_arguments.done" => {
+        if let (Some(OutputItem::FunctionCall { arguments, .. }), Some(done)) = (
+          self.items.get_mut(&index),
+          payload.get("arguments").and_then(Value::as_str),
+        ) {
+          *arguments = done.to_string();
+        }
+      }
+      "response.output_item.done" => match self.items.get(&index) {
+        Some(OutputItem::Message { started: true, .. }) | Some(OutputItem::Reasoning { started: true, .. }) => {
+          output.push(StreamEvent::ContentStop);
+        }
+        _ => {}
+      },
+      "response.completed" | "response.incomplete" => {
+        let reason = payload.pointer("/response/incomplete_details/reason").and_then(Value::as_str);
+        self.truncated = reason == Some("max_output_tokens");
+        if let Some(usage) = payload.pointer("/response/usage").filter(|usage| usage.is_object()) {
+          let cached = token_count(usage, "/input_tokens_details/cached_tokens");
+          self.usage = Usage {
+            input_tokens: token_count(usage, "/input_tokens").saturating_sub(cached),
+            output_tokens: token_count(usage, "/output_tokens"),
+            cache_read_tokens: cached,
+            cache_write_tokens: 0,
+          };
+        }
+      }
+      "response.failed" | "error" => {
+        let message = payload
+          .pointer("/response/error/message")
+          .or_else(|| payload.pointer("/error/message"))
+          .or_else(|| payload.get("message"))
+          .and_then(Value::as_str)
+          .unwrap_or("unknown stream error");
+        let message = format!("OpenAI stream error: {}", message);
+        let code = payload
+          .pointer("/response/error/code")
+          .or_else(|| payload.get("code"))
+          .and_then(Value::as_str);
+        return Err(match code {
+          Some("server_error" | "rate_limit_exceeded") => StreamError::transient(message),
+          _ => StreamError::fatal(message),
+        });
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+}
+
+impl StreamParser for ResponsesStreamParser {
+  fn push(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>, StreamError> {
+    let mut output = Vec::new();
+    for event in self.decoder.push(chunk) {
+      if event.data == "[DONE]" {
+        continue;
+      }
+      self.handle(&event, &mut output)?;
+    }
+    Ok(output)
+  }
+
+  fn finish(self: Box<Self>) -> ModelTurn {
+    let mut turn = ModelTurn { usage: self.usage, truncated: self.truncated, ..ModelTurn::default() };
+    for item in self.items.into_values() {
+      match item {
+        OutputItem::Reasoning { summary, .. } => {
+          let summary = summary.trim();
+          if !summary.is_empty() {
+            turn.content_blocks.push(json!({ "type": "thinking", "thinking": summary }));
+          }
+        }
+        OutputItem::Message { text, .. } => {
+          if !text.trim().is_empty() {
+            turn.content_blocks.push(json!({ "type": "text", "text": text }));
+          }
+        }
+        OutputItem::FunctionCall { call_id, name, arguments } => {
+          let call = ToolCall { id: call_id, name, input: parse_tool_input(&arguments, None) };
+          turn.content_blocks.push(tool_use_block(&call));
+          turn.tool_calls.push(call);
+        }
+      }
+    }
+    turn
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::providers::params::ReasoningEffort;
+
+  #[test]
+  fn build_request_asks_reasoning_models_for_summaries() {
+    let history = vec![json!({ "type": "user_prompt", "prompt": "hi" })];
+    let request = |model| {
+      OpenAIResponsesAdapter.build_request(&ChatRequest {
+        model,
+        history: &history,
+        tools: &[],
+        system: None,
+        params: &GenerationParams::default(),
+      })
+    };
+    assert_eq!(request("o4-mini")["reasoning"], json!({ "summary": "auto" }));
+    assert!(request("gpt-4.1").get("reasoning").is_none());
+    assert_eq!(request("gpt-4.1")["input"][0], json!({ "role": "user", "content": "hi" }));
+  }
+
+  #[test]
+  fn generation_params_follow_the_model_family() {
+    let params = GenerationParams {
+      max_tokens: Some(2048),
+      reasoning_effort: Some(ReasoningEffort::High),
+      ..GenerationParams::default()
+    };
+    let body = OpenAIResponsesAdapter.build_request(&ChatRequest {
+      model: "gpt-5",
+      history: &[],
+      tools: &[],
+      system: Some("Be brief."),
+      params: &params,
+    });
+    assert_eq!(body["max_output_tokens"], 2048);
+    assert_eq!(body["instructions"], "Be brief.");
+    assert_eq!(body["reasoning"], json!({ "summary": "auto", "effort": "high" }));
+
+    assert!(OpenAIResponsesAdapter.validate_params("gpt-5", &params).is_ok());
+    assert!(OpenAIResponsesAdapter.validate_params("gpt-4.1", &params).is_err());
+    let sampled = GenerationParams { temperature: Some(0.3), ..GenerationParams::default() };
+    assert!(OpenAIResponsesAdapter.validate_params("o3", &sampled).is_err());
+    let stop = GenerationParams { stop: vec!["x".into()], ..GenerationParams::default() };
+    assert!(OpenAIResponsesAdapter.validate_params("gpt-4.1", &stop).is_err());
+  }
+
+  #[test]
+  fn build_input_links_calls_and_outputs() {
+    let history = vec![
+      json!({ "type": "user_prompt", "prompt": "list" }),
+      json!({ "type": "assistant", "message": { "content": [
+        { "type": "thinking", "thinking": "Need ls." },
+        { "type": "text", "text": "Listing." },
+        { "type": "tool_use", "id": "call_1", "name": "Bash", "input": { "command": "ls" } }
+      ] } }),
+      json!({ "type": "user", "message": { "content": [
+        { "type": "tool_result", "tool_use_id": "call_1", "content": "a.txt", "is_error": false }
+      ] } }),
+      json!({ "type": "marker", "kind": "interrupted", "text": "Interrupted by user." }),
+    ];
+    let items = build_input(&history);
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[1], json!({ "role": "assistant", "content": "Listing." }));
+    assert_eq!(
+      items[2],
+      json!({ "type": "function_call", "call_id": "call_1", "name": "Bash", "arguments": "{\"command\":\"ls\"}" })
+    );
+    assert_eq!(items[3], json!({ "type": "function_call_output", "call_id": "call_1", "output": "a.txt" }));
+  }
+
+  #[test]
+  fn parser_handles_reasoning_text_and_function_calls() {
+    let stream = concat!(
+      "event: response.output_item.added\ndata: {\"output_index\":0,\"item\":{\"type\":\"reasoning\",\"id\":\"rs_1\"}}\n\n",
+      "event: response.reasoning_summary_text.delta\ndata: {\"output_index\":0,\"delta\":\"Checking files.\"}\n\n",
+      "event: response.output_item.done\ndata: {\"output_index\":0,\"item\":{\"type\":\"reasoning\"}}\n\n",
+      "event: response.output_item.added\ndata: {\"output_index\":1,\"item\":{\"type\":\"message\",\"id\":\"msg_1\"}}\n\n",
+      "event: response.output_text.delta\ndata: {\"output_index\":1,\"delta\":\"On it.\"}\n\n",
+      "event: response.output_item.done\ndata: {\"output_index\":1,\"item\":{\"type\":\"message\"}}\n\n",
+      "event: response.output_item.added\ndata: {\"output_index\":2,\"item\":{\"type\":\"function_call\",\"call_id\":\"call_9\",\"name\":\"Read\",\"arguments\":\"\"}}\n\n",
+      "event: response.function_call_arguments.delta\ndata: {\"output_index\":2,\"delta\":\"{\\\"file_path\\\":\"}\n\n",
+      "event: response.function_call_arguments.delta\ndata: {\"output_index\":2,\"delta\":\"\\\"a.txt\\\"}\"}\n\n",
+      "event: response.output_item.done\ndata: {\"output_index\":2,\"item\":{\"type\":\"function_call\"}}\n\n",
+      "event: response.completed\ndata: {\"response\":{\"status\":\"completed\",\"usage\":{\"input_tokens\":50,\"output_tokens\":80,\"input_tokens_details\":{\"cached_tokens\":10}}}}\n\n",
+    );
+    let mut parser = OpenAIResponsesAdapter.stream_parser();
+    let events = parser.push(stream.as_bytes()).unwrap();
+    assert_eq!(
+      events,
+      vec![
+        StreamEvent::ContentStart,
+        StreamEvent::ThinkingDelta("Checking files.".into()),
+        StreamEvent::ContentStop,
+        StreamEvent::ContentStart,
+        StreamEvent::TextDelta("On it.".into()),
+        StreamEvent::ContentStop,
+      ]
+    );
+    let turn = parser.finish();
+    assert_eq!(turn.content_blocks[0], json!({ "type": "thinking", "thinking": "Checking files." }));
+    assert_eq!(turn.content_blocks[1], json!({ "type": "text", "text": "On it." }));
+    assert_eq!(turn.usage.input_tokens, 40);
+    assert_eq!(turn.usage.output_tokens, 80);
+    assert_eq!(turn.usage.cache_read_tokens, 10);
+    assert_eq!(
+      turn.tool_calls,
+      vec![ToolCall { id: "call_9".into(), name: "Read".into(), input: json!({ "file_path": "a.txt" }) }]
+    );
+  }
+
+  #[test]
+  fn parser_surfaces_failed_responses() {
+    let mut parser = OpenAIResponsesAdapter.stream_parser();
+    let error = parser
+      .push(b"event: response.failed\ndata: {\"response\":{\"error\":{\"message\":\"Rate limited\"}}}\n\n")
+      .unwrap_err();
+    assert!(error.message.contains("Rate limited"));
+    assert!(!error.retryable);
+  }
+
+  #[test]
+  fn parser_flags_incomplete_responses() {
+    let mut parser = OpenAIResponsesAdapter.stream_parser();
+    parser
+      .push(b"event: response.incomplete\ndata: {\"response\":{\"incomplete_details\":{\"reason\":\"max_output_tokens\"}}}\n\n")
+      .unwrap();
+    assert!(parser.finish().truncated);
+  }
+}
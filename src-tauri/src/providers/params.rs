@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// How much hidden reasoning a reasoning model may spend before answering.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+  Minimal,
+  Low,
+  Medium,
+  High,
+}
+
+impl ReasoningEffort {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ReasoningEffort::Minimal => "minimal",
+      ReasoningEffort::Low => "low",
+      ReasoningEffort::Medium => "medium",
+      ReasoningEffort::High => "high",
+    }
+  }
+}
+
+/// Per-session sampling settings. Unset fields fall back to the provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GenerationParams {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_tokens: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub temperature: Option<f64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub top_p: Option<f64>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub stop: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reasoning_effort: Option<ReasoningEffort>,
+  /// Tokens the model may spend thinking before it answers (Anthropic extended thinking, Gemini).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub thinking_budget: Option<u32>,
+}
+
+impl GenerationParams {
+  /// Range checks shared by every provider; adapters add their own limits on top.
+  pub fn check(&self, max_temperature: f64, max_stop: usize) -> Result<(), String> {
+    if self.max_tokens == Some(0) {
+      return Err("max_tokens 必须大于 0。".into());
+    }
+    if let Some(temperature) = self.temperature {
+      if !(0.0..=max_temperature).contains(&temperature) {
+        return Err(format!("temperature 必须在 0 到 {} 之间。", max_temperature));
+      }
+    }
+    if let Some(top_p) = self.top_p {
+      if !(top_p > 0.0 && top_p <= 1.0) {
+        return Err("top_p 必须在 0 到 1 之间（不含 0）。".into());
+      }
+    }
+    if self.stop.iter().any(String::is_empty) {
+      return Err("stop 序列不能为空字符串。".into());
+    }
+    if self.stop.len() > max_stop {
+      return Err(format!("最多只能设置 {} 个 stop 序列。", max_stop));
+    }
+    Ok(())
+  }
+
+  /// Copies the set fields into `target` under the provider's key names.
+  pub fn write_into(&self, target: &mut Map<String, Value>, keys: ParamKeys) {
+    if let (Some(key), Some(max_tokens)) = (keys.max_tokens, self.max_tokens) {
+      target.insert(key.into(), max_tokens.into());
+    }
+    if let Some(temperature) = self.temperature {
+      target.insert(keys.temperature.into(), temperature.into());
+    }
+    if let Some(top_p) = self.top_p {
+      target.insert(keys.top_p.into(), top_p.into());
+    }
+    if let Some(key) = keys.stop.filter(|_| !self.stop.is_empty()) {
+      target.insert(key.into(), self.stop.clone().into());
+    }
+  }
+}
+
+/// A provider's names for the common parameters; `None` when it is set elsewhere or unsupported.
+#[derive(Debug, Clone, Copy)]
+pub struct ParamKeys {
+  pub max_tokens: Option<&'static str>,
+  pub temperature: &'static str,
+  pub top_p: &'static str,
+  pub stop: Option<&'static str>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn deserializes_camel_case_with_defaults() {
+    let params: GenerationParams =
+      serde_json::from_str(r#"{"maxTokens":4096,"reasoningEffort":"high","stop":["END"]}"#).unwrap();
+    assert_eq!(params.max_tokens, Some(4096));
+    assert_eq!(params.reasoning_effort, Some(ReasoningEffort::High));
+    assert_eq!(params.temperature, None);
+    assert_eq!(serde_json::to_string(&GenerationParams::default()).unwrap(), "{}");
+  }
+
+  #[test]
+  fn check_rejects_out_of_range_values() {
+    let ok = GenerationParams { temperature: Some(1.5), top_p: Some(1.0), ..GenerationParams::default() };
+    assert!(ok.check(2.0, 4).is_ok());
+    assert!(ok.check(1.0, 4).is_err());
+    assert!(GenerationParams { max_tokens: Some(0), ..GenerationParams::default() }.check(2.0, 4).is_err());
+    assert!(GenerationParams { top_p: Some(0.0), ..GenerationParams::default() }.check(2.0, 4).is_err());
+    let stops = GenerationParams { stop: vec!["a".into(), "b".into()], ..GenerationParams::default() };
+    assert!(stops.check(2.0, 1).is_err());
+  }
+}
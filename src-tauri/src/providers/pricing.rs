@@ -0,0 +1,106 @@
+use super::adapter::Usage;
+use super::registry::ProviderKind;
+
+/// List prices in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+  pub input: f64,
+  pub output: f64,
+  pub cache_read: f64,
+  pub cache_write: f64,
+}
+
+const fn price(input: f64, output: f64, cache_read: f64, cache_write: f64) -> ModelPrice {
+  ModelPrice { input, output, cache_read, cache_write }
+}
+
+/// Model id prefixes, most specific first so `gpt-4o-mini` is not priced as `gpt-4o`.
+/// Update alongside the providers' published pricing; unknown models are reported as unpriced.
+const ANTHROPIC_PRICES: &[(&str, ModelPrice)] = &[
+  ("claude-opus-4-5", price(5.0, 25.0, 0.5, 6.25)),
+  ("claude-opus-4", price(15.0, 75.0, 1.5, 18.75)),
+  ("claude-sonnet-4", price(3.0, 15.0, 0.3, 3.75)),
+  ("claude-3-7-sonnet", price(3.0, 15.0, 0.3, 3.75)),
+  ("claude-3-5-sonnet", price(3.0, 15.0, 0.3, 3.75)),
+  ("claude-haiku-4-5", price(1.0, 5.0, 0.1, 1.25)),
+  ("claude-3-5-haiku", price(0.8, 4.0, 0.08, 1.0)),
+  ("claude-3-opus", price(15.0, 75.0, 1.5, 18.75)),
+  ("claude-3-haiku", price(0.25, 1.25, 0.03, 0.3)),
+];
+
+const OPENAI_PRICES: &[(&str, ModelPrice)] = &[
+  ("gpt-5-nano", price(0.05, 0.4, 0.005, 0.0)),
+  ("gpt-5-mini", price(0.25, 2.0, 0.025, 0.0)),
+  ("gpt-5", price(1.25, 10.0, 0.125, 0.0)),
+  ("gpt-4.1-nano", price(0.1, 0.4, 0.025, 0.0)),
+  ("gpt-4.1-mini", price(0.4, 1.6, 0.1, 0.0)),
+  ("gpt-4.1", price(2.0, 8.0, 0.5, 0.0)),
+  ("gpt-4o-mini", price(0.15, 0.6, 0.075, 0.0)),
+  ("gpt-4o", price(2.5, 10.0, 1.25, 0.0)),
+  ("o4-mini", price(1.1, 4.4, 0.275, 0.0)),
+  ("o3-mini", price(1.1, 4.4, 0.55, 0.0)),
+  ("o3", price(2.0, 8.0, 0.5, 0.0)),
+  ("o1-mini", price(1.1, 4.4, 0.55, 0.0)),
+  ("o1", price(15.0, 60.0, 7.5, 0.0)),
+];
+
+const GEMINI_PRICES: &[(&str, ModelPrice)] = &[
+  ("gemini-2.5-pro", price(1.25, 10.0, 0.31, 0.0)),
+  ("gemini-2.5-flash-lite", price(0.1, 0.4, 0.025, 0.0)),
+  ("gemini-2.5-flash", price(0.3, 2.5, 0.075, 0.0)),
+  ("gemini-2.0-flash-lite", price(0.075, 0.3, 0.0, 0.0)),
+  ("gemini-2.0-flash", price(0.1, 0.4, 0.025, 0.0)),
+];
+
+pub fn price_for(provider: &ProviderKind, model: &str) -> Option<ModelPrice> {
+  let table = match provider {
+    ProviderKind::Anthropic => ANTHROPIC_PRICES,
+    ProviderKind::OpenAI => OPENAI_PRICES,
+    ProviderKind::Gemini => GEMINI_PRICES,
+    // Local models cost nothing per token.
+    ProviderKind::Ollama => return Some(price(0.0, 0.0, 0.0, 0.0)),
+  };
+  let model = model.trim().trim_start_matches("models/").to_ascii_lowercase();
+  table
+    .iter()
+    .find(|(prefix, _)| model.starts_with(prefix))
+    .map(|(_, price)| *price)
+}
+
+pub fn cost_usd(usage: &Usage, price: &ModelPrice) -> f64 {
+  let per_token = |count: u64, rate: f64| count as f64 * rate / 1_000_000.0;
+  per_token(usage.input_tokens, price.input)
+    + per_token(usage.output_tokens, price.output)
+    + per_token(usage.cache_read_tokens, price.cache_read)
+    + per_token(usage.cache_write_tokens, price.cache_write)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn prefers_the_most_specific_prefix() {
+    let mini = price_for(&ProviderKind::OpenAI, "gpt-4o-mini-2024-07-18").unwrap();
+    assert_eq!(mini.input, 0.15);
+    let full = price_for(&ProviderKind::OpenAI, "gpt-4o").unwrap();
+    assert_eq!(full.input, 2.5);
+    let sonnet = price_for(&ProviderKind::Anthropic, "claude-sonnet-4-5-20250929").unwrap();
+    assert_eq!(sonnet.output, 15.0);
+    assert!(price_for(&ProviderKind::Gemini, "models/gemini-2.5-flash").is_some());
+    assert!(price_for(&ProviderKind::Anthropic, "my-finetune").is_none());
+  }
+
+  #[test]
+  fn cost_counts_every_token_class() {
+    let usage = Usage {
+      input_tokens: 1_000_000,
+      output_tokens: 100_000,
+      cache_read_tokens: 2_000_000,
+      cache_write_tokens: 0,
+    };
+    let price = price_for(&ProviderKind::Anthropic, "claude-sonnet-4-5").unwrap();
+    assert!((cost_usd(&usage, &price) - 5.1).abs() < 1e-9);
+    assert_eq!(cost_usd(&usage, &price_for(&ProviderKind::Ollama, "llama3.1").unwrap()), 0.0);
+  }
+}
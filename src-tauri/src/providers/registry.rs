@@ -1,10 +1,55 @@
 use serde::{Deserialize, Serialize};
 
+use super::adapter::ProviderAdapter;
+use super::anthropic::AnthropicAdapter;
+use super::gemini::GeminiAdapter;
+use super::ollama::OllamaAdapter;
+use super::openai::OpenAIAdapter;
+use super::openai_responses::OpenAIResponsesAdapter;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ProviderKind {
   Anthropic,
   OpenAI,
+  Ollama,
+  Gemini,
+}
+
+/// Which OpenAI endpoint family a session talks to.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenAIApi {
+  #[default]
+  ChatCompletions,
+  Responses,
+}
+
+/// Per-session provider switches that are not part of the request history.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderOptions {
+  #[serde(default)]
+  pub openai_api: OpenAIApi,
+}
+
+impl ProviderKind {
+  pub fn adapter(&self, options: &ProviderOptions) -> Box<dyn ProviderAdapter> {
+    match self {
+      ProviderKind::Anthropic => Box::new(AnthropicAdapter),
+      ProviderKind::OpenAI => match options.openai_api {
+        OpenAIApi::ChatCompletions => Box::new(OpenAIAdapter),
+        OpenAIApi::Responses => Box::new(OpenAIResponsesAdapter),
+      },
+      ProviderKind::Ollama => Box::new(OllamaAdapter),
+      ProviderKind::Gemini => Box::new(GeminiAdapter),
+    }
+  }
+
+  /// Local providers run without credentials.
+  pub fn requires_api_key(&self) -> bool {
+    !matches!(self, ProviderKind::Ollama)
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -52,4 +97,24 @@ mod tests {
     let got = store.get_key("p1").unwrap();
     assert_eq!(got, Some("sk-test".into()));
   }
+
+  #[test]
+  fn provider_kind_uses_lowercase_names() {
+    let kind: ProviderKind = serde_json::from_str("\"ollama\"").unwrap();
+    assert_eq!(kind, ProviderKind::Ollama);
+    assert!(!kind.requires_api_key());
+    assert!(ProviderKind::OpenAI.requires_api_key());
+  }
+
+  #[test]
+  fn openai_api_option_selects_adapter() {
+    let responses = ProviderOptions { openai_api: OpenAIApi::Responses };
+    assert_eq!(ProviderKind::OpenAI.adapter(&responses).default_url(), "https://api.openai.com/v1/responses");
+    assert_eq!(
+      ProviderKind::OpenAI.adapter(&ProviderOptions::default()).default_url(),
+      "https://api.openai.com/v1/chat/completions"
+    );
+    let parsed: ProviderOptions = serde_json::from_str("{\"openaiApi\":\"responses\"}").unwrap();
+    assert_eq!(parsed, responses);
+  }
 }
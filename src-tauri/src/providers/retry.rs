@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+/// How often and how patiently a failed model request is retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+  /// Retries after the first attempt; `0` disables retrying.
+  pub max_retries: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_retries: 4,
+      base_delay: Duration::from_secs(1),
+      max_delay: Duration::from_secs(60),
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Delay before retry number `attempt` (1-based). A server supplied `retry_after` wins over
+  /// the computed backoff and is waited in full; when it is longer than `max_delay` this is
+  /// `None` and the caller gives up, since retrying sooner would only be throttled again.
+  /// Otherwise the delay doubles per attempt with up to 50% jitter so concurrent sessions do
+  /// not retry in lockstep. `jitter` is a sample from `[0, 1)`.
+  pub fn delay(&self, attempt: u32, retry_after: Option<Duration>, jitter: f64) -> Option<Duration> {
+    if let Some(retry_after) = retry_after {
+      return (retry_after <= self.max_delay).then_some(retry_after);
+    }
+    let exponent = attempt.saturating_sub(1).min(16);
+    let backoff = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+    Some(backoff.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0))
+  }
+}
+
+/// Rate limits, timeouts, conflicts, Anthropic's 529 "overloaded" and other server errors.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+  matches!(status.as_u16(), 408 | 409 | 429) || status.is_server_error()
+}
+
+/// Reads `retry-after-ms` (OpenAI) or `retry-after` in seconds; HTTP-date values are ignored.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+  let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+  if let Some(ms) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+    return duration_from_secs(ms / 1000.0);
+  }
+  header("retry-after")
+    .and_then(|value| value.parse::<f64>().ok())
+    .and_then(duration_from_secs)
+}
+
+/// Values too large for a `Duration` saturate; `RetryPolicy::delay` gives up on them anyway.
+fn duration_from_secs(secs: f64) -> Option<Duration> {
+  (secs >= 0.0).then(|| Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::header::HeaderValue;
+
+  #[test]
+  fn delay_doubles_with_jitter_and_caps() {
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.delay(1, None, 1.0), Some(Duration::from_secs(1)));
+    assert_eq!(policy.delay(1, None, 0.0), Some(Duration::from_millis(500)));
+    assert_eq!(policy.delay(3, None, 1.0), Some(Duration::from_secs(4)));
+    assert_eq!(policy.delay(30, None, 1.0), Some(Duration::from_secs(60)));
+  }
+
+  #[test]
+  fn retry_after_overrides_backoff_and_gives_up_past_the_cap() {
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.delay(1, Some(Duration::from_secs(7)), 0.3), Some(Duration::from_secs(7)));
+    assert_eq!(policy.delay(1, Some(Duration::from_secs(60)), 0.3), Some(Duration::from_secs(60)));
+    assert_eq!(policy.delay(1, Some(Duration::from_secs(600)), 0.3), None);
+  }
+
+  #[test]
+  fn classifies_statuses() {
+    for code in [408, 409, 429, 500, 502, 503, 529] {
+      assert!(is_retryable_status(StatusCode::from_u16(code).unwrap()), "{}", code);
+    }
+    for code in [400, 401, 403, 404, 422] {
+      assert!(!is_retryable_status(StatusCode::from_u16(code).unwrap()), "{}", code);
+    }
+  }
+
+  #[test]
+  fn parses_retry_after_headers() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(retry_after(&headers), None);
+    headers.insert("retry-after", HeaderValue::from_static("3"));
+    assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+    headers.insert("retry-after-ms", HeaderValue::from_static("1500"));
+    assert_eq!(retry_after(&headers), Some(Duration::from_millis(1500)));
+    let mut dated = HeaderMap::new();
+    dated.insert("retry-after", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+    assert_eq!(retry_after(&dated), None);
+  }
+
+  #[test]
+  fn huge_retry_after_saturates_instead_of_panicking() {
+    let mut headers = HeaderMap::new();
+    headers.insert("retry-after", HeaderValue::from_static("1e30"));
+    assert_eq!(retry_after(&headers), Some(Duration::MAX));
+    assert_eq!(RetryPolicy::default().delay(1, retry_after(&headers), 0.5), None);
+    headers.insert("retry-after", HeaderValue::from_static("-5"));
+    assert_eq!(retry_after(&headers), None);
+    headers.insert("retry-after", HeaderValue::from_static("NaN"));
+    assert_eq!(retry_after(&headers), None);
+  }
+}
@@ -0,0 +1,90 @@
+/// One server-sent event: the optional `event:` name and the joined `data:` lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+  pub event: Option<String>,
+  pub data: String,
+}
+
+/// Buffers raw response bytes and yields complete events. Splitting happens on bytes so a
+/// multi-byte character spread across two network chunks is never mangled.
+#[derive(Default)]
+pub struct SseDecoder {
+  buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+  pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+    self.buffer.extend_from_slice(chunk);
+    let mut events = Vec::new();
+    while let Some((end, separator)) = find_boundary(&self.buffer) {
+      let raw: Vec<u8> = self.buffer.drain(..end + separator).collect();
+      if let Some(event) = parse_event(&String::from_utf8_lossy(&raw[..end])) {
+        events.push(event);
+      }
+    }
+    events
+  }
+}
+
+fn find_boundary(buffer: &[u8]) -> Option<(usize, usize)> {
+  let lf = buffer.windows(2).position(|window| window == b"\n\n").map(|pos| (pos, 2));
+  let crlf = buffer.windows(4).position(|window| window == b"\r\n\r\n").map(|pos| (pos, 4));
+  match (lf, crlf) {
+    (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+    (a, b) => a.or(b),
+  }
+}
+
+fn parse_event(raw: &str) -> Option<SseEvent> {
+  let mut event = None;
+  let mut data_lines = Vec::new();
+  for line in raw.lines() {
+    let line = line.trim();
+    if let Some(value) = line.strip_prefix("event:") {
+      event = Some(value.trim().to_string());
+    } else if let Some(value) = line.strip_prefix("data:") {
+      data_lines.push(value.trim());
+    }
+  }
+  if data_lines.is_empty() {
+    return None;
+  }
+  Some(SseEvent {
+    event,
+    data: data_lines.join("\n"),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decoder_waits_for_complete_events() {
+    let mut decoder = SseDecoder::default();
+    assert!(decoder.push(b"event: ping\ndata: {\"a\"").is_empty());
+    let events = decoder.push(b":1}\n\ndata: [DONE]\n\n");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event.as_deref(), Some("ping"));
+    assert_eq!(events[0].data, "{\"a\":1}");
+    assert_eq!(events[1].event, None);
+    assert_eq!(events[1].data, "[DONE]");
+  }
+
+  #[test]
+  fn decoder_keeps_split_utf8_intact() {
+    let mut decoder = SseDecoder::default();
+    let bytes = "data: 你好\n\n".as_bytes();
+    assert!(decoder.push(&bytes[..8]).is_empty());
+    let events = decoder.push(&bytes[8..]);
+    assert_eq!(events[0].data, "你好");
+  }
+
+  #[test]
+  fn decoder_accepts_crlf_and_skips_comments() {
+    let mut decoder = SseDecoder::default();
+    let events = decoder.push(b": keep-alive\r\n\r\ndata: x\r\n\r\n");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].data, "x");
+  }
+}
@@ -1,12 +1,29 @@
 use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, Notify};
 
-use crate::events::{PermissionMode, SessionInfo, SessionStatus};
-use crate::providers::registry::ProviderKind;
+use crate::events::{
+  DailyUsage, PermissionMode, SearchMatch, SessionInfo, SessionSearchResult, SessionStatus, UsageTotals,
+};
+use crate::providers::adapter::Usage;
+use crate::providers::params::GenerationParams;
+use crate::providers::pricing;
+use crate::providers::registry::{InMemorySecretStore, ProviderKind, ProviderOptions, SecretStore};
+use crate::checkpoints::Snapshot;
+use crate::storage::session_store::{CheckpointRow, SessionRow, SessionStore, UsageRow, UsageTotalsRow};
+
+const DATABASE_FILE: &str = "sessions.db";
+const SEARCH_HIT_LIMIT: usize = 200;
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+/// Suffix that keeps ids of sessions created in the same millisecond apart.
+static SESSION_SEQ: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Clone)]
 pub struct ProviderSettings {
@@ -15,50 +32,117 @@ pub struct ProviderSettings {
   pub model: String,
   pub base_url: Option<String>,
   pub permission_mode: PermissionMode,
+  /// Permission rules from `session.start` that skip the prompt, e.g. `Bash(git status:*)`.
+  pub allowed_tools: Vec<String>,
+  pub options: ProviderOptions,
+  pub params: GenerationParams,
+}
+
+/// Stop signal shared between `session.stop` and one run of a session's agent loop.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+  inner: Arc<CancelInner>,
 }
 
 #[derive(Default)]
+struct CancelInner {
+  cancelled: AtomicBool,
+  notify: Notify,
+}
+
+impl CancelToken {
+  pub fn cancel(&self) {
+    self.inner.cancelled.store(true, Ordering::SeqCst);
+    self.inner.notify.notify_waiters();
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.inner.cancelled.load(Ordering::SeqCst)
+  }
+
+  /// Completes once `cancel` has been called, immediately if it already was.
+  pub async fn cancelled(&self) {
+    loop {
+      let notified = self.inner.notify.notified();
+      if self.is_cancelled() {
+        return;
+      }
+      notified.await;
+    }
+  }
+
+  fn same_as(&self, other: &CancelToken) -> bool {
+    Arc::ptr_eq(&self.inner, &other.inner)
+  }
+}
+
 pub struct SessionState {
-  sessions: Mutex<HashMap<String, SessionInfo>>,
-  messages: Mutex<HashMap<String, Vec<Value>>>,
-  providers: Mutex<HashMap<String, ProviderSettings>>,
+  store: Mutex<SessionStore>,
+  /// API keys by session id. They are never written to the database, so sessions restored after a
+  /// restart need the key sent again.
+  secrets: Box<dyn SecretStore>,
   pending_permissions: Mutex<HashMap<String, oneshot::Sender<Value>>>,
+  runs: Mutex<HashMap<String, CancelToken>>,
+}
+
+impl Default for SessionState {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl SessionState {
+  /// Creates a state backed by an in-memory database; nothing survives the process.
   pub fn new() -> Self {
-    Self::default()
+    let store = SessionStore::new_in_memory().expect("in-memory session store");
+    Self::with_store(store)
+  }
+
+  /// Opens (or creates) the session database inside `data_dir`.
+  pub fn open(data_dir: &Path) -> Result<Self, String> {
+    std::fs::create_dir_all(data_dir).map_err(|e| e.to_string())?;
+    let store = SessionStore::open(&data_dir.join(DATABASE_FILE)).map_err(|e| e.to_string())?;
+    // A session cannot still be running after a restart; its task died with the previous process.
+    store
+      .reset_status(&to_db(&SessionStatus::Running), &to_db(&SessionStatus::Idle))
+      .map_err(|e| e.to_string())?;
+    Ok(Self::with_store(store))
+  }
+
+  fn with_store(store: SessionStore) -> Self {
+    Self {
+      store: Mutex::new(store),
+      secrets: Box::new(InMemorySecretStore::new()),
+      pending_permissions: Mutex::new(HashMap::new()),
+      runs: Mutex::new(HashMap::new()),
+    }
   }
 
   pub fn list_sessions(&self) -> Vec<SessionInfo> {
-    let mut list: Vec<SessionInfo> = self
-      .sessions
-      .lock()
-      .expect("session lock")
-      .values()
-      .cloned()
-      .collect();
-    list.sort_by_key(|session| session.updated_at);
-    list
+    let store = self.store();
+    let rows = logged(store.list_sessions(), "list sessions");
+    let mut usage = logged(store.usage_by_session(), "load usage");
+    rows
+      .into_iter()
+      .map(|row| {
+        let totals = usage.remove(&row.id).unwrap_or_default();
+        session_from_row(row, totals)
+      })
+      .collect()
   }
 
   pub fn get_session(&self, id: &str) -> Option<SessionInfo> {
-    self
-      .sessions
-      .lock()
-      .expect("session lock")
-      .get(id)
-      .cloned()
+    let store = self.store();
+    let row = logged(store.get_session(id), "load session")?;
+    let usage = logged(store.session_usage(id), "load usage");
+    Some(session_from_row(row, usage))
   }
 
   pub fn get_messages(&self, id: &str) -> Vec<Value> {
-    self
-      .messages
-      .lock()
-      .expect("message lock")
-      .get(id)
-      .cloned()
-      .unwrap_or_default()
+    logged(self.store().list_messages(id), "load messages")
+      .into_iter()
+      .filter_map(|row| serde_json::from_str(&row.payload).ok())
+      .collect()
   }
 
   pub fn create_session(
@@ -66,9 +150,9 @@ impl SessionState {
     title: String,
     cwd: Option<String>,
     provider: ProviderSettings,
-  ) -> SessionInfo {
+  ) -> Result<SessionInfo, String> {
     let now = now_ms();
-    let id = format!("session-{}", now);
+    let id = format!("session-{}-{}", now, SESSION_SEQ.fetch_add(1, Ordering::Relaxed));
     let session = SessionInfo {
       id: id.clone(),
       title,
@@ -79,26 +163,29 @@ impl SessionState {
       model: Some(provider.model.clone()),
       created_at: now,
       updated_at: now,
+      usage: UsageTotals::default(),
     };
 
-    self
-      .sessions
-      .lock()
-      .expect("session lock")
-      .insert(id.clone(), session.clone());
-    self
-      .messages
-      .lock()
-      .expect("message lock")
-      .entry(id)
-      .or_default();
-    self
-      .providers
-      .lock()
-      .expect("provider lock")
-      .insert(session.id.clone(), provider);
+    let row = SessionRow {
+      id,
+      title: session.title.clone(),
+      status: to_db(&session.status),
+      cwd: session.cwd.clone(),
+      claude_session_id: None,
+      provider: Some(to_db(&provider.provider)),
+      model: Some(provider.model),
+      base_url: provider.base_url,
+      permission_mode: Some(to_db(&provider.permission_mode)),
+      created_at: now,
+      updated_at: now,
+      provider_options: serde_json::to_string(&provider.options).ok(),
+      generation_params: serde_json::to_string(&provider.params).ok(),
+      allowed_tools: serde_json::to_string(&provider.allowed_tools).ok(),
+    };
+    self.store().insert_session(&row).map_err(|error| format!("保存会话失败：{}", error))?;
+    self.set_api_key(&session.id, &provider.api_key);
 
-    session
+    Ok(session)
   }
 
   pub fn update_session(
@@ -108,42 +195,166 @@ impl SessionState {
     title: Option<String>,
     cwd: Option<String>,
   ) -> Option<SessionInfo> {
-    let mut sessions = self.sessions.lock().expect("session lock");
-    let session = sessions.get_mut(id)?;
-    session.status = status;
-    if let Some(title) = title {
-      session.title = title;
-    }
-    if let Some(cwd) = cwd {
-      session.cwd = Some(cwd);
+    let store = self.store();
+    let updated = logged(
+      store.update_session(id, &to_db(&status), title.as_deref(), cwd.as_deref(), now_ms()),
+      "update session",
+    );
+    if !updated {
+      return None;
     }
-    session.updated_at = now_ms();
-    Some(session.clone())
+    drop(store);
+    self.get_session(id)
   }
 
   pub fn add_message(&self, id: &str, message: Value) {
-    let mut messages = self.messages.lock().expect("message lock");
-    messages.entry(id.to_string()).or_default().push(message);
+    let payload = message.to_string();
+    logged(self.store().append_message(id, &payload, now_ms()).map(|_| ()), "append message");
+  }
+
+  pub fn set_api_key(&self, id: &str, api_key: &str) {
+    if let Err(error) = self.secrets.set_key(id, api_key) {
+      log::error!("failed to store API key: {}", error);
+    }
+  }
+
+  pub fn permission_mode(&self, id: &str) -> PermissionMode {
+    self.get_provider(id).map_or(PermissionMode::Ask, |settings| settings.permission_mode)
+  }
+
+  pub fn set_permission_mode(&self, id: &str, mode: &PermissionMode) {
+    logged(self.store().update_permission_mode(id, &to_db(mode)), "update permission mode");
+  }
+
+  /// Adds a permission rule the session allows without asking, as if passed in `allowedTools`.
+  pub fn add_allowed_tool(&self, id: &str, rule: &str) {
+    let Some(mut settings) = self.get_provider(id) else {
+      return;
+    };
+    if settings.allowed_tools.iter().any(|existing| existing == rule) {
+      return;
+    }
+    settings.allowed_tools.push(rule.to_string());
+    if let Ok(encoded) = serde_json::to_string(&settings.allowed_tools) {
+      logged(self.store().update_allowed_tools(id, &encoded), "update allowed tools");
+    }
+  }
+
+  /// Stores the checkpoint for a file a tool call just modified, if it changed. It is placed at the
+  /// position the call's result is about to take in the history.
+  pub fn record_checkpoint(&self, id: &str, tool_use_id: &str, snapshot: Snapshot) {
+    let store = self.store();
+    let seq = logged(store.next_seq(id), "load message count");
+    if let Some(checkpoint) = snapshot.finish(id, tool_use_id, seq, now_ms()) {
+      logged(store.record_checkpoint(&checkpoint), "record checkpoint");
+    }
+  }
+
+  pub fn checkpoints_since(&self, id: &str, message_index: usize) -> Vec<CheckpointRow> {
+    logged(self.store().checkpoints_since(id, message_index as i64), "load checkpoints")
+  }
+
+  /// Drops the history from `message_index` on, along with its checkpoints.
+  pub fn truncate_messages(&self, id: &str, message_index: usize) {
+    logged(self.store().truncate_messages(id, message_index as i64), "truncate messages");
+  }
+
+  /// Records one model call, priced with the session's provider and model, and returns the
+  /// session's new totals.
+  pub fn record_usage(&self, id: &str, provider: &ProviderSettings, usage: &Usage) -> UsageTotals {
+    let cost_usd = pricing::price_for(&provider.provider, &provider.model)
+      .map(|price| pricing::cost_usd(usage, &price));
+    let row = UsageRow {
+      session_id: id.to_string(),
+      provider: Some(to_db(&provider.provider)),
+      model: Some(provider.model.clone()),
+      input_tokens: usage.input_tokens as i64,
+      output_tokens: usage.output_tokens as i64,
+      cache_read_tokens: usage.cache_read_tokens as i64,
+      cache_write_tokens: usage.cache_write_tokens as i64,
+      cost_usd,
+      created_at: now_ms(),
+    };
+    let store = self.store();
+    logged(store.record_usage(&row), "record usage");
+    usage_totals(logged(store.session_usage(id), "load usage"))
+  }
+
+  /// Daily totals across all sessions for the last `days` days, newest first.
+  pub fn usage_by_day(&self, days: u32) -> Vec<DailyUsage> {
+    let since = now_ms() - i64::from(days) * DAY_MS;
+    logged(self.store().usage_by_day(since), "load daily usage")
+      .into_iter()
+      .map(|(date, totals)| DailyUsage { date, usage: usage_totals(totals) })
+      .collect()
+  }
+
+  /// Groups full-text hits by session, keeping sessions in order of their best match.
+  pub fn search_sessions(&self, query: &str) -> Vec<SessionSearchResult> {
+    let store = self.store();
+    let hits = logged(store.search(query, SEARCH_HIT_LIMIT), "search messages");
+    let mut results: Vec<SessionSearchResult> = Vec::new();
+    for hit in hits {
+      let position = match results.iter().position(|result| result.session_id == hit.session_id) {
+        Some(position) => position,
+        None => {
+          let Some(session) = logged(store.get_session(&hit.session_id), "load session") else {
+            continue;
+          };
+          results.push(SessionSearchResult {
+            session_id: session.id,
+            title: session.title,
+            updated_at: session.updated_at,
+            matches: Vec::new(),
+          });
+          results.len() - 1
+        }
+      };
+      results[position].matches.push(SearchMatch {
+        message_index: hit.seq.max(0) as usize,
+        kind: hit.kind,
+        snippet: hit.snippet,
+        highlights: hit.highlights,
+      });
+    }
+    for result in &mut results {
+      result.matches.sort_by_key(|item| item.message_index);
+    }
+    results
   }
 
   pub fn get_provider(&self, id: &str) -> Option<ProviderSettings> {
-    self
-      .providers
-      .lock()
-      .expect("provider lock")
-      .get(id)
-      .cloned()
+    let row = logged(self.store().get_session(id), "load session")?;
+    Some(ProviderSettings {
+      provider: from_db(row.provider.as_deref()?)?,
+      api_key: self.secrets.get_key(id).ok().flatten().unwrap_or_default(),
+      model: row.model?,
+      base_url: row.base_url,
+      permission_mode: row
+        .permission_mode
+        .as_deref()
+        .and_then(from_db)
+        .unwrap_or(PermissionMode::Ask),
+      allowed_tools: row
+        .allowed_tools
+        .as_deref()
+        .and_then(|raw| serde_json::from_str(raw).ok())
+        .unwrap_or_default(),
+      options: row
+        .provider_options
+        .as_deref()
+        .and_then(|raw| serde_json::from_str(raw).ok())
+        .unwrap_or_default(),
+      params: row
+        .generation_params
+        .as_deref()
+        .and_then(|raw| serde_json::from_str(raw).ok())
+        .unwrap_or_default(),
+    })
   }
 
   pub fn list_recent_cwds(&self, limit: usize) -> Vec<String> {
-    let mut list: Vec<SessionInfo> = self
-      .sessions
-      .lock()
-      .expect("session lock")
-      .values()
-      .cloned()
-      .collect();
-    list.sort_by_key(|session| session.updated_at);
+    let mut list = self.list_sessions();
     list.reverse();
 
     let mut seen = HashSet::new();
@@ -183,16 +394,303 @@ impl SessionState {
     false
   }
 
+  /// Drops a pending permission request without answering it, e.g. when its run was stopped.
+  pub fn discard_permission(&self, tool_use_id: &str) {
+    self.pending_permissions.lock().expect("permission lock").remove(tool_use_id);
+  }
+
+  /// Registers a new agent-loop run for `id`, cancelling any run it supersedes.
+  pub fn begin_run(&self, id: &str) -> CancelToken {
+    let token = CancelToken::default();
+    let previous = self
+      .runs
+      .lock()
+      .expect("run lock")
+      .insert(id.to_string(), token.clone());
+    if let Some(previous) = previous {
+      previous.cancel();
+    }
+    token
+  }
+
+  pub fn finish_run(&self, id: &str, token: &CancelToken) {
+    let mut runs = self.runs.lock().expect("run lock");
+    if runs.get(id).is_some_and(|current| current.same_as(token)) {
+      runs.remove(id);
+    }
+  }
+
+  pub fn is_running(&self, id: &str) -> bool {
+    self.runs.lock().expect("run lock").contains_key(id)
+  }
+
+  /// Cancels the active run of `id`; returns false when nothing was running.
+  pub fn cancel_run(&self, id: &str) -> bool {
+    match self.runs.lock().expect("run lock").remove(id) {
+      Some(token) => {
+        token.cancel();
+        true
+      }
+      None => false,
+    }
+  }
+
   pub fn delete_session(&self, id: &str) {
-    self.sessions.lock().expect("session lock").remove(id);
-    self.messages.lock().expect("message lock").remove(id);
-    self.providers.lock().expect("provider lock").remove(id);
+    self.cancel_run(id);
+    logged(self.store().delete_session(id), "delete session");
+  }
+
+  fn store(&self) -> std::sync::MutexGuard<'_, SessionStore> {
+    self.store.lock().expect("store lock")
+  }
+}
+
+fn session_from_row(row: SessionRow, usage: UsageTotalsRow) -> SessionInfo {
+  SessionInfo {
+    id: row.id,
+    title: row.title,
+    status: from_db(&row.status).unwrap_or(SessionStatus::Idle),
+    cwd: row.cwd,
+    claude_session_id: row.claude_session_id,
+    provider: row.provider.as_deref().and_then(from_db),
+    model: row.model,
+    created_at: row.created_at,
+    updated_at: row.updated_at,
+    usage: usage_totals(usage),
   }
 }
 
+fn usage_totals(row: UsageTotalsRow) -> UsageTotals {
+  let count = |value: i64| value.max(0) as u64;
+  UsageTotals {
+    calls: count(row.calls),
+    input_tokens: count(row.input_tokens),
+    output_tokens: count(row.output_tokens),
+    cache_read_tokens: count(row.cache_read_tokens),
+    cache_write_tokens: count(row.cache_write_tokens),
+    cost_usd: row.cost_usd,
+    unpriced_calls: count(row.unpriced_calls),
+  }
+}
+
+/// Stores enums by their serde name so the database matches the IPC payloads.
+fn to_db<T: Serialize>(value: &T) -> String {
+  match serde_json::to_value(value) {
+    Ok(Value::String(text)) => text,
+    _ => String::new(),
+  }
+}
+
+fn from_db<T: DeserializeOwned>(value: &str) -> Option<T> {
+  serde_json::from_value(Value::String(value.to_string())).ok()
+}
+
+fn logged<T: Default>(result: rusqlite::Result<T>, action: &str) -> T {
+  result.unwrap_or_else(|error| {
+    log::error!("failed to {}: {}", action, error);
+    T::default()
+  })
+}
+
 fn now_ms() -> i64 {
   SystemTime::now()
     .duration_since(UNIX_EPOCH)
     .map(|duration| duration.as_millis() as i64)
     .unwrap_or(0)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::providers::registry::OpenAIApi;
+  use serde_json::json;
+  use tempfile::tempdir;
+
+  fn provider() -> ProviderSettings {
+    ProviderSettings {
+      provider: ProviderKind::OpenAI,
+      api_key: "sk-test".into(),
+      model: "gpt-test".into(),
+      base_url: Some("http://localhost:1234".into()),
+      permission_mode: PermissionMode::Auto,
+      allowed_tools: vec!["Bash(git status:*)".into()],
+      options: ProviderOptions { openai_api: OpenAIApi::Responses },
+      params: GenerationParams { max_tokens: Some(16000), ..GenerationParams::default() },
+    }
+  }
+
+  #[test]
+  fn sessions_and_history_survive_reopen() {
+    let dir = tempdir().unwrap();
+    let session_id = {
+      let state = SessionState::open(dir.path()).unwrap();
+      let session = state.create_session("Fix build".into(), Some("/work".into()), provider()).unwrap();
+      state.add_message(&session.id, json!({ "type": "user_prompt", "prompt": "hi" }));
+      state.add_message(&session.id, json!({ "type": "assistant", "message": { "content": [] } }));
+      assert_eq!(state.get_provider(&session.id).unwrap().api_key, "sk-test");
+      session.id
+    };
+    let database = std::fs::read(dir.path().join(DATABASE_FILE)).unwrap();
+    assert!(!database.windows(7).any(|window| window == b"sk-test"), "API key written to disk");
+
+    let state = SessionState::open(dir.path()).unwrap();
+    let session = state.get_session(&session_id).expect("session restored");
+    assert_eq!(session.title, "Fix build");
+    assert_eq!(session.status, SessionStatus::Idle);
+    assert_eq!(session.provider, Some(ProviderKind::OpenAI));
+    assert_eq!(state.get_messages(&session_id).len(), 2);
+
+    let settings = state.get_provider(&session_id).expect("provider restored");
+    assert_eq!(settings.model, "gpt-test");
+    assert_eq!(settings.permission_mode, PermissionMode::Auto);
+    assert_eq!(settings.base_url.as_deref(), Some("http://localhost:1234"));
+    assert_eq!(settings.options.openai_api, OpenAIApi::Responses);
+    assert!(settings.api_key.is_empty());
+  }
+
+  #[test]
+  fn generation_params_survive_reopen() {
+    let dir = tempdir().unwrap();
+    let session_id = SessionState::open(dir.path()).unwrap().create_session("t".into(), None, provider()).unwrap().id;
+
+    let state = SessionState::open(dir.path()).unwrap();
+    assert_eq!(state.get_provider(&session_id).unwrap().params.max_tokens, Some(16000));
+  }
+
+  #[test]
+  fn allowed_tools_are_persisted_once() {
+    let dir = tempdir().unwrap();
+    let session_id = {
+      let state = SessionState::open(dir.path()).unwrap();
+      let session = state.create_session("t".into(), None, provider()).unwrap();
+      state.add_allowed_tool(&session.id, "Edit(src/**)");
+      state.add_allowed_tool(&session.id, "Edit(src/**)");
+      session.id
+    };
+
+    let state = SessionState::open(dir.path()).unwrap();
+    let allowed = state.get_provider(&session_id).unwrap().allowed_tools;
+    assert_eq!(allowed, vec!["Bash(git status:*)".to_string(), "Edit(src/**)".to_string()]);
+  }
+
+  #[test]
+  fn permission_mode_changes_are_persisted() {
+    let dir = tempdir().unwrap();
+    let session_id = {
+      let state = SessionState::open(dir.path()).unwrap();
+      let session = state.create_session("t".into(), None, provider()).unwrap();
+      assert_eq!(state.permission_mode(&session.id), PermissionMode::Auto);
+      state.set_permission_mode(&session.id, &PermissionMode::Plan);
+      assert_eq!(state.permission_mode(&session.id), PermissionMode::Plan);
+      session.id
+    };
+
+    let state = SessionState::open(dir.path()).unwrap();
+    assert_eq!(state.permission_mode(&session_id), PermissionMode::Plan);
+    state.set_permission_mode(&session_id, &PermissionMode::Auto);
+    let settings = state.get_provider(&session_id).unwrap();
+    assert_eq!(settings.permission_mode, PermissionMode::Auto);
+    assert_eq!(settings.model, "gpt-test", "switching modes leaves the rest of the settings alone");
+    assert_eq!(settings.params.max_tokens, Some(16000));
+  }
+
+  #[test]
+  fn sessions_created_together_are_all_persisted() {
+    let state = SessionState::new();
+    let first = state.create_session("first".into(), None, provider()).unwrap();
+    let second = state.create_session("second".into(), None, provider()).unwrap();
+    assert_ne!(first.id, second.id);
+    assert_eq!(state.list_sessions().len(), 2);
+  }
+
+  #[test]
+  fn search_sessions_groups_hits_by_session() {
+    let state = SessionState::new();
+    let first = state.create_session("first".into(), None, provider()).unwrap();
+    state.add_message(&first.id, json!({ "type": "user_prompt", "prompt": "deploy the app" }));
+    state.add_message(&first.id, json!({ "type": "user_prompt", "prompt": "deploy again" }));
+    let second = state.create_session("second".into(), None, provider()).unwrap();
+    state.add_message(&second.id, json!({ "type": "user_prompt", "prompt": "unrelated" }));
+
+    let results = state.search_sessions("deploy");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].title, "first");
+    let indexes: Vec<usize> = results[0].matches.iter().map(|m| m.message_index).collect();
+    assert_eq!(indexes, vec![0, 1]);
+  }
+
+  #[test]
+  fn cancel_run_signals_the_active_token_only() {
+    let state = SessionState::new();
+    let first = state.begin_run("s1");
+    let second = state.begin_run("s1");
+    assert!(first.is_cancelled(), "a new run supersedes the previous one");
+    assert!(!second.is_cancelled());
+
+    state.finish_run("s1", &first);
+    assert!(state.cancel_run("s1"));
+    assert!(second.is_cancelled());
+    assert!(!state.cancel_run("s1"));
+  }
+
+  #[tokio::test]
+  async fn cancelled_wakes_waiters() {
+    let token = CancelToken::default();
+    let waiter = {
+      let token = token.clone();
+      tokio::spawn(async move { token.cancelled().await })
+    };
+    tokio::task::yield_now().await;
+    token.cancel();
+    waiter.await.unwrap();
+    token.cancelled().await;
+  }
+
+  #[test]
+  fn record_usage_accumulates_priced_and_unpriced_calls() {
+    let state = SessionState::new();
+    let session = state.create_session("t".into(), None, provider()).unwrap();
+    let usage = Usage { input_tokens: 10, output_tokens: 5, ..Usage::default() };
+    let totals = state.record_usage(&session.id, &provider(), &usage);
+    assert_eq!(totals.calls, 1);
+    assert_eq!(totals.unpriced_calls, 1, "gpt-test has no list price");
+
+    let priced = ProviderSettings { model: "gpt-4o".into(), ..provider() };
+    let totals = state.record_usage(&session.id, &priced, &usage);
+    assert_eq!(totals.input_tokens, 20);
+    assert!(totals.cost_usd > 0.0);
+    assert_eq!(state.list_sessions()[0].usage, totals);
+    assert_eq!(state.usage_by_day(1)[0].usage.calls, 2);
+  }
+
+  #[test]
+  fn checkpoints_follow_the_history() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    let state = SessionState::new();
+    let session = state.create_session("t".into(), None, provider()).unwrap();
+    state.add_message(&session.id, json!({ "type": "user_prompt", "prompt": "write it" }));
+
+    let snapshot = Snapshot::take(file.clone());
+    std::fs::write(&file, "new").unwrap();
+    state.record_checkpoint(&session.id, "tool-1", snapshot);
+    let checkpoints = state.checkpoints_since(&session.id, 0);
+    assert_eq!(checkpoints.len(), 1);
+    assert_eq!(checkpoints[0].seq, 1);
+    assert_eq!(checkpoints[0].before, None);
+
+    state.truncate_messages(&session.id, 0);
+    assert!(state.get_messages(&session.id).is_empty());
+    assert!(state.checkpoints_since(&session.id, 0).is_empty());
+  }
+
+  #[test]
+  fn delete_session_removes_history() {
+    let state = SessionState::new();
+    let session = state.create_session("t".into(), None, provider()).unwrap();
+    state.add_message(&session.id, json!({ "type": "user_prompt", "prompt": "hi" }));
+    state.delete_session(&session.id);
+    assert!(state.get_session(&session.id).is_none());
+    assert!(state.get_messages(&session.id).is_empty());
+  }
+}
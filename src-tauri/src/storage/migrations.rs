@@ -0,0 +1,272 @@
+use std::fmt;
+
+use rusqlite::{params, Connection};
+
+use super::search;
+
+pub struct Migration {
+  pub version: i64,
+  pub name: &'static str,
+  pub sql: &'static str,
+  /// Optional data step run after `sql`, inside the same transaction.
+  pub backfill: Option<fn(&Connection) -> rusqlite::Result<()>>,
+}
+
+/// Ordered schema steps. Append new entries; never edit one that has shipped.
+pub const MIGRATIONS: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "create sessions and messages",
+    // `if not exists` adopts databases written before versioning existed.
+    sql: "
+      create table if not exists sessions(
+        id text primary key,
+        title text not null,
+        status text not null,
+        cwd text,
+        claude_session_id text,
+        provider text,
+        model text,
+        base_url text,
+        permission_mode text,
+        created_at integer not null,
+        updated_at integer not null
+      );
+      create table if not exists messages(
+        session_id text not null references sessions(id) on delete cascade,
+        seq integer not null,
+        payload text not null,
+        created_at integer not null,
+        primary key (session_id, seq)
+      );
+    ",
+    backfill: None,
+  },
+  Migration {
+    version: 2,
+    name: "full-text index over messages",
+    sql: "
+      create virtual table message_search using fts5(
+        session_id unindexed,
+        seq unindexed,
+        kind unindexed,
+        body,
+        tokenize = 'unicode61'
+      );
+    ",
+    backfill: Some(index_existing_messages),
+  },
+  Migration {
+    version: 3,
+    name: "per-session provider options",
+    sql: "alter table sessions add column provider_options text;",
+    backfill: None,
+  },
+  Migration {
+    version: 4,
+    name: "token usage per model call",
+    sql: "
+      create table model_usage(
+        id integer primary key autoincrement,
+        session_id text not null references sessions(id) on delete cascade,
+        provider text,
+        model text,
+        input_tokens integer not null,
+        output_tokens integer not null,
+        cache_read_tokens integer not null,
+        cache_write_tokens integer not null,
+        cost_usd real,
+        created_at integer not null
+      );
+      create index model_usage_by_session on model_usage(session_id);
+      create index model_usage_by_time on model_usage(created_at);
+    ",
+    backfill: None,
+  },
+  Migration {
+    version: 5,
+    name: "per-session generation parameters",
+    sql: "alter table sessions add column generation_params text;",
+    backfill: None,
+  },
+  Migration {
+    version: 6,
+    name: "file checkpoints for rewind",
+    // A null content means the file did not exist.
+    sql: "
+      create table file_checkpoints(
+        id integer primary key autoincrement,
+        session_id text not null references sessions(id) on delete cascade,
+        tool_use_id text not null,
+        seq integer not null,
+        path text not null,
+        before_content blob,
+        after_content blob,
+        created_at integer not null
+      );
+      create index file_checkpoints_by_session on file_checkpoints(session_id, seq);
+    ",
+    backfill: None,
+  },
+  Migration {
+    version: 7,
+    name: "per-session allowed tools",
+    sql: "alter table sessions add column allowed_tools text;",
+    backfill: None,
+  },
+];
+
+fn index_existing_messages(conn: &Connection) -> rusqlite::Result<()> {
+  let mut select = conn.prepare("select session_id, seq, payload from messages")?;
+  let mut insert = conn.prepare("insert into message_search(session_id,seq,kind,body) values (?1,?2,?3,?4)")?;
+  let rows = select
+    .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?)))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  for (session_id, seq, payload) in rows {
+    let parsed = serde_json::from_str(&payload).unwrap_or_default();
+    if let Some((kind, body)) = search::searchable_text(&parsed) {
+      insert.execute(params![session_id, seq, kind, body])?;
+    }
+  }
+  Ok(())
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+  Sqlite(rusqlite::Error),
+  UnsupportedVersion { found: i64, supported: i64 },
+}
+
+impl fmt::Display for MigrationError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      MigrationError::Sqlite(error) => write!(f, "{}", error),
+      MigrationError::UnsupportedVersion { found, supported } => write!(
+        f,
+        "session database is at schema version {} but this build only supports up to {}; please upgrade open-cowork",
+        found, supported
+      ),
+    }
+  }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+  fn from(error: rusqlite::Error) -> Self {
+    MigrationError::Sqlite(error)
+  }
+}
+
+pub fn latest_version() -> i64 {
+  MIGRATIONS.last().map(|migration| migration.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> rusqlite::Result<i64> {
+  conn.query_row("select coalesce(max(version), 0) from schema_version", [], |row| row.get(0))
+}
+
+/// Brings `conn` up to `latest_version()`, applying each pending step in its own transaction.
+pub fn migrate(conn: &mut Connection) -> Result<i64, MigrationError> {
+  conn.execute_batch(
+    "create table if not exists schema_version(version integer primary key, name text not null, applied_at integer not null)",
+  )?;
+  let current = current_version(conn)?;
+  let supported = latest_version();
+  if current > supported {
+    return Err(MigrationError::UnsupportedVersion { found: current, supported });
+  }
+
+  for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+    let tx = conn.transaction()?;
+    tx.execute_batch(migration.sql)?;
+    if let Some(backfill) = migration.backfill {
+      backfill(&tx)?;
+    }
+    tx.execute(
+      "insert into schema_version(version,name,applied_at) values (?1,?2,strftime('%s','now'))",
+      params![migration.version, migration.name],
+    )?;
+    tx.commit()?;
+  }
+  Ok(supported)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn versions_are_contiguous_from_one() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+      assert_eq!(migration.version, index as i64 + 1, "{}", migration.name);
+    }
+  }
+
+  #[test]
+  fn migrate_fresh_database_to_latest() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+    assert_eq!(current_version(&conn).unwrap(), latest_version());
+    conn.execute("insert into sessions(id,title,status,created_at,updated_at) values ('s1','t','idle',1,1)", [])
+      .unwrap();
+  }
+
+  #[test]
+  fn migrate_is_idempotent() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    migrate(&mut conn).unwrap();
+    let applied: i64 = conn
+      .query_row("select count(*) from schema_version", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(applied, MIGRATIONS.len() as i64);
+  }
+
+  #[test]
+  fn migrate_adopts_unversioned_database() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+    conn.execute("insert into sessions(id,title,status,created_at,updated_at) values ('s1','t','idle',1,1)", [])
+      .unwrap();
+    migrate(&mut conn).unwrap();
+    let count: i64 = conn.query_row("select count(*) from sessions", [], |row| row.get(0)).unwrap();
+    assert_eq!(count, 1);
+  }
+
+  #[test]
+  fn search_migration_indexes_existing_messages() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+    conn.execute("insert into sessions(id,title,status,created_at,updated_at) values ('s1','t','idle',1,1)", [])
+      .unwrap();
+    conn.execute(
+      "insert into messages(session_id,seq,payload,created_at) values ('s1',0,?1,1)",
+      [r#"{"type":"user_prompt","prompt":"rename the column"}"#],
+    )
+    .unwrap();
+    migrate(&mut conn).unwrap();
+    let hits: i64 = conn
+      .query_row("select count(*) from message_search where message_search match 'column'", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(hits, 1);
+  }
+
+  #[test]
+  fn migrate_refuses_newer_database() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    conn.execute(
+      "insert into schema_version(version,name,applied_at) values (?1,'future',0)",
+      [latest_version() + 1],
+    )
+    .unwrap();
+    match migrate(&mut conn) {
+      Err(MigrationError::UnsupportedVersion { found, supported }) => {
+        assert_eq!(found, latest_version() + 1);
+        assert_eq!(supported, latest_version());
+      }
+      other => panic!("expected unsupported version, got {:?}", other.map(|_| ())),
+    }
+  }
+}
@@ -1 +1,3 @@
+pub mod migrations;
+pub mod search;
 pub mod session_store;
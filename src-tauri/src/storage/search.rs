@@ -0,0 +1,147 @@
+use serde_json::Value;
+
+/// Private-use characters SQLite's `snippet()` wraps matches in. They are stripped from indexed
+/// text and from the snippet, so a transcript can never fake a highlight or smuggle in markup.
+pub const HIGHLIGHT_START: char = '\u{E000}';
+pub const HIGHLIGHT_END: char = '\u{E001}';
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+  pub session_id: String,
+  pub seq: i64,
+  pub kind: String,
+  /// Plain text; never contains markup.
+  pub snippet: String,
+  /// `[start, end)` ranges of matched terms in `snippet`, in UTF-16 code units as JavaScript counts.
+  pub highlights: Vec<[usize; 2]>,
+}
+
+/// Returns the message kind and the text worth indexing, or `None` for messages with nothing searchable.
+pub fn searchable_text(payload: &Value) -> Option<(&'static str, String)> {
+  let (kind, text) = match payload.get("type").and_then(Value::as_str) {
+    Some("user_prompt") => ("user_prompt", payload.get("prompt").and_then(Value::as_str)?.to_string()),
+    Some("assistant") => ("assistant", collect_blocks(payload, "text", |block| block.get("text"))),
+    Some("user") => ("tool_result", collect_blocks(payload, "tool_result", |block| block.get("content"))),
+    _ => return None,
+  };
+  let text = text.replace([HIGHLIGHT_START, HIGHLIGHT_END], "");
+  if text.trim().is_empty() {
+    None
+  } else {
+    Some((kind, text))
+  }
+}
+
+/// Splits a `snippet()` result into plain text and the highlighted ranges.
+pub fn split_highlights(marked: &str) -> (String, Vec<[usize; 2]>) {
+  let mut text = String::with_capacity(marked.len());
+  let mut highlights = Vec::new();
+  let mut offset = 0;
+  let mut start = None;
+  for ch in marked.chars() {
+    match ch {
+      HIGHLIGHT_START => start = Some(offset),
+      HIGHLIGHT_END => {
+        if let Some(start) = start.take() {
+          highlights.push([start, offset]);
+        }
+      }
+      _ => {
+        text.push(ch);
+        offset += ch.len_utf16();
+      }
+    }
+  }
+  (text, highlights)
+}
+
+fn collect_blocks(payload: &Value, block_type: &str, field: impl Fn(&Value) -> Option<&Value>) -> String {
+  let mut parts = Vec::new();
+  if let Some(blocks) = payload.pointer("/message/content").and_then(Value::as_array) {
+    for block in blocks {
+      if block.get("type").and_then(Value::as_str) == Some(block_type) {
+        if let Some(value) = field(block) {
+          push_text(value, &mut parts);
+        }
+      }
+    }
+  }
+  parts.join("\n")
+}
+
+fn push_text(value: &Value, parts: &mut Vec<String>) {
+  match value {
+    Value::String(text) => parts.push(text.clone()),
+    Value::Array(items) => {
+      for item in items {
+        if let Some(text) = item.get("text").and_then(Value::as_str) {
+          parts.push(text.to_string());
+        }
+      }
+    }
+    _ => {}
+  }
+}
+
+/// Turns free-form user input into an FTS5 query where every word must match, so
+/// punctuation or operators in the input can never produce a syntax error.
+pub fn fts_query(input: &str) -> Option<String> {
+  let terms: Vec<String> = input
+    .split_whitespace()
+    .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+    .collect();
+  if terms.is_empty() {
+    None
+  } else {
+    Some(terms.join(" "))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn searchable_text_covers_prompts_assistant_text_and_tool_results() {
+    let prompt = json!({ "type": "user_prompt", "prompt": "fix the migration script" });
+    assert_eq!(searchable_text(&prompt), Some(("user_prompt", "fix the migration script".into())));
+
+    let assistant = json!({ "type": "assistant", "message": { "content": [
+      { "type": "text", "text": "Looking now." },
+      { "type": "tool_use", "id": "t1", "name": "Read", "input": { "file_path": "a" } }
+    ] } });
+    assert_eq!(searchable_text(&assistant), Some(("assistant", "Looking now.".into())));
+
+    let tool = json!({ "type": "user", "message": { "content": [
+      { "type": "tool_result", "tool_use_id": "t1", "content": "fn main() {}", "is_error": false }
+    ] } });
+    assert_eq!(searchable_text(&tool), Some(("tool_result", "fn main() {}".into())));
+
+    let tool_only = json!({ "type": "assistant", "message": { "content": [
+      { "type": "tool_use", "id": "t1", "name": "Read", "input": {} }
+    ] } });
+    assert_eq!(searchable_text(&tool_only), None);
+  }
+
+  #[test]
+  fn highlights_are_offsets_into_plain_text() {
+    let marked = "é <b> \u{E000}migration\u{E001} 😀 \u{E000}script\u{E001}";
+    let (text, highlights) = split_highlights(marked);
+    assert_eq!(text, "é <b> migration 😀 script");
+    let utf16: Vec<u16> = text.encode_utf16().collect();
+    let words: Vec<String> =
+      highlights.iter().map(|[start, end]| String::from_utf16_lossy(&utf16[*start..*end])).collect();
+    assert_eq!(words, vec!["migration", "script"]);
+
+    let forged = json!({ "type": "user_prompt", "prompt": "a \u{E000}fake\u{E001} hit" });
+    assert_eq!(searchable_text(&forged), Some(("user_prompt", "a fake hit".into())));
+  }
+
+  #[test]
+  fn fts_query_quotes_every_term() {
+    assert_eq!(fts_query("  "), None);
+    assert_eq!(fts_query("migration script"), Some("\"migration\" \"script\"".into()));
+    assert_eq!(fts_query("say \"hi\" OR"), Some("\"say\" \"\"\"hi\"\"\" \"OR\"".into()));
+  }
+}
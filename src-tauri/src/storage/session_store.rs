@@ -1,59 +1,565 @@
-use rusqlite::{Connection, Result};
+use std::path::Path;
 
-#[derive(Debug, Clone)]
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+use super::migrations::{self, MigrationError};
+use super::search::{self, SearchHit, HIGHLIGHT_END, HIGHLIGHT_START};
+
+const SESSION_COLUMNS: &str = "id,title,status,cwd,claude_session_id,provider,model,base_url,permission_mode,created_at,updated_at,provider_options,generation_params,allowed_tools";
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SessionRow {
   pub id: String,
   pub title: String,
   pub status: String,
+  pub cwd: Option<String>,
+  pub claude_session_id: Option<String>,
+  pub provider: Option<String>,
+  pub model: Option<String>,
+  pub base_url: Option<String>,
+  pub permission_mode: Option<String>,
+  pub created_at: i64,
+  pub updated_at: i64,
+  /// JSON-encoded `ProviderOptions`.
+  pub provider_options: Option<String>,
+  /// JSON-encoded `GenerationParams`.
+  pub generation_params: Option<String>,
+  /// JSON array of permission rules the session allows without asking.
+  pub allowed_tools: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageRow {
+  pub seq: i64,
+  pub payload: String,
+  pub created_at: i64,
+}
+
+/// One model call's token counts; `cost_usd` is `None` when the model has no known price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRow {
+  pub session_id: String,
+  pub provider: Option<String>,
+  pub model: Option<String>,
+  pub input_tokens: i64,
+  pub output_tokens: i64,
+  pub cache_read_tokens: i64,
+  pub cache_write_tokens: i64,
+  pub cost_usd: Option<f64>,
+  pub created_at: i64,
 }
 
+/// A file as it was before and after one tool call changed it; `None` content means the file did
+/// not exist. `seq` is the position of that call's result in the session's messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckpointRow {
+  pub session_id: String,
+  pub tool_use_id: String,
+  pub seq: i64,
+  pub path: String,
+  pub before: Option<Vec<u8>>,
+  pub after: Option<Vec<u8>>,
+  pub created_at: i64,
+}
+
+/// Sums over a set of usage rows. Unpriced calls add tokens but no cost.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageTotalsRow {
+  pub calls: i64,
+  pub input_tokens: i64,
+  pub output_tokens: i64,
+  pub cache_read_tokens: i64,
+  pub cache_write_tokens: i64,
+  pub cost_usd: f64,
+  pub unpriced_calls: i64,
+}
+
+const USAGE_SUMS: &str = "count(*), coalesce(sum(input_tokens),0), coalesce(sum(output_tokens),0),
+  coalesce(sum(cache_read_tokens),0), coalesce(sum(cache_write_tokens),0), coalesce(sum(cost_usd),0),
+  coalesce(sum(cost_usd is null),0)";
+
 pub struct SessionStore {
   conn: Connection,
 }
 
 impl SessionStore {
-  pub fn new_in_memory() -> Result<Self> {
-    let conn = Connection::open_in_memory()?;
-    conn.execute(
-      "create table sessions(id text primary key, title text, status text)",
-      [],
-    )?;
+  pub fn open(path: &Path) -> Result<Self, MigrationError> {
+    Self::init(Connection::open(path)?)
+  }
+
+  pub fn new_in_memory() -> Result<Self, MigrationError> {
+    Self::init(Connection::open_in_memory()?)
+  }
+
+  fn init(mut conn: Connection) -> Result<Self, MigrationError> {
+    conn.execute_batch("pragma foreign_keys = on;")?;
+    migrations::migrate(&mut conn)?;
     Ok(Self { conn })
   }
 
-  pub fn insert_session(&self, id: &str, title: &str, status: &str) -> Result<()> {
+  pub fn insert_session(&self, row: &SessionRow) -> Result<()> {
     self.conn.execute(
-      "insert into sessions(id,title,status) values (?1,?2,?3)",
-      (id, title, status),
+      &format!("insert into sessions({}) values (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12,?13,?14)", SESSION_COLUMNS),
+      params![
+        row.id,
+        row.title,
+        row.status,
+        row.cwd,
+        row.claude_session_id,
+        row.provider,
+        row.model,
+        row.base_url,
+        row.permission_mode,
+        row.created_at,
+        row.updated_at,
+        row.provider_options,
+        row.generation_params,
+        row.allowed_tools,
+      ],
     )?;
     Ok(())
   }
 
+  pub fn update_session(
+    &self,
+    id: &str,
+    status: &str,
+    title: Option<&str>,
+    cwd: Option<&str>,
+    updated_at: i64,
+  ) -> Result<bool> {
+    let changed = self.conn.execute(
+      "update sessions set status = ?2, title = coalesce(?3, title), cwd = coalesce(?4, cwd), updated_at = ?5 where id = ?1",
+      params![id, status, title, cwd, updated_at],
+    )?;
+    Ok(changed > 0)
+  }
+
+  pub fn update_permission_mode(&self, id: &str, permission_mode: &str) -> Result<bool> {
+    let changed = self
+      .conn
+      .execute("update sessions set permission_mode = ?2 where id = ?1", params![id, permission_mode])?;
+    Ok(changed > 0)
+  }
+
+  pub fn update_allowed_tools(&self, id: &str, allowed_tools: &str) -> Result<bool> {
+    let changed = self
+      .conn
+      .execute("update sessions set allowed_tools = ?2 where id = ?1", params![id, allowed_tools])?;
+    Ok(changed > 0)
+  }
+
+  /// Moves every session in `from` status to `to`, e.g. runs that were cut off by an app exit.
+  pub fn reset_status(&self, from: &str, to: &str) -> Result<usize> {
+    self
+      .conn
+      .execute("update sessions set status = ?2 where status = ?1", params![from, to])
+  }
+
+  pub fn get_session(&self, id: &str) -> Result<Option<SessionRow>> {
+    self
+      .conn
+      .query_row(
+        &format!("select {} from sessions where id = ?1", SESSION_COLUMNS),
+        [id],
+        session_from_row,
+      )
+      .optional()
+  }
+
   pub fn list_sessions(&self) -> Result<Vec<SessionRow>> {
-    let mut stmt = self.conn.prepare("select id,title,status from sessions order by id")?;
+    let mut stmt = self
+      .conn
+      .prepare(&format!("select {} from sessions order by updated_at, id", SESSION_COLUMNS))?;
     let rows = stmt
-      .query_map([], |row| {
-        Ok(SessionRow {
-          id: row.get(0)?,
-          title: row.get(1)?,
-          status: row.get(2)?,
+      .query_map([], session_from_row)?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+  }
+
+  pub fn delete_session(&mut self, id: &str) -> Result<()> {
+    let tx = self.conn.transaction()?;
+    tx.execute("delete from message_search where session_id = ?1", [id])?;
+    tx.execute("delete from model_usage where session_id = ?1", [id])?;
+    tx.execute("delete from file_checkpoints where session_id = ?1", [id])?;
+    tx.execute("delete from messages where session_id = ?1", [id])?;
+    tx.execute("delete from sessions where id = ?1", [id])?;
+    tx.commit()
+  }
+
+  pub fn append_message(&self, session_id: &str, payload: &str, created_at: i64) -> Result<i64> {
+    let tx = self.conn.unchecked_transaction()?;
+    let seq: i64 = tx.query_row(
+      "select coalesce(max(seq) + 1, 0) from messages where session_id = ?1",
+      [session_id],
+      |row| row.get(0),
+    )?;
+    tx.execute(
+      "insert into messages(session_id,seq,payload,created_at) values (?1,?2,?3,?4)",
+      params![session_id, seq, payload, created_at],
+    )?;
+    let parsed = serde_json::from_str(payload).unwrap_or_default();
+    if let Some((kind, body)) = search::searchable_text(&parsed) {
+      tx.execute(
+        "insert into message_search(session_id,seq,kind,body) values (?1,?2,?3,?4)",
+        params![session_id, seq, kind, body],
+      )?;
+    }
+    tx.commit()?;
+    Ok(seq)
+  }
+
+  pub fn next_seq(&self, session_id: &str) -> Result<i64> {
+    self.conn.query_row(
+      "select coalesce(max(seq) + 1, 0) from messages where session_id = ?1",
+      [session_id],
+      |row| row.get(0),
+    )
+  }
+
+  /// Drops messages and file checkpoints from position `seq` on.
+  pub fn truncate_messages(&self, session_id: &str, seq: i64) -> Result<()> {
+    let tx = self.conn.unchecked_transaction()?;
+    tx.execute("delete from message_search where session_id = ?1 and seq >= ?2", params![session_id, seq])?;
+    tx.execute("delete from messages where session_id = ?1 and seq >= ?2", params![session_id, seq])?;
+    tx.execute("delete from file_checkpoints where session_id = ?1 and seq >= ?2", params![session_id, seq])?;
+    tx.commit()
+  }
+
+  pub fn record_checkpoint(&self, checkpoint: &CheckpointRow) -> Result<()> {
+    self.conn.execute(
+      "insert into file_checkpoints(session_id,tool_use_id,seq,path,before_content,after_content,created_at)
+       values (?1,?2,?3,?4,?5,?6,?7)",
+      params![
+        checkpoint.session_id,
+        checkpoint.tool_use_id,
+        checkpoint.seq,
+        checkpoint.path,
+        checkpoint.before,
+        checkpoint.after,
+        checkpoint.created_at,
+      ],
+    )?;
+    Ok(())
+  }
+
+  /// Checkpoints at or after position `seq`, oldest first.
+  pub fn checkpoints_since(&self, session_id: &str, seq: i64) -> Result<Vec<CheckpointRow>> {
+    let mut stmt = self.conn.prepare(
+      "select session_id,tool_use_id,seq,path,before_content,after_content,created_at
+       from file_checkpoints where session_id = ?1 and seq >= ?2 order by seq, id",
+    )?;
+    let rows = stmt
+      .query_map(params![session_id, seq], |row| {
+        Ok(CheckpointRow {
+          session_id: row.get(0)?,
+          tool_use_id: row.get(1)?,
+          seq: row.get(2)?,
+          path: row.get(3)?,
+          before: row.get(4)?,
+          after: row.get(5)?,
+          created_at: row.get(6)?,
         })
       })?
       .collect::<Result<Vec<_>, _>>()?;
     Ok(rows)
   }
+
+  /// Full-text search over every indexed message, best matches first.
+  pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+    let Some(fts_query) = search::fts_query(query) else {
+      return Ok(Vec::new());
+    };
+    let mut stmt = self.conn.prepare(
+      "select session_id, seq, kind, snippet(message_search, 3, ?2, ?3, '…', 16)
+       from message_search where message_search match ?1
+       order by bm25(message_search) limit ?4",
+    )?;
+    let rows = stmt
+      .query_map(
+        params![fts_query, HIGHLIGHT_START.to_string(), HIGHLIGHT_END.to_string(), limit as i64],
+        |row| {
+          let (snippet, highlights) = search::split_highlights(&row.get::<_, String>(3)?);
+          Ok(SearchHit { session_id: row.get(0)?, seq: row.get(1)?, kind: row.get(2)?, snippet, highlights })
+        },
+      )?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+  }
+
+  pub fn record_usage(&self, usage: &UsageRow) -> Result<()> {
+    self.conn.execute(
+      "insert into model_usage(session_id,provider,model,input_tokens,output_tokens,cache_read_tokens,cache_write_tokens,cost_usd,created_at)
+       values (?1,?2,?3,?4,?5,?6,?7,?8,?9)",
+      params![
+        usage.session_id,
+        usage.provider,
+        usage.model,
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_read_tokens,
+        usage.cache_write_tokens,
+        usage.cost_usd,
+        usage.created_at,
+      ],
+    )?;
+    Ok(())
+  }
+
+  pub fn session_usage(&self, session_id: &str) -> Result<UsageTotalsRow> {
+    self.conn.query_row(
+      &format!("select {} from model_usage where session_id = ?1", USAGE_SUMS),
+      [session_id],
+      |row| totals_from_row(row, 0),
+    )
+  }
+
+  pub fn usage_by_session(&self) -> Result<HashMap<String, UsageTotalsRow>> {
+    let mut stmt = self
+      .conn
+      .prepare(&format!("select session_id, {} from model_usage group by session_id", USAGE_SUMS))?;
+    let rows = stmt
+      .query_map([], |row| Ok((row.get(0)?, totals_from_row(row, 1)?)))?
+      .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(rows)
+  }
+
+  /// Totals per local calendar day (`YYYY-MM-DD`) for calls at or after `since`, newest day first.
+  pub fn usage_by_day(&self, since: i64) -> Result<Vec<(String, UsageTotalsRow)>> {
+    let mut stmt = self.conn.prepare(&format!(
+      "select date(created_at / 1000, 'unixepoch', 'localtime') as day, {}
+       from model_usage where created_at >= ?1 group by day order by day desc",
+      USAGE_SUMS
+    ))?;
+    let rows = stmt
+      .query_map([since], |row| Ok((row.get(0)?, totals_from_row(row, 1)?)))?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+  }
+
+  pub fn list_messages(&self, session_id: &str) -> Result<Vec<MessageRow>> {
+    let mut stmt = self
+      .conn
+      .prepare("select seq,payload,created_at from messages where session_id = ?1 order by seq")?;
+    let rows = stmt
+      .query_map([session_id], |row| {
+        Ok(MessageRow {
+          seq: row.get(0)?,
+          payload: row.get(1)?,
+          created_at: row.get(2)?,
+        })
+      })?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+  }
+}
+
+fn totals_from_row(row: &Row<'_>, offset: usize) -> Result<UsageTotalsRow> {
+  Ok(UsageTotalsRow {
+    calls: row.get(offset)?,
+    input_tokens: row.get(offset + 1)?,
+    output_tokens: row.get(offset + 2)?,
+    cache_read_tokens: row.get(offset + 3)?,
+    cache_write_tokens: row.get(offset + 4)?,
+    cost_usd: row.get(offset + 5)?,
+    unpriced_calls: row.get(offset + 6)?,
+  })
+}
+
+fn session_from_row(row: &Row<'_>) -> Result<SessionRow> {
+  Ok(SessionRow {
+    id: row.get(0)?,
+    title: row.get(1)?,
+    status: row.get(2)?,
+    cwd: row.get(3)?,
+    claude_session_id: row.get(4)?,
+    provider: row.get(5)?,
+    model: row.get(6)?,
+    base_url: row.get(7)?,
+    permission_mode: row.get(8)?,
+    created_at: row.get(9)?,
+    updated_at: row.get(10)?,
+    provider_options: row.get(11)?,
+    generation_params: row.get(12)?,
+    allowed_tools: row.get(13)?,
+  })
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use tempfile::tempdir;
+
+  fn row(id: &str, updated_at: i64) -> SessionRow {
+    SessionRow {
+      id: id.into(),
+      title: "Test".into(),
+      status: "idle".into(),
+      cwd: Some("/tmp/project".into()),
+      claude_session_id: None,
+      provider: Some("anthropic".into()),
+      model: Some("claude-test".into()),
+      base_url: None,
+      permission_mode: Some("ask".into()),
+      created_at: 1,
+      updated_at,
+      provider_options: Some("{\"openaiApi\":\"responses\"}".into()),
+      generation_params: Some("{\"maxTokens\":4096}".into()),
+      allowed_tools: Some("[\"Read\"]".into()),
+    }
+  }
 
   #[test]
   fn insert_and_list() {
     let store = SessionStore::new_in_memory().unwrap();
-    store.insert_session("s1", "Test", "idle").unwrap();
+    store.insert_session(&row("s2", 20)).unwrap();
+    store.insert_session(&row("s1", 10)).unwrap();
     let rows = store.list_sessions().unwrap();
-    assert_eq!(rows.len(), 1);
+    assert_eq!(rows.len(), 2);
     assert_eq!(rows[0].id, "s1");
+    assert_eq!(rows[0].cwd.as_deref(), Some("/tmp/project"));
+  }
+
+  #[test]
+  fn update_keeps_unset_fields() {
+    let store = SessionStore::new_in_memory().unwrap();
+    store.insert_session(&row("s1", 10)).unwrap();
+    assert!(store.update_session("s1", "running", None, None, 30).unwrap());
+    let got = store.get_session("s1").unwrap().unwrap();
+    assert_eq!(got.status, "running");
+    assert_eq!(got.title, "Test");
+    assert_eq!(got.updated_at, 30);
+    assert!(!store.update_session("missing", "idle", None, None, 30).unwrap());
+  }
+
+  #[test]
+  fn messages_append_in_order_and_delete_with_session() {
+    let mut store = SessionStore::new_in_memory().unwrap();
+    store.insert_session(&row("s1", 10)).unwrap();
+    assert_eq!(store.append_message("s1", "{\"n\":0}", 1).unwrap(), 0);
+    assert_eq!(store.append_message("s1", "{\"n\":1}", 2).unwrap(), 1);
+    let messages = store.list_messages("s1").unwrap();
+    assert_eq!(messages.iter().map(|m| m.seq).collect::<Vec<_>>(), vec![0, 1]);
+
+    store.delete_session("s1").unwrap();
+    assert!(store.get_session("s1").unwrap().is_none());
+    assert!(store.list_messages("s1").unwrap().is_empty());
+  }
+
+  #[test]
+  fn search_returns_highlighted_snippets_with_offsets() {
+    let mut store = SessionStore::new_in_memory().unwrap();
+    store.insert_session(&row("s1", 10)).unwrap();
+    store
+      .append_message("s1", r#"{"type":"user_prompt","prompt":"please fix the migration script"}"#, 1)
+      .unwrap();
+    store
+      .append_message(
+        "s1",
+        r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"migration applied","is_error":false}]}}"#,
+        2,
+      )
+      .unwrap();
+
+    let hits = store.search("migration script", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].seq, 0);
+    assert_eq!(hits[0].kind, "user_prompt");
+    assert_eq!(hits[0].snippet, "please fix the migration script");
+    assert_eq!(hits[0].highlights, vec![[15, 24], [25, 31]]);
+
+    assert_eq!(store.search("migration", 10).unwrap().len(), 2);
+    assert!(store.search("\"unbalanced", 10).unwrap().is_empty());
+
+    store.delete_session("s1").unwrap();
+    assert!(store.search("migration", 10).unwrap().is_empty());
+  }
+
+  fn usage(session_id: &str, cost_usd: Option<f64>, created_at: i64) -> UsageRow {
+    UsageRow {
+      session_id: session_id.into(),
+      provider: Some("anthropic".into()),
+      model: Some("claude-test".into()),
+      input_tokens: 100,
+      output_tokens: 20,
+      cache_read_tokens: 5,
+      cache_write_tokens: 1,
+      cost_usd,
+      created_at,
+    }
+  }
+
+  #[test]
+  fn usage_totals_by_session_and_day() {
+    const DAY_MS: i64 = 86_400_000;
+    let mut store = SessionStore::new_in_memory().unwrap();
+    store.insert_session(&row("s1", 10)).unwrap();
+    store.insert_session(&row("s2", 10)).unwrap();
+    store.record_usage(&usage("s1", Some(0.25), 10 * DAY_MS)).unwrap();
+    store.record_usage(&usage("s1", None, 10 * DAY_MS + 1)).unwrap();
+    store.record_usage(&usage("s2", Some(1.0), 12 * DAY_MS)).unwrap();
+
+    let s1 = store.session_usage("s1").unwrap();
+    assert_eq!(s1.calls, 2);
+    assert_eq!(s1.input_tokens, 200);
+    assert_eq!(s1.cache_write_tokens, 2);
+    assert_eq!(s1.cost_usd, 0.25);
+    assert_eq!(s1.unpriced_calls, 1);
+    assert_eq!(store.session_usage("missing").unwrap(), UsageTotalsRow::default());
+    assert_eq!(store.usage_by_session().unwrap()["s2"].calls, 1);
+
+    let days = store.usage_by_day(0).unwrap();
+    assert_eq!(days.iter().map(|(_, totals)| totals.calls).collect::<Vec<_>>(), vec![1, 2]);
+    assert!(days[0].0 > days[1].0);
+    assert_eq!(store.usage_by_day(11 * DAY_MS).unwrap().len(), 1);
+
+    store.delete_session("s1").unwrap();
+    assert_eq!(store.session_usage("s1").unwrap().calls, 0);
+  }
+
+  fn checkpoint(seq: i64, path: &str) -> CheckpointRow {
+    CheckpointRow {
+      session_id: "s1".into(),
+      tool_use_id: format!("tool-{}", seq),
+      seq,
+      path: path.into(),
+      before: None,
+      after: Some(b"new".to_vec()),
+      created_at: 1,
+    }
+  }
+
+  #[test]
+  fn truncate_drops_later_messages_and_checkpoints() {
+    let store = SessionStore::new_in_memory().unwrap();
+    store.insert_session(&row("s1", 10)).unwrap();
+    for prompt in ["first deploy", "second deploy", "third deploy"] {
+      store.append_message("s1", &format!(r#"{{"type":"user_prompt","prompt":"{}"}}"#, prompt), 1).unwrap();
+    }
+    store.record_checkpoint(&checkpoint(0, "/w/a.txt")).unwrap();
+    store.record_checkpoint(&checkpoint(2, "/w/b.txt")).unwrap();
+    assert_eq!(store.checkpoints_since("s1", 1).unwrap(), vec![checkpoint(2, "/w/b.txt")]);
+
+    store.truncate_messages("s1", 1).unwrap();
+    assert_eq!(store.next_seq("s1").unwrap(), 1);
+    assert_eq!(store.search("deploy", 10).unwrap().len(), 1);
+    assert!(store.checkpoints_since("s1", 1).unwrap().is_empty());
+    assert_eq!(store.checkpoints_since("s1", 0).unwrap().len(), 1);
+  }
+
+  #[test]
+  fn file_store_survives_reopen() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("sessions.db");
+    {
+      let store = SessionStore::open(&path).unwrap();
+      store.insert_session(&row("s1", 10)).unwrap();
+      store.append_message("s1", "{}", 1).unwrap();
+    }
+    let store = SessionStore::open(&path).unwrap();
+    assert_eq!(store.list_sessions().unwrap().len(), 1);
+    assert_eq!(store.list_messages("s1").unwrap().len(), 1);
   }
 }
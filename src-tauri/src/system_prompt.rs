@@ -0,0 +1,183 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::tools::ToolSpec;
+
+/// Checked in every directory from the filesystem root down to the session cwd.
+pub const INSTRUCTION_FILES: &[&str] = &["AGENTS.md", "CLAUDE.md", ".open-cowork/instructions.md"];
+/// Larger instruction files are cut so one stray file cannot fill the context window.
+const MAX_INSTRUCTION_BYTES: usize = 64 * 1024;
+
+const BASE_PROMPT: &str = "You are open-cowork, an agent that helps the user with software engineering and other \
+tasks on their computer. Use the available tools to inspect files, run commands and make changes; prefer reading \
+code before editing it, keep changes focused on what was asked, and report results faithfully, including \
+failures. Relative paths are resolved against the working directory. Answer in the language the user writes in.";
+
+/// Appended while the session is in plan mode.
+pub const PLAN_MODE_PROMPT: &str = "Plan mode is active. The user wants to review your approach before anything \
+changes: explore with the read-only tools (Read, Glob, Grep, WebFetch) and ask questions if needed, but do not try \
+to edit files or run commands; those calls are refused. When you are ready, call ExitPlanMode with a concrete plan. \
+Once the user approves it, plan mode ends and you can carry it out.";
+
+/// An instruction file found above (or in) the working directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionFile {
+  pub path: PathBuf,
+  pub content: String,
+}
+
+/// Composes the base prompt, tool summary, environment and project instructions for one request.
+pub fn build(cwd: Option<&str>, tools: &[ToolSpec]) -> String {
+  let cwd = cwd.map(str::trim).filter(|cwd| !cwd.is_empty()).map(Path::new);
+  let mut sections = vec![BASE_PROMPT.to_string()];
+
+  if !tools.is_empty() {
+    let lines: Vec<String> = tools
+      .iter()
+      .map(|tool| format!("- {}: {}", tool.name, tool.description))
+      .collect();
+    sections.push(format!("Available tools:\n{}", lines.join("\n")));
+  }
+
+  sections.push(environment(cwd, &today_utc()));
+
+  let instructions = cwd.map(find_instruction_files).unwrap_or_default();
+  if !instructions.is_empty() {
+    let mut text = String::from(
+      "Project instructions follow. Files closer to the working directory take precedence over those above it.",
+    );
+    for file in &instructions {
+      let path = file.path.display();
+      text.push_str(&format!("\n\n<instructions path=\"{}\">\n{}\n</instructions>", path, file.content.trim()));
+    }
+    sections.push(text);
+  }
+
+  sections.join("\n\n")
+}
+
+fn environment(cwd: Option<&Path>, today: &str) -> String {
+  let mut lines = Vec::new();
+  match cwd {
+    Some(cwd) => {
+      lines.push(format!("Working directory: {}", cwd.display()));
+      let in_git = cwd.ancestors().any(|dir| dir.join(".git").exists());
+      lines.push(format!("Is a git repository: {}", if in_git { "yes" } else { "no" }));
+    }
+    None => lines.push("Working directory: not set".to_string()),
+  }
+  lines.push(format!("Platform: {} ({})", std::env::consts::OS, std::env::consts::ARCH));
+  if let Some(shell) = std::env::var("SHELL").ok().filter(|shell| !shell.is_empty()) {
+    lines.push(format!("Shell: {}", shell));
+  }
+  lines.push(format!("Today's date (UTC): {}", today));
+  format!("<env>\n{}\n</env>", lines.join("\n"))
+}
+
+/// Instruction files from the outermost directory inwards, so later entries are the more specific.
+pub fn find_instruction_files(cwd: &Path) -> Vec<InstructionFile> {
+  let start = cwd.canonicalize().unwrap_or_else(|_| cwd.to_path_buf());
+  let mut dirs: Vec<&Path> = start.ancestors().collect();
+  dirs.reverse();
+
+  let mut files = Vec::new();
+  for dir in dirs {
+    for name in INSTRUCTION_FILES {
+      let path = dir.join(name);
+      if !path.is_file() {
+        continue;
+      }
+      match std::fs::read(&path) {
+        Ok(bytes) => files.push(InstructionFile { content: decode_capped(&bytes), path }),
+        Err(error) => log::warn!("failed to read {}: {}", path.display(), error),
+      }
+    }
+  }
+  files
+}
+
+fn decode_capped(bytes: &[u8]) -> String {
+  if bytes.len() <= MAX_INSTRUCTION_BYTES {
+    return String::from_utf8_lossy(bytes).into_owned();
+  }
+  let mut text = String::from_utf8_lossy(&bytes[..MAX_INSTRUCTION_BYTES]).into_owned();
+  text.push_str(&format!("\n[truncated: file is {} bytes]", bytes.len()));
+  text
+}
+
+fn today_utc() -> String {
+  let secs = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0);
+  let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+  format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::tempdir;
+
+  #[test]
+  fn civil_from_days_handles_epoch_and_leap_days() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    assert_eq!(civil_from_days(20_743), (2026, 10, 17));
+  }
+
+  #[test]
+  fn instruction_files_are_ordered_outermost_first() {
+    let root = tempdir().unwrap();
+    let nested = root.path().join("app/src");
+    std::fs::create_dir_all(nested.join(".open-cowork")).unwrap();
+    std::fs::write(root.path().join("AGENTS.md"), "root rules").unwrap();
+    std::fs::write(root.path().join("app/CLAUDE.md"), "app rules").unwrap();
+    std::fs::write(nested.join(".open-cowork/instructions.md"), "src rules").unwrap();
+
+    let contents: Vec<String> =
+      find_instruction_files(&nested).into_iter().map(|file| file.content).collect();
+    assert_eq!(contents, vec!["root rules", "app rules", "src rules"]);
+  }
+
+  #[test]
+  fn build_includes_environment_tools_and_instructions() {
+    let dir = tempdir().unwrap();
+    std::fs::write(dir.path().join("AGENTS.md"), "Run cargo fmt before committing.").unwrap();
+    let tools = vec![ToolSpec {
+      name: "Read",
+      description: "Read a file from disk.",
+      parameters: serde_json::json!({}),
+    }];
+
+    let prompt = build(dir.path().to_str(), &tools);
+    assert!(prompt.starts_with(BASE_PROMPT));
+    assert!(prompt.contains("- Read: Read a file from disk."));
+    assert!(prompt.contains(&format!("Working directory: {}", dir.path().display())));
+    assert!(prompt.contains("Run cargo fmt before committing."));
+
+    let bare = build(None, &[]);
+    assert!(bare.contains("Working directory: not set"));
+    assert!(!bare.contains("Project instructions"));
+  }
+
+  #[test]
+  fn oversized_files_are_truncated() {
+    let text = decode_capped(&vec![b'a'; MAX_INSTRUCTION_BYTES + 10]);
+    assert!(text.ends_with(&format!("[truncated: file is {} bytes]", MAX_INSTRUCTION_BYTES + 10)));
+  }
+}
@@ -1,23 +1,68 @@
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 use super::ToolExecutionResult;
 
-pub fn run_command(command: &str, cwd: Option<&str>) -> Result<ToolExecutionResult, String> {
-  let mut cmd = Command::new("sh");
-  cmd.arg("-lc").arg(command);
+/// Runs `command` through the shell. Dropping the returned future kills the child process and,
+/// on Unix, everything it started (pipelines, `cmd &`, grandchildren), which is how a stopped
+/// session or a timeout interrupts a long-running command.
+pub async fn run_command(command: &str, cwd: Option<&str>) -> Result<ToolExecutionResult, String> {
+  let mut cmd = tokio::process::Command::new("sh");
+  cmd
+    .arg("-lc")
+    .arg(command)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true);
+  // Its own process group, so the whole tree can be signalled at once.
+  #[cfg(unix)]
+  cmd.process_group(0);
   if let Some(cwd) = cwd {
     cmd.current_dir(cwd);
   }
-  let output = cmd.output().map_err(|e| format!("Command failed: {}", e))?;
+  let child = cmd.spawn().map_err(|e| format!("Command failed: {}", e))?;
+  let group = ProcessGroup::new(child.id());
+  let output = child.wait_with_output().await.map_err(|e| format!("Command failed: {}", e))?;
+  group.disarm();
   let stdout = String::from_utf8_lossy(&output.stdout).to_string();
   let stderr = String::from_utf8_lossy(&output.stderr).to_string();
   let combined = format_output(&stdout, &stderr);
   Ok(ToolExecutionResult {
     content: combined,
     is_error: !output.status.success(),
+    attachments: Vec::new(),
   })
 }
 
+/// Kills a command's process group when dropped before `disarm`, i.e. when the run is abandoned.
+struct ProcessGroup {
+  #[cfg_attr(not(unix), allow(dead_code))]
+  id: Option<u32>,
+}
+
+impl ProcessGroup {
+  fn new(leader: Option<u32>) -> Self {
+    Self { id: leader }
+  }
+
+  /// The command finished on its own; anything it left running in the background stays.
+  fn disarm(mut self) {
+    self.id = None;
+  }
+}
+
+impl Drop for ProcessGroup {
+  fn drop(&mut self) {
+    #[cfg(unix)]
+    if let Some(pgid) = self.id.and_then(|id| libc::pid_t::try_from(id).ok()) {
+      // SAFETY: kill(2) takes no pointers; a negative pid addresses the process group.
+      unsafe {
+        libc::kill(-pgid, libc::SIGKILL);
+      }
+    }
+  }
+}
+
 pub fn grep(pattern: &str, path: Option<&str>) -> Result<ToolExecutionResult, String> {
   let mut cmd = Command::new("rg");
   cmd.arg("--line-number").arg("--no-heading").arg(pattern);
@@ -27,13 +72,13 @@ pub fn grep(pattern: &str, path: Option<&str>) -> Result<ToolExecutionResult, St
   let output = cmd.output().map_err(|e| format!("rg failed: {}", e))?;
   if !output.status.success() {
     if output.status.code() == Some(1) {
-      return Ok(ToolExecutionResult { content: String::new(), is_error: false });
+      return Ok(ToolExecutionResult { content: String::new(), is_error: false, attachments: Vec::new() });
     }
     let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-    return Ok(ToolExecutionResult { content: stderr, is_error: true });
+    return Ok(ToolExecutionResult { content: stderr, is_error: true, attachments: Vec::new() });
   }
   let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-  Ok(ToolExecutionResult { content: stdout, is_error: false })
+  Ok(ToolExecutionResult { content: stdout, is_error: false, attachments: Vec::new() })
 }
 
 fn format_output(stdout: &str, stderr: &str) -> String {
@@ -44,3 +89,52 @@ fn format_output(stdout: &str, stderr: &str) -> String {
     (false, false) => format!("{}\n{}", stdout, stderr),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, Instant};
+
+  #[tokio::test]
+  async fn run_command_captures_output_and_status() {
+    let ok = run_command("echo hello", None).await.unwrap();
+    assert_eq!(ok.content.trim(), "hello");
+    assert!(!ok.is_error);
+
+    let failed = run_command("echo oops >&2; exit 3", None).await.unwrap();
+    assert_eq!(failed.content.trim(), "oops");
+    assert!(failed.is_error);
+  }
+
+  #[tokio::test]
+  async fn dropping_run_command_does_not_wait_for_the_child() {
+    let started = Instant::now();
+    let result = tokio::time::timeout(Duration::from_millis(200), run_command("sleep 5", None)).await;
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+  }
+
+  #[cfg(target_os = "linux")]
+  #[tokio::test]
+  async fn dropping_run_command_kills_background_processes() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("pid");
+    let command = format!("sh -c 'sleep 30 & echo $! > {}; wait'", pid_file.display());
+    let result = tokio::time::timeout(Duration::from_millis(500), run_command(&command, None)).await;
+    assert!(result.is_err());
+
+    let pid = std::fs::read_to_string(&pid_file).unwrap().trim().to_string();
+    // A killed process may linger as a zombie until it is reaped; that counts as gone.
+    let alive = || {
+      std::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|stat| stat.rsplit(')').next().and_then(|rest| rest.split_whitespace().next()).map(str::to_string))
+        .is_some_and(|state| state != "Z")
+    };
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while alive() && Instant::now() < deadline {
+      std::thread::sleep(Duration::from_millis(20));
+    }
+    assert!(!alive(), "background sleep {} survived", pid);
+  }
+}
@@ -1,23 +1,223 @@
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek};
 use std::path::Path;
 
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use glob::glob;
+use similar::TextDiff;
 
-pub fn read_file(path: &Path) -> Result<String, String> {
-  fs::read_to_string(path).map_err(|e| format!("Read failed: {}", e))
+use crate::providers::attachments::{sniff_media_type, Attachment, MAX_DOCUMENT_BYTES, MAX_IMAGE_BYTES};
+
+/// Bytes inspected to tell text from binary data.
+const SNIFF_BYTES: usize = 8 * 1024;
+/// Lines returned by `Read` when no `limit` is given.
+pub const DEFAULT_READ_LIMIT: usize = 2_000;
+/// Longer lines (minified code, logs) are cut to this many characters.
+pub const MAX_LINE_CHARS: usize = 2_000;
+/// Cap on the numbered output of a single `Read`, whatever the limit.
+pub const MAX_READ_CHARS: usize = 100_000;
+
+#[derive(Debug, PartialEq)]
+pub enum FileContent {
+  /// Numbered lines, as rendered by `number_lines`.
+  Text(String),
+  /// An image or PDF, handed to the model as content rather than text.
+  Binary(Attachment),
+}
+
+/// Reads `path` for the `Read` tool. Only the first bytes are sniffed up front: images and PDFs
+/// are size-checked before being loaded, and text is streamed up to the requested lines, so a
+/// multi-GB log costs no more memory than a page of it.
+pub fn read_file(path: &Path, offset: usize, limit: usize) -> Result<FileContent, String> {
+  let read_error = |e: std::io::Error| format!("Read failed: {}", e);
+  let mut file = File::open(path).map_err(read_error)?;
+  let size = file.metadata().map_err(read_error)?.len();
+  let mut head = Vec::with_capacity(SNIFF_BYTES);
+  (&mut file).take(SNIFF_BYTES as u64).read_to_end(&mut head).map_err(read_error)?;
+  let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+  if let Some(media_type) = sniff_media_type(&head) {
+    let limit = if media_type == "application/pdf" { MAX_DOCUMENT_BYTES } else { MAX_IMAGE_BYTES };
+    if size > limit as u64 {
+      return Err(format!(
+        "{} is too large to read ({} KB, limit {} KB).",
+        path.display(),
+        size.div_ceil(1024),
+        limit / 1024
+      ));
+    }
+    file.rewind().map_err(read_error)?;
+    let mut bytes = Vec::new();
+    file.take(limit as u64 + 1).read_to_end(&mut bytes).map_err(read_error)?;
+    let data = STANDARD.encode(&bytes);
+    return Ok(FileContent::Binary(Attachment { name, media_type: media_type.into(), data }));
+  }
+  if head.contains(&0) {
+    return Err(format!(
+      "{} is a binary file ({} bytes); Read supports text, PNG/JPEG/GIF/WebP images and PDFs.",
+      path.display(),
+      size
+    ));
+  }
+  file.rewind().map_err(read_error)?;
+  number_lines(BufReader::new(file), offset, limit).map(FileContent::Text)
+}
+
+/// Renders `limit` lines starting at the 1-based line `offset` in `cat -n` style, cutting long
+/// lines and stopping at `MAX_READ_CHARS`; a notice at the end says how to read the rest.
+/// Lines are read one at a time and reading stops once the page is full.
+pub fn number_lines(mut reader: impl BufRead, offset: usize, limit: usize) -> Result<String, String> {
+  let start = offset.max(1);
+  let mut line = Vec::new();
+  let mut next_line = |line: &mut Vec<u8>| {
+    read_line_capped(&mut reader, line, (MAX_LINE_CHARS + 1) * 4).map_err(|e| format!("Read failed: {}", e))
+  };
+  let mut end = 0;
+  while end + 1 < start && next_line(&mut line)? {
+    end += 1;
+  }
+
+  let mut output = String::new();
+  let mut cut_lines = 0;
+  let mut more = false;
+  while next_line(&mut line)? {
+    if end + 1 - start == limit {
+      more = true;
+      break;
+    }
+    // Mostly-text files in a legacy encoding are still worth showing.
+    let text = String::from_utf8_lossy(&line);
+    let text = match text.char_indices().nth(MAX_LINE_CHARS) {
+      Some((cut, _)) => {
+        cut_lines += 1;
+        format!("{} [line truncated]", &text[..cut])
+      }
+      None => text.into_owned(),
+    };
+    let entry = format!("{:>6}\t{}\n", end + 1, text);
+    if end >= start && output.len() + entry.len() > MAX_READ_CHARS {
+      more = true;
+      break;
+    }
+    output.push_str(&entry);
+    end += 1;
+  }
+  if end < start && !more {
+    return match end {
+      0 => Ok("(empty file)".into()),
+      total => Err(format!("Offset {} is past the end of the file ({} lines).", offset, total)),
+    };
+  }
+  output.pop();
+
+  let mut notices = Vec::new();
+  if more {
+    notices.push(format!("[Showing lines {}-{}. Use offset={} to read more.]", start, end, end + 1));
+  }
+  if cut_lines > 0 {
+    notices.push(format!("[{} lines longer than {} characters were truncated.]", cut_lines, MAX_LINE_CHARS));
+  }
+  if !notices.is_empty() {
+    output.push_str("\n\n");
+    output.push_str(&notices.join("\n"));
+  }
+  Ok(output)
+}
+
+/// Reads one line into `line` without its line ending, keeping at most `cap` bytes of it so a
+/// file without newlines cannot exhaust memory. Returns false at the end of the input.
+fn read_line_capped(reader: &mut impl BufRead, line: &mut Vec<u8>, cap: usize) -> std::io::Result<bool> {
+  line.clear();
+  let mut read_any = false;
+  loop {
+    let buf = reader.fill_buf()?;
+    if buf.is_empty() {
+      break;
+    }
+    read_any = true;
+    let newline = buf.iter().position(|&byte| byte == b'\n');
+    let chunk = &buf[..newline.unwrap_or(buf.len())];
+    let keep = chunk.len().min(cap.saturating_sub(line.len()));
+    line.extend_from_slice(&chunk[..keep]);
+    let used = newline.map_or(buf.len(), |index| index + 1);
+    reader.consume(used);
+    if newline.is_some() {
+      break;
+    }
+  }
+  if line.last() == Some(&b'\r') {
+    line.pop();
+  }
+  Ok(read_any)
 }
 
 pub fn write_file(path: &Path, content: &str) -> Result<(), String> {
   fs::write(path, content).map_err(|e| format!("Write failed: {}", e))
 }
 
-pub fn edit_file(path: &Path, old_string: &str, new_string: &str) -> Result<(), String> {
+/// One string replacement; `old_string` must occur exactly once unless `replace_all` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+  pub old_string: String,
+  pub new_string: String,
+  pub replace_all: bool,
+}
+
+/// Applies `edits` in order and writes the file only if every one of them matched, so a failed
+/// edit leaves it untouched. Returns the number of replacements made.
+pub fn edit_file(path: &Path, edits: &[Edit]) -> Result<usize, String> {
   let content = fs::read_to_string(path).map_err(|e| format!("Read failed: {}", e))?;
-  if !content.contains(old_string) {
-    return Err("Old string not found in file.".into());
+  let (updated, replaced) = apply_edits(&content, edits)?;
+  fs::write(path, updated).map_err(|e| format!("Write failed: {}", e))?;
+  Ok(replaced)
+}
+
+/// The file contents after `edits`, and the number of replacements made.
+pub fn apply_edits(content: &str, edits: &[Edit]) -> Result<(String, usize), String> {
+  let mut content = content.to_string();
+  let mut replaced = 0;
+  for (index, edit) in edits.iter().enumerate() {
+    let (updated, count) = apply_edit(&content, edit).map_err(|error| {
+      if edits.len() > 1 {
+        format!("Edit {} of {} failed: {} No changes were made.", index + 1, edits.len(), error)
+      } else {
+        error
+      }
+    })?;
+    content = updated;
+    replaced += count;
   }
-  let updated = content.replacen(old_string, new_string, 1);
-  fs::write(path, updated).map_err(|e| format!("Write failed: {}", e))
+  Ok((content, replaced))
+}
+
+fn apply_edit(content: &str, edit: &Edit) -> Result<(String, usize), String> {
+  if edit.old_string.is_empty() {
+    return Err("old_string must not be empty.".into());
+  }
+  if edit.old_string == edit.new_string {
+    return Err("old_string and new_string are identical.".into());
+  }
+  let count = content.matches(edit.old_string.as_str()).count();
+  match count {
+    0 => Err("Old string not found in file.".into()),
+    1 => Ok((content.replacen(&edit.old_string, &edit.new_string, 1), 1)),
+    _ if edit.replace_all => Ok((content.replace(&edit.old_string, &edit.new_string), count)),
+    _ => Err(format!(
+      "Old string matches {} times in the file. Include more surrounding context to make it unique, \
+       or set replace_all to replace every occurrence.",
+      count
+    )),
+  }
+}
+
+/// `diff -u` style comparison of two versions of `path`, with three lines of context.
+pub fn unified_diff(path: &Path, old: &str, new: &str) -> String {
+  let label = path.display().to_string();
+  TextDiff::from_lines(old, new)
+    .unified_diff()
+    .context_radius(3)
+    .header(&format!("a/{}", label.trim_start_matches('/')), &format!("b/{}", label.trim_start_matches('/')))
+    .to_string()
 }
 
 pub fn glob_paths(pattern: &str, base: Option<&str>) -> Result<Vec<String>, String> {
@@ -45,6 +245,7 @@ pub fn glob_paths(pattern: &str, base: Option<&str>) -> Result<Vec<String>, Stri
 #[cfg(test)]
 mod tests {
   use super::*;
+  use std::io::Write;
   use tempfile::tempdir;
 
   #[test]
@@ -52,7 +253,108 @@ mod tests {
     let dir = tempdir().unwrap();
     let file = dir.path().join("a.txt");
     fs::write(&file, "hello").unwrap();
-    let got = read_file(&file).unwrap();
-    assert_eq!(got, "hello");
+    let got = read_file(&file, 1, DEFAULT_READ_LIMIT).unwrap();
+    assert_eq!(got, FileContent::Text("     1\thello".into()));
+  }
+
+  #[test]
+  fn read_file_returns_images_and_rejects_other_binaries() {
+    let dir = tempdir().unwrap();
+    let image = dir.path().join("shot.png");
+    fs::write(&image, b"\x89PNG\r\n\x1a\nrest").unwrap();
+    match read_file(&image, 1, DEFAULT_READ_LIMIT).unwrap() {
+      FileContent::Binary(attachment) => {
+        assert_eq!(attachment.name, "shot.png");
+        assert_eq!(attachment.media_type, "image/png");
+        assert_eq!(STANDARD.decode(&attachment.data).unwrap(), b"\x89PNG\r\n\x1a\nrest");
+      }
+      other => panic!("expected an image, got {:?}", other),
+    }
+
+    let archive = dir.path().join("a.zip");
+    fs::write(&archive, b"PK\x03\x04\x00\x00").unwrap();
+    assert!(read_file(&archive, 1, DEFAULT_READ_LIMIT).unwrap_err().contains("binary file"));
+  }
+
+  #[test]
+  fn read_file_checks_the_size_before_loading_an_image() {
+    let dir = tempdir().unwrap();
+    let image = dir.path().join("huge.png");
+    let file = File::create(&image).unwrap();
+    (&file).write_all(b"\x89PNG\r\n\x1a\n").unwrap();
+    // Sparse, so the test does not actually write the bytes.
+    file.set_len(64 * 1024 * 1024 * 1024).unwrap();
+    assert!(read_file(&image, 1, DEFAULT_READ_LIMIT).unwrap_err().contains("too large to read"));
+  }
+
+  fn edit(old_string: &str, new_string: &str, replace_all: bool) -> Edit {
+    Edit { old_string: old_string.into(), new_string: new_string.into(), replace_all }
+  }
+
+  #[test]
+  fn edit_file_requires_a_unique_match() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.rs");
+    fs::write(&file, "let a = 1;\nlet b = 1;\n").unwrap();
+
+    let error = edit_file(&file, &[edit("= 1", "= 2", false)]).unwrap_err();
+    assert!(error.contains("matches 2 times"));
+    assert!(edit_file(&file, &[edit("= 3", "= 2", false)]).unwrap_err().contains("not found"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "let a = 1;\nlet b = 1;\n");
+
+    assert_eq!(edit_file(&file, &[edit("a = 1", "a = 2", false)]).unwrap(), 1);
+    assert_eq!(edit_file(&file, &[edit("let", "const", true)]).unwrap(), 2);
+    assert_eq!(fs::read_to_string(&file).unwrap(), "const a = 2;\nconst b = 1;\n");
+  }
+
+  #[test]
+  fn multi_edit_is_all_or_nothing() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "one two three").unwrap();
+
+    let error = edit_file(&file, &[edit("one", "1", false), edit("four", "4", false)]).unwrap_err();
+    assert!(error.starts_with("Edit 2 of 2 failed"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one two three");
+
+    // Later edits see the result of earlier ones.
+    edit_file(&file, &[edit("one", "1", false), edit("1 two", "1 2", false)]).unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "1 2 three");
+  }
+
+  #[test]
+  fn unified_diff_shows_changed_lines_with_context() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+    let new = "a\nb\nc\nd\nE\nf\ng\nh\n";
+    let diff = unified_diff(Path::new("/src/lib.rs"), old, new);
+    assert_eq!(diff, "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -2,7 +2,7 @@\n b\n c\n d\n-e\n+E\n f\n g\n h\n");
+    assert_eq!(unified_diff(Path::new("a.txt"), "same\n", "same\n"), "");
+  }
+
+  #[test]
+  fn number_lines_paginates() {
+    let text = "alpha\r\nbeta\ngamma\ndelta\n".as_bytes();
+    assert_eq!(number_lines(text, 1, DEFAULT_READ_LIMIT).unwrap(), "     1\talpha\n     2\tbeta\n     3\tgamma\n     4\tdelta");
+    assert_eq!(
+      number_lines(text, 2, 2).unwrap(),
+      "     2\tbeta\n     3\tgamma\n\n[Showing lines 2-3. Use offset=4 to read more.]"
+    );
+    assert_eq!(number_lines(text, 4, 1).unwrap(), "     4\tdelta");
+    assert_eq!(number_lines(text, 5, 10).unwrap_err(), "Offset 5 is past the end of the file (4 lines).");
+    assert!(number_lines(text, 9, 10).unwrap_err().contains("past the end"));
+    assert_eq!(number_lines("".as_bytes(), 1, 10).unwrap(), "(empty file)");
+  }
+
+  #[test]
+  fn number_lines_caps_long_lines_and_output() {
+    let long = format!("{}\nshort", "é".repeat(MAX_LINE_CHARS + 5));
+    let output = number_lines(long.as_bytes(), 1, 10).unwrap();
+    assert!(output.starts_with(&format!("     1\t{} [line truncated]\n", "é".repeat(MAX_LINE_CHARS))));
+    assert!(output.ends_with("[1 lines longer than 2000 characters were truncated.]"));
+
+    let many = format!("{}\n", "x".repeat(1_000)).repeat(500);
+    let output = number_lines(many.as_bytes(), 1, DEFAULT_READ_LIMIT).unwrap();
+    assert!(output.len() < MAX_READ_CHARS + 200);
+    assert!(output.ends_with("[Showing lines 1-99. Use offset=100 to read more.]"));
   }
 }
@@ -1,84 +1,309 @@
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::providers::attachments::Attachment;
+
 pub mod command_tools;
 pub mod fs_tools;
 pub mod web_tools;
+pub mod workspace;
+
+use workspace::Workspace;
+
+/// The tools plan mode runs without asking; everything else except `AskUserQuestion` is refused.
+pub const READ_ONLY_TOOLS: &[&str] = &["Read", "Glob", "Grep", "WebFetch"];
+
+/// Diffs are shown to the user and stored with the tool result; a full rewrite of a large file is cut here.
+const MAX_DIFF_CHARS: usize = 50_000;
 
 pub struct ToolExecutionResult {
   pub content: String,
   pub is_error: bool,
+  /// Images or documents returned alongside `content`, e.g. when `Read` opens a screenshot.
+  pub attachments: Vec<Attachment>,
+}
+
+/// Provider-neutral tool declaration; each provider adapter renders it in its own wire format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+  pub name: &'static str,
+  pub description: &'static str,
+  pub parameters: Value,
+}
+
+pub fn definitions() -> Vec<ToolSpec> {
+  vec![
+    tool_def(
+      "Read",
+      "Read a file from disk. Text comes back numbered like `cat -n` (the numbers are not part of the file), \
+       2000 lines at a time; pass offset and limit to page through larger files. Images (PNG, JPEG, GIF, WebP) \
+       and PDFs are returned as attachments.",
+      json!({
+        "type": "object",
+        "properties": {
+          "file_path": { "type": "string" },
+          "offset": { "type": "integer", "description": "1-based line number to start reading from." },
+          "limit": { "type": "integer", "description": "Number of lines to read (default 2000)." }
+        },
+        "required": ["file_path"]
+      }),
+    ),
+    tool_def("Write", "Write a file to disk.", json!({
+      "type": "object",
+      "properties": {
+        "file_path": { "type": "string" },
+        "content": { "type": "string" }
+      },
+      "required": ["file_path", "content"]
+    })),
+    tool_def(
+      "Edit",
+      "Replace a string in a file. old_string must match exactly once unless replace_all is true.",
+      json!({
+        "type": "object",
+        "properties": {
+          "file_path": { "type": "string" },
+          "old_string": { "type": "string" },
+          "new_string": { "type": "string" },
+          "replace_all": { "type": "boolean", "description": "Replace every occurrence of old_string." }
+        },
+        "required": ["file_path", "old_string", "new_string"]
+      }),
+    ),
+    tool_def(
+      "MultiEdit",
+      "Apply several Edit-style replacements to one file, in order. Either all edits apply or none do.",
+      json!({
+        "type": "object",
+        "properties": {
+          "file_path": { "type": "string" },
+          "edits": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "old_string": { "type": "string" },
+                "new_string": { "type": "string" },
+                "replace_all": { "type": "boolean" }
+              },
+              "required": ["old_string", "new_string"]
+            }
+          }
+        },
+        "required": ["file_path", "edits"]
+      }),
+    ),
+    tool_def("Bash", "Run a shell command.", json!({
+      "type": "object",
+      "properties": { "command": { "type": "string" } },
+      "required": ["command"]
+    })),
+    tool_def("Glob", "Find files matching a glob pattern.", json!({
+      "type": "object",
+      "properties": {
+        "pattern": { "type": "string" },
+        "path": { "type": "string" }
+      },
+      "required": ["pattern"]
+    })),
+    tool_def("Grep", "Search for text within files.", json!({
+      "type": "object",
+      "properties": {
+        "pattern": { "type": "string" },
+        "path": { "type": "string" }
+      },
+      "required": ["pattern"]
+    })),
+    tool_def("WebFetch", "Fetch a URL over HTTP.", json!({
+      "type": "object",
+      "properties": { "url": { "type": "string" } },
+      "required": ["url"]
+    })),
+    tool_def("Task", "Create a sub-task description.", json!({
+      "type": "object",
+      "properties": { "description": { "type": "string" } },
+      "required": ["description"]
+    })),
+    tool_def("AskUserQuestion", "Ask user clarifying questions.", ask_user_schema()),
+  ]
+}
+
+/// Only offered in plan mode; the call is answered by the user approving or rejecting the plan.
+pub fn exit_plan_mode_definition() -> ToolSpec {
+  tool_def(
+    "ExitPlanMode",
+    "Present your implementation plan to the user and ask to leave plan mode. Call it once you have \
+explored enough to propose concrete changes; if the user rejects the plan, revise it and call it again.",
+    json!({
+      "type": "object",
+      "properties": {
+        "plan": { "type": "string", "description": "The plan in Markdown: the steps and the files they touch." }
+      },
+      "required": ["plan"]
+    }),
+  )
+}
+
+fn tool_def(name: &'static str, description: &'static str, parameters: Value) -> ToolSpec {
+  ToolSpec { name, description, parameters }
+}
+
+fn ask_user_schema() -> Value {
+  json!({
+    "type": "object",
+    "properties": {
+      "questions": {
+        "type": "array",
+        "items": {
+          "type": "object",
+          "properties": {
+            "question": { "type": "string" },
+            "header": { "type": "string" },
+            "options": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "label": { "type": "string" },
+                  "description": { "type": "string" }
+                },
+                "required": ["label"]
+              }
+            },
+            "multiSelect": { "type": "boolean" }
+          },
+          "required": ["question"]
+        }
+      }
+    },
+    "required": ["questions"]
+  })
 }
 
+/// Runs one tool call. With a `workspace`, file tools refuse paths outside it; pass `None` once the
+/// user has approved the out-of-bounds access.
 pub async fn execute_tool(
   name: &str,
   input: &Value,
   cwd: Option<&str>,
+  workspace: Option<&Workspace>,
 ) -> Result<ToolExecutionResult, String> {
+  let check = |path: &Path| workspace.map_or(Ok(()), |workspace| workspace.check(path));
   match name {
     "Read" => {
       let file_path = get_required_string(input, "file_path")?;
+      let offset = get_optional_count(input, "offset")?.unwrap_or(1);
+      let limit = get_optional_count(input, "limit")?.unwrap_or(fs_tools::DEFAULT_READ_LIMIT);
       let path = resolve_path(&file_path, cwd);
-      let content = fs_tools::read_file(&path)?;
-      Ok(ToolExecutionResult { content, is_error: false })
+      check(&path)?;
+      match fs_tools::read_file(&path, offset, limit)? {
+        fs_tools::FileContent::Text(content) => Ok(ToolExecutionResult {
+          content,
+          is_error: false,
+          attachments: Vec::new(),
+        }),
+        fs_tools::FileContent::Binary(attachment) => Ok(ToolExecutionResult {
+          content: format!(
+            "Read {} ({}, {} KB); its contents are attached.",
+            path.display(),
+            attachment.media_type,
+            attachment.decoded_len().div_ceil(1024)
+          ),
+          is_error: false,
+          attachments: vec![attachment],
+        }),
+      }
     }
     "Write" => {
       let file_path = get_required_string(input, "file_path")?;
       let content = get_required_string(input, "content")?;
       let path = resolve_path(&file_path, cwd);
+      check(&path)?;
       fs_tools::write_file(&path, &content)?;
       Ok(ToolExecutionResult {
         content: format!("Wrote {} bytes to {}", content.len(), path.display()),
         is_error: false,
+        attachments: Vec::new(),
       })
     }
     "Edit" => {
       let file_path = get_required_string(input, "file_path")?;
-      let old_string = get_required_string(input, "old_string")?;
-      let new_string = get_required_string(input, "new_string")?;
       let path = resolve_path(&file_path, cwd);
-      fs_tools::edit_file(&path, &old_string, &new_string)?;
+      check(&path)?;
+      let replaced = fs_tools::edit_file(&path, &[parse_edit(input)?])?;
+      let content = if replaced > 1 {
+        format!("Updated {} ({} replacements)", path.display(), replaced)
+      } else {
+        format!("Updated {}", path.display())
+      };
+      Ok(ToolExecutionResult { content, is_error: false, attachments: Vec::new() })
+    }
+    "MultiEdit" => {
+      let file_path = get_required_string(input, "file_path")?;
+      let edits = input
+        .get("edits")
+        .and_then(Value::as_array)
+        .filter(|edits| !edits.is_empty())
+        .ok_or_else(|| "Missing required field: edits".to_string())?
+        .iter()
+        .map(parse_edit)
+        .collect::<Result<Vec<_>, _>>()?;
+      let path = resolve_path(&file_path, cwd);
+      check(&path)?;
+      let replaced = fs_tools::edit_file(&path, &edits)?;
       Ok(ToolExecutionResult {
-        content: format!("Updated {}", path.display()),
+        content: format!("Applied {} edits ({} replacements) to {}", edits.len(), replaced, path.display()),
         is_error: false,
+        attachments: Vec::new(),
       })
     }
     "Bash" => {
       let command = get_required_string(input, "command")?;
-      let result = command_tools::run_command(&command, cwd)?;
+      let result = command_tools::run_command(&command, cwd).await?;
       Ok(result)
     }
     "Glob" => {
       let pattern = get_required_string(input, "pattern")?;
-      let base = get_optional_string(input, "path")
-        .or_else(|| cwd.map(|value| value.to_string()));
-      let matches = fs_tools::glob_paths(&pattern, base.as_deref())?;
+      let base = search_base(input, cwd);
+      check(&glob_root(&pattern, base.as_deref()))?;
+      let base = base.map(|path| path.to_string_lossy().into_owned());
+      let mut matches = fs_tools::glob_paths(&pattern, base.as_deref())?;
+      // `..` after a wildcard can still lead out of the workspace.
+      if let Some(workspace) = workspace {
+        matches.retain(|path| workspace.contains(Path::new(path)));
+      }
       Ok(ToolExecutionResult {
         content: matches.join("\n"),
         is_error: false,
+        attachments: Vec::new(),
       })
     }
     "Grep" => {
       let pattern = get_required_string(input, "pattern")?;
-      let path = get_optional_string(input, "path")
-        .or_else(|| get_optional_string(input, "file_path"))
-        .or_else(|| cwd.map(|value| value.to_string()));
+      let path = search_base(input, cwd);
+      if let Some(path) = &path {
+        check(path)?;
+      }
+      let path = path.map(|path| path.to_string_lossy().into_owned());
       let result = command_tools::grep(&pattern, path.as_deref())?;
       Ok(result)
     }
     "WebFetch" => {
       let url = get_required_string(input, "url")?;
       let content = web_tools::fetch_url(&url).await?;
-      Ok(ToolExecutionResult { content, is_error: false })
+      Ok(ToolExecutionResult { content, is_error: false, attachments: Vec::new() })
     }
     "Task" => {
       let description = get_required_string(input, "description")?;
       Ok(ToolExecutionResult {
         content: format!("Task noted: {}", description),
         is_error: false,
+        attachments: Vec::new(),
       })
     }
     "AskUserQuestion" => Err("AskUserQuestion should be handled via permission workflow.".into()),
+    "ExitPlanMode" => Err("ExitPlanMode should be handled via permission workflow.".into()),
     other => Err(format!("Unsupported tool: {}", other)),
   }
 }
@@ -95,6 +320,104 @@ fn get_optional_string(input: &Value, key: &str) -> Option<String> {
   input.get(key).and_then(Value::as_str).map(|value| value.to_string())
 }
 
+fn parse_edit(input: &Value) -> Result<fs_tools::Edit, String> {
+  Ok(fs_tools::Edit {
+    old_string: get_required_string(input, "old_string")?,
+    new_string: get_required_string(input, "new_string")?,
+    replace_all: input.get("replace_all").and_then(Value::as_bool).unwrap_or(false),
+  })
+}
+
+/// Every path a file tool call would read or write, for checking against the workspace.
+pub fn accessed_paths(name: &str, input: &Value, cwd: Option<&str>) -> Vec<PathBuf> {
+  match name {
+    "Read" | "Write" | "Edit" | "MultiEdit" => get_optional_string(input, "file_path")
+      .map(|path| resolve_path(&path, cwd))
+      .into_iter()
+      .collect(),
+    "Glob" => get_optional_string(input, "pattern")
+      .map(|pattern| glob_root(&pattern, search_base(input, cwd).as_deref()))
+      .into_iter()
+      .collect(),
+    "Grep" => search_base(input, cwd).into_iter().collect(),
+    _ => Vec::new(),
+  }
+}
+
+/// The directory `Glob` and `Grep` search: their `path` argument, else the session cwd.
+fn search_base(input: &Value, cwd: Option<&str>) -> Option<PathBuf> {
+  get_optional_string(input, "path")
+    .or_else(|| get_optional_string(input, "file_path"))
+    .map(|path| resolve_path(&path, cwd))
+    .or_else(|| cwd.map(PathBuf::from))
+}
+
+/// The part of a glob pattern before its first wildcard, i.e. the directory it searches.
+fn glob_root(pattern: &str, base: Option<&Path>) -> PathBuf {
+  let full = base.map_or_else(|| PathBuf::from(pattern), |base| base.join(pattern));
+  let mut root = PathBuf::new();
+  for component in full.components() {
+    if component.as_os_str().to_string_lossy().contains(['*', '?', '[', '{']) {
+      break;
+    }
+    root.push(component);
+  }
+  if root.as_os_str().is_empty() {
+    root.push(".");
+  }
+  root
+}
+
+/// The file a `Write`, `Edit` or `MultiEdit` call modifies.
+pub fn file_target(name: &str, input: &Value, cwd: Option<&str>) -> Option<PathBuf> {
+  match name {
+    "Write" | "Edit" | "MultiEdit" => Some(resolve_path(&get_optional_string(input, "file_path")?, cwd)),
+    _ => None,
+  }
+}
+
+/// The change a `Write`, `Edit` or `MultiEdit` call would make, as a unified diff against the file
+/// on disk. `None` for other tools, or when the edit would fail anyway.
+pub fn preview_diff(name: &str, input: &Value, cwd: Option<&str>) -> Option<String> {
+  let path = file_target(name, input, cwd)?;
+  let current = || fs::read_to_string(&path).ok();
+  let (old, new) = match name {
+    "Write" => (current().unwrap_or_default(), get_optional_string(input, "content")?),
+    "Edit" => {
+      let old = current()?;
+      let (new, _) = fs_tools::apply_edits(&old, &[parse_edit(input).ok()?]).ok()?;
+      (old, new)
+    }
+    "MultiEdit" => {
+      let edits = input.get("edits")?.as_array()?.iter().map(parse_edit).collect::<Result<Vec<_>, _>>().ok()?;
+      let old = current()?;
+      let (new, _) = fs_tools::apply_edits(&old, &edits).ok()?;
+      (old, new)
+    }
+    _ => return None,
+  };
+  let mut diff = fs_tools::unified_diff(&path, &old, &new);
+  if let Some((cut, _)) = diff.char_indices().nth(MAX_DIFF_CHARS) {
+    diff.truncate(cut);
+    diff.push_str("\n[diff truncated]\n");
+  }
+  Some(diff)
+}
+
+/// A positive integer field; models sometimes send numbers as strings, which is accepted too.
+fn get_optional_count(input: &Value, key: &str) -> Result<Option<usize>, String> {
+  let count = match input.get(key) {
+    None | Some(Value::Null) => return Ok(None),
+    Some(Value::Number(number)) => number.as_u64(),
+    Some(Value::String(text)) => text.trim().parse().ok(),
+    Some(_) => None,
+  };
+  match count {
+    Some(count) if count > 0 => Ok(Some(count as usize)),
+    _ => Err(format!("Field {} must be a positive integer.", key)),
+  }
+}
+
 fn resolve_path(path: &str, cwd: Option<&str>) -> PathBuf {
   let candidate = Path::new(path);
   if candidate.is_absolute() {
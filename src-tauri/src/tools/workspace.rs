@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// What happens when a file tool reaches outside the workspace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutsideAccess {
+  /// Ask the user, even in `PermissionMode::Auto`.
+  Ask,
+  Deny,
+}
+
+/// The directories file tools may use without asking: the session cwd plus any extra roots.
+/// Paths are compared after resolving symlinks, so a link inside the workspace that points
+/// elsewhere counts as outside.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+  roots: Vec<PathBuf>,
+}
+
+impl Workspace {
+  pub fn new(cwd: &str, extra_roots: &[PathBuf]) -> Self {
+    let roots = std::iter::once(Path::new(cwd))
+      .chain(extra_roots.iter().map(PathBuf::as_path))
+      .map(real_path)
+      .collect();
+    Self { roots }
+  }
+
+  /// False for a path that cannot be resolved, such as a symlink loop.
+  pub fn contains(&self, path: &Path) -> bool {
+    resolve(path, MAX_SYMLINKS).is_some_and(|real| self.roots.iter().any(|root| real.starts_with(root)))
+  }
+
+  /// The entries of `paths` that lie outside every root.
+  pub fn outside<'a>(&self, paths: &'a [PathBuf]) -> Vec<&'a Path> {
+    paths.iter().filter(|path| !self.contains(path)).map(PathBuf::as_path).collect()
+  }
+
+  pub fn check(&self, path: &Path) -> Result<(), String> {
+    if self.contains(path) {
+      return Ok(());
+    }
+    Err(format!(
+      "{} is outside the workspace ({}). Ask the user before touching files elsewhere.",
+      path.display(),
+      self.roots.iter().map(|root| root.display().to_string()).collect::<Vec<_>>().join(", ")
+    ))
+  }
+}
+
+/// How many symlinks a path may go through before it counts as a loop, as with `ELOOP`.
+const MAX_SYMLINKS: usize = 40;
+
+/// `path` with symlinks resolved. A path that does not exist yet (a file about to be written)
+/// resolves through its nearest existing ancestor.
+pub fn real_path(path: &Path) -> PathBuf {
+  resolve(path, MAX_SYMLINKS).unwrap_or_else(|| path.to_path_buf())
+}
+
+/// `real_path`, or `None` once more than `links` symlinks have been followed.
+fn resolve(path: &Path, links: usize) -> Option<PathBuf> {
+  let mut base = path;
+  let mut rest = Vec::new();
+  loop {
+    if let Ok(real) = fs::canonicalize(base) {
+      rest.reverse();
+      return join_unresolved(real, &rest, links);
+    }
+    match (base.components().next_back(), base.parent()) {
+      (Some(component), Some(parent)) => {
+        rest.push(component);
+        base = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+      }
+      _ => return Some(path.to_path_buf()),
+    }
+  }
+}
+
+/// Appends the components `canonicalize` could not resolve. One of them can still be a dangling
+/// symlink (`ws/link -> /etc/evil`), which Write would follow, so links are resolved here too.
+fn join_unresolved(mut resolved: PathBuf, rest: &[Component], links: usize) -> Option<PathBuf> {
+  for (index, component) in rest.iter().enumerate() {
+    match component {
+      Component::ParentDir => {
+        resolved.pop();
+      }
+      Component::Normal(part) => {
+        resolved.push(part);
+        if let Ok(target) = fs::read_link(&resolved) {
+          resolved.pop();
+          let mut next = resolved.join(target);
+          next.extend(&rest[index + 1..]);
+          return resolve(&next, links.checked_sub(1)?);
+        }
+      }
+      _ => {}
+    }
+  }
+  Some(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::tempdir;
+
+  #[test]
+  fn workspace_contains_new_files_but_not_escapes() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("project");
+    fs::create_dir_all(root.join("src")).unwrap();
+    let workspace = Workspace::new(root.to_str().unwrap(), &[]);
+
+    assert!(workspace.contains(&root.join("src/lib.rs")));
+    assert!(workspace.contains(&root.join("new/dir/file.txt")));
+    assert!(!workspace.contains(&root.join("src/../../secret.txt")));
+    assert!(!workspace.contains(Path::new("/etc/passwd")));
+    assert!(workspace.check(&dir.path().join("other.txt")).unwrap_err().contains("outside the workspace"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinks_out_of_the_workspace_are_outside() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("project");
+    let elsewhere = dir.path().join("elsewhere");
+    fs::create_dir_all(&root).unwrap();
+    fs::create_dir_all(&elsewhere).unwrap();
+    std::os::unix::fs::symlink(&elsewhere, root.join("link")).unwrap();
+
+    let workspace = Workspace::new(root.to_str().unwrap(), &[]);
+    assert!(!workspace.contains(&root.join("link/file.txt")));
+
+    let widened = Workspace::new(root.to_str().unwrap(), std::slice::from_ref(&elsewhere));
+    assert!(widened.contains(&root.join("link/file.txt")));
+    let paths = vec![root.join("a.txt"), dir.path().join("b.txt")];
+    assert_eq!(widened.outside(&paths), vec![dir.path().join("b.txt").as_path()]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn dangling_symlinks_are_resolved_before_the_check() {
+    let dir = tempdir().unwrap();
+    let root = dir.path().join("project");
+    fs::create_dir_all(&root).unwrap();
+    std::os::unix::fs::symlink(dir.path().join("evil.txt"), root.join("escape")).unwrap();
+    std::os::unix::fs::symlink(dir.path().join("missing"), root.join("escape_dir")).unwrap();
+    std::os::unix::fs::symlink("target.txt", root.join("local")).unwrap();
+    std::os::unix::fs::symlink("loop_b", root.join("loop_a")).unwrap();
+    std::os::unix::fs::symlink("loop_a", root.join("loop_b")).unwrap();
+
+    let workspace = Workspace::new(root.to_str().unwrap(), &[]);
+    assert!(!workspace.contains(&root.join("escape")));
+    assert!(!workspace.contains(&root.join("escape_dir/file.txt")));
+    assert_eq!(real_path(&root.join("escape")), fs::canonicalize(dir.path()).unwrap().join("evil.txt"));
+    assert!(workspace.contains(&root.join("local")));
+    assert!(!workspace.contains(&root.join("loop_a")));
+  }
+}
@@ -10,14 +10,16 @@ import { MessageCard } from "./components/EventCard";
 import MDContent from "./render/markdown";
 
 type StreamEventDelta = { type?: string; [key: string]: unknown };
-type StreamEventPayload = { type?: string; delta?: StreamEventDelta };
+type StreamEventPayload = { type?: string; delta?: StreamEventDelta; message?: string };
 type StreamEventMessage = { type: "stream_event"; event?: StreamEventPayload };
 
 function App() {
   const messagesEndRef = useRef<HTMLDivElement>(null);
   const partialMessageRef = useRef("");
   const [partialMessage, setPartialMessage] = useState("");
+  const [partialIsThinking, setPartialIsThinking] = useState(false);
   const [showPartialMessage, setShowPartialMessage] = useState(false);
+  const [retryNotice, setRetryNotice] = useState("");
   const [sidebarCollapsed, setSidebarCollapsed] = useState(false);
 
   const sessions = useAppStore((s) => s.sessions);
@@ -50,19 +52,34 @@ function App() {
   };
 
   const handlePartialMessages = useCallback((partialEvent: ServerEvent) => {
+    if (partialEvent.type === "session.status") setRetryNotice("");
     if (partialEvent.type !== "stream.message") return;
 
     const message = partialEvent.payload.message;
     if (!isStreamEventMessage(message)) return;
 
     const eventType = message.event?.type;
+    if (eventType === "reset") {
+      // A failed attempt's partial output is discarded; the retry streams the reply from scratch.
+      partialMessageRef.current = "";
+      setPartialMessage(partialMessageRef.current);
+      setPartialIsThinking(false);
+    }
+
+    if (eventType === "retry") {
+      setRetryNotice(message.event?.message ?? "");
+      setShowPartialMessage(true);
+    }
+
     if (eventType === "content_block_start") {
+      setRetryNotice("");
       partialMessageRef.current = "";
       setPartialMessage(partialMessageRef.current);
       setShowPartialMessage(true);
     }
 
     if (eventType === "content_block_delta") {
+      setPartialIsThinking(message.event?.delta?.type === "thinking_delta");
       partialMessageRef.current += getPartialMessageContent(message.event) || "";
       setPartialMessage(partialMessageRef.current);
       messagesEndRef.current?.scrollIntoView({ behavior: "smooth" });
@@ -94,6 +111,15 @@ function App() {
     if (connected) sendEvent({ type: "session.list" });
   }, [connected, sendEvent]);
 
+  // Every recorded model call bumps a session's call count, so the daily report is refreshed then.
+  const totalCalls = useMemo(
+    () => Object.values(sessions).reduce((sum, session) => sum + (session.usage?.calls ?? 0), 0),
+    [sessions]
+  );
+  useEffect(() => {
+    if (connected) sendEvent({ type: "usage.report", payload: {} });
+  }, [connected, totalCalls, sendEvent]);
+
   useEffect(() => {
     if (!activeSessionId || !connected) return;
     const session = sessions[activeSessionId];
@@ -116,6 +142,21 @@ function App() {
     sendEvent({ type: "session.delete", payload: { sessionId } });
   }, [sendEvent]);
 
+  const handleCompactSession = useCallback((sessionId: string) => {
+    const { providerConfigs, activeProvider } = useAppStore.getState();
+    const apiKey = providerConfigs[sessions[sessionId]?.provider ?? activeProvider].apiKey.trim();
+    sendEvent({ type: "session.compact", payload: { sessionId, apiKey: apiKey || undefined } });
+  }, [sendEvent, sessions]);
+
+  const handleRewind = useCallback((messageIndex: number) => {
+    if (!activeSessionId) return;
+    const confirmed = window.confirm(
+      "Rewind to before this message? Later messages are removed and file changes made by Write/Edit since then are undone."
+    );
+    if (!confirmed) return;
+    sendEvent({ type: "session.rewind", payload: { sessionId: activeSessionId, toMessageIndex: messageIndex } });
+  }, [activeSessionId, sendEvent]);
+
   const handlePermissionResult = useCallback((toolUseId: string, result: PermissionResult) => {
     if (!activeSessionId) return;
     sendEvent({ type: "permission.response", payload: { sessionId: activeSessionId, toolUseId, result } });
@@ -146,6 +187,7 @@ function App() {
           connected={connected}
           onNewSession={handleNewSession}
           onDeleteSession={handleDeleteSession}
+          onCompactSession={handleCompactSession}
           collapsed={sidebarCollapsed}
           onToggleCollapse={() => setSidebarCollapsed((prev) => !prev)}
         />
@@ -221,6 +263,7 @@ function App() {
                       isRunning={isRunning}
                       permissionRequest={permissionRequests[0]}
                       onPermissionResult={handlePermissionResult}
+                      onRewind={msg.type === "user_prompt" && !isRunning ? () => handleRewind(idx) : undefined}
                     />
                   </div>
                 ))}
@@ -228,7 +271,15 @@ function App() {
             )}
 
             <div className="partial-message mt-6">
-              <MDContent text={partialMessage} />
+              {retryNotice && <div className="mb-2 px-1 text-xs text-muted">{retryNotice}</div>}
+              {partialIsThinking && partialMessage ? (
+                <div className="px-1 text-sm italic text-muted">
+                  <div className="mb-1 text-xs font-medium not-italic">Thinking</div>
+                  <div className="whitespace-pre-wrap">{partialMessage}</div>
+                </div>
+              ) : (
+                <MDContent text={partialMessage} />
+              )}
               {showPartialMessage && (
                 <div className="mt-3 flex flex-col gap-2 px-1">
                   <div className="relative h-3 w-2/12 overflow-hidden rounded-full bg-ink-900/10">
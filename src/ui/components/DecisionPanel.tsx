@@ -1,6 +1,8 @@
 import { useState } from "react";
-import type { PermissionResult } from "@anthropic-ai/claude-agent-sdk";
+import type { PermissionResult, PermissionUpdate } from "@anthropic-ai/claude-agent-sdk";
 import type { PermissionRequest } from "../store/useAppStore";
+import MDContent from "../render/markdown";
+import { DiffView } from "./DiffView";
 
 type AskUserQuestionInput = {
   questions?: Array<{
@@ -12,6 +14,13 @@ type AskUserQuestionInput = {
   answers?: Record<string, string>;
 };
 
+/** Splits `Bash(git status:*)` into the SDK's `{ toolName, ruleContent }`. */
+function toRuleValue(rule: string) {
+  const match = /^(\w+)(?:\((.+)\))?$/s.exec(rule.trim());
+  if (!match) return null;
+  return match[2] ? { toolName: match[1], ruleContent: match[2] } : { toolName: match[1] };
+}
+
 export function DecisionPanel({
   request,
   onSubmit
@@ -23,6 +32,19 @@ export function DecisionPanel({
   const questions = input?.questions ?? [];
   const [selectedOptions, setSelectedOptions] = useState<Record<number, string[]>>({});
   const [otherInputs, setOtherInputs] = useState<Record<number, string>>({});
+  const [ruleText, setRuleText] = useState(request.suggestion ?? "");
+  const [planFeedback, setPlanFeedback] = useState("");
+  const ruleValue = toRuleValue(ruleText);
+
+  const allowWithRule = (destination: "session" | "projectSettings") => {
+    if (!ruleValue) return;
+    const update: PermissionUpdate = { type: "addRules", rules: [ruleValue], behavior: "allow", destination };
+    onSubmit({
+      behavior: "allow",
+      updatedInput: request.input as Record<string, unknown>,
+      updatedPermissions: [update]
+    });
+  };
 
   const toggleOption = (qIndex: number, optionLabel: string, multiSelect?: boolean) => {
     setSelectedOptions((prev) => {
@@ -138,17 +160,87 @@ export function DecisionPanel({
     );
   }
 
+  if (request.toolName === "ExitPlanMode") {
+    const plan = (request.input as { plan?: string } | null)?.plan ?? "";
+    const approve = (mode: "default" | "acceptEdits") =>
+      onSubmit({
+        behavior: "allow",
+        updatedInput: request.input as Record<string, unknown>,
+        updatedPermissions: [{ type: "setMode", mode, destination: "session" }]
+      });
+    return (
+      <div className="rounded-2xl border border-accent/20 bg-accent-subtle p-5">
+        <div className="text-xs font-semibold text-accent">Plan ready for review</div>
+        <div className="mt-3 max-h-96 overflow-auto rounded-xl bg-surface p-4 text-sm text-ink-700">
+          <MDContent text={plan} />
+        </div>
+        <div className="mt-3">
+          <label className="block text-xs font-medium text-muted">Feedback (if you want changes)</label>
+          <textarea
+            rows={2}
+            className="mt-1 w-full rounded-xl border border-ink-900/10 bg-surface px-3 py-2 text-sm text-ink-700 focus:border-info/50 focus:outline-none"
+            placeholder="What should the plan do differently?"
+            value={planFeedback}
+            onChange={(e) => setPlanFeedback(e.target.value)}
+          />
+        </div>
+        <div className="mt-4 flex flex-wrap gap-3">
+          <button
+            className="rounded-full bg-accent px-5 py-2 text-sm font-medium text-white shadow-soft hover:bg-accent-hover transition-colors"
+            onClick={() => approve("default")}
+          >
+            Approve, ask before edits
+          </button>
+          <button
+            className="rounded-full border border-accent/30 bg-surface px-5 py-2 text-sm font-medium text-accent hover:bg-surface-tertiary transition-colors"
+            onClick={() => approve("acceptEdits")}
+          >
+            Approve and edit automatically
+          </button>
+          <button
+            className="rounded-full border border-ink-900/10 bg-surface px-5 py-2 text-sm font-medium text-ink-700 hover:bg-surface-tertiary transition-colors"
+            onClick={() => onSubmit({ behavior: "deny", message: planFeedback.trim() })}
+          >
+            Keep planning
+          </button>
+        </div>
+      </div>
+    );
+  }
+
   return (
     <div className="rounded-2xl border border-accent/20 bg-accent-subtle p-5">
       <div className="text-xs font-semibold text-accent">Permission Request</div>
       <p className="mt-2 text-sm text-ink-700">
         Claude wants to use: <span className="font-medium">{request.toolName}</span>
       </p>
-      <div className="mt-3 rounded-xl bg-surface-tertiary p-3">
-        <pre className="text-xs text-ink-600 font-mono whitespace-pre-wrap break-words max-h-40 overflow-auto">
-          {JSON.stringify(request.input, null, 2)}
-        </pre>
-      </div>
+      {request.reason && <p className="mt-1 text-xs text-error">{request.reason}</p>}
+      {request.rule && (
+        <p className="mt-1 text-xs text-muted">
+          Matched {request.rule.behavior} rule <span className="font-mono">{request.rule.rule}</span> from{" "}
+          {request.rule.source}
+        </p>
+      )}
+      {request.diff ? (
+        <DiffView diff={request.diff} className="mt-3 max-h-80 bg-surface-tertiary" />
+      ) : (
+        <div className="mt-3 rounded-xl bg-surface-tertiary p-3">
+          <pre className="text-xs text-ink-600 font-mono whitespace-pre-wrap break-words max-h-40 overflow-auto">
+            {JSON.stringify(request.input, null, 2)}
+          </pre>
+        </div>
+      )}
+      {request.suggestion && (
+        <div className="mt-3">
+          <label className="block text-xs font-medium text-muted">Rule to remember</label>
+          <input
+            type="text"
+            className="mt-1 w-full rounded-xl border border-ink-900/10 bg-surface px-3 py-2 font-mono text-xs text-ink-700 focus:border-info/50 focus:outline-none"
+            value={ruleText}
+            onChange={(e) => setRuleText(e.target.value)}
+          />
+        </div>
+      )}
       <div className="mt-4 flex flex-wrap gap-3">
         <button
           className="rounded-full bg-accent px-5 py-2 text-sm font-medium text-white shadow-soft hover:bg-accent-hover transition-colors"
@@ -156,6 +248,24 @@ export function DecisionPanel({
         >
           Allow
         </button>
+        {request.suggestion && (
+          <>
+            <button
+              className="rounded-full border border-accent/30 bg-surface px-5 py-2 text-sm font-medium text-accent hover:bg-surface-tertiary transition-colors disabled:opacity-50"
+              disabled={!ruleValue}
+              onClick={() => allowWithRule("session")}
+            >
+              Allow for session
+            </button>
+            <button
+              className="rounded-full border border-accent/30 bg-surface px-5 py-2 text-sm font-medium text-accent hover:bg-surface-tertiary transition-colors disabled:opacity-50"
+              disabled={!ruleValue}
+              onClick={() => allowWithRule("projectSettings")}
+            >
+              Always allow in project
+            </button>
+          </>
+        )}
         <button
           className="rounded-full border border-ink-900/10 bg-surface px-5 py-2 text-sm font-medium text-ink-700 hover:bg-surface-tertiary transition-colors"
           onClick={() => onSubmit({ behavior: "deny", message: "User denied the request" })}
@@ -0,0 +1,21 @@
+const lineClass = (line: string) => {
+  if (line.startsWith("+++") || line.startsWith("---")) return "text-muted";
+  if (line.startsWith("@@")) return "text-accent";
+  if (line.startsWith("+")) return "bg-success-light text-success";
+  if (line.startsWith("-")) return "bg-error-light text-error";
+  return "text-ink-600";
+};
+
+/** Renders a unified diff produced by the backend for Write/Edit/MultiEdit. */
+export function DiffView({ diff, className = "" }: { diff: string; className?: string }) {
+  const lines = diff.replace(/\n$/, "").split("\n");
+  return (
+    <pre className={`text-xs font-mono overflow-auto rounded-xl p-3 ${className}`}>
+      {lines.map((line, index) => (
+        <div key={index} className={`whitespace-pre-wrap break-words px-1 ${lineClass(line)}`}>
+          {line || " "}
+        </div>
+      ))}
+    </pre>
+  );
+}
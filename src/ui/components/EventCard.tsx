@@ -6,13 +6,18 @@ import type {
   SDKResultMessage,
   SDKUserMessage
 } from "@anthropic-ai/claude-agent-sdk";
-import type { StreamMessage } from "../types";
+import type { Attachment, StreamMessage, UserPromptMessage } from "../types";
 import type { PermissionRequest } from "../store/useAppStore";
 import MDContent from "../render/markdown";
 import { DecisionPanel } from "./DecisionPanel";
+import { DiffView } from "./DiffView";
 
 type MessageContent = SDKAssistantMessage["message"]["content"][number];
-type ToolResultContent = Extract<SDKUserMessage["message"]["content"][number], { type: "tool_result" }>;
+/** Our backend adds `attachments` when a tool (e.g. Read on an image) returns files, and `diff` for file edits. */
+type ToolResultContent = Extract<SDKUserMessage["message"]["content"][number], { type: "tool_result" }> & {
+  attachments?: Attachment[];
+  diff?: string;
+};
 type ToolUseContent = Extract<MessageContent, { type: "tool_use" }>;
 type ToolStatus = "pending" | "success" | "error";
 const toolStatusMap = new Map<string, ToolStatus>();
@@ -185,6 +190,8 @@ const ToolResult = ({ messageContent }: { messageContent: ToolResultContent }) =
             <span>{isExpanded ? "Collapse" : `Show ${lines.length - MAX_VISIBLE_LINES} more lines`}</span>
           </button>
         )}
+        {messageContent.diff && <DiffView diff={messageContent.diff} className="mt-2 max-h-96 bg-surface" />}
+        {messageContent.attachments && <AttachmentList attachments={messageContent.attachments} />}
         <div ref={bottomRef} />
       </div>
     </div>
@@ -226,7 +233,7 @@ const ToolUseCard = ({
     const input = messageContent.input as Record<string, unknown> | undefined;
     switch (messageContent.name) {
       case "Bash": return getInputString(input, "command");
-      case "Read": case "Write": case "Edit": return getInputString(input, "file_path");
+      case "Read": case "Write": case "Edit": case "MultiEdit": return getInputString(input, "file_path");
       case "Glob": case "Grep": return getInputString(input, "pattern");
       case "Task": return getInputString(input, "description");
       case "WebFetch": return getInputString(input, "url");
@@ -321,13 +328,52 @@ const SystemInfoCard = ({ message, showIndicator = false }: { message: SDKMessag
   );
 };
 
-const UserMessageCard = ({ message, showIndicator = false }: { message: { type: "user_prompt"; prompt: string }; showIndicator?: boolean }) => (
-  <div className="flex flex-col mt-4">
+const AttachmentList = ({ attachments }: { attachments: Attachment[] }) =>
+  attachments.length === 0 ? null : (
+    <div className="mt-2 flex flex-wrap gap-2">
+      {attachments.map((attachment, index) =>
+        attachment.mediaType.startsWith("image/") ? (
+          <img
+            key={index}
+            src={`data:${attachment.mediaType};base64,${attachment.data}`}
+            alt={attachment.name}
+            title={attachment.name}
+            className="h-24 max-w-[200px] rounded-lg border border-ink-900/10 object-cover"
+          />
+        ) : (
+          <span key={index} className="rounded-full border border-ink-900/10 bg-surface px-3 py-1 text-xs text-ink-700">
+            {attachment.name}
+          </span>
+        )
+      )}
+    </div>
+  );
+
+const UserMessageCard = ({
+  message,
+  showIndicator = false,
+  onRewind
+}: {
+  message: UserPromptMessage;
+  showIndicator?: boolean;
+  onRewind?: () => void;
+}) => (
+  <div className="group flex flex-col mt-4">
     <div className="header text-accent flex items-center gap-2">
       <StatusDot variant="success" isActive={showIndicator} isVisible={showIndicator} />
       User
+      {onRewind && (
+        <button
+          className="ml-auto text-xs font-normal text-muted opacity-0 transition-opacity hover:text-accent group-hover:opacity-100"
+          onClick={onRewind}
+          title="Undo this message and everything after it"
+        >
+          Rewind
+        </button>
+      )}
     </div>
     <MDContent text={message.prompt} />
+    {message.attachments && <AttachmentList attachments={message.attachments} />}
   </div>
 );
 
@@ -336,18 +382,38 @@ export function MessageCard({
   isLast = false,
   isRunning = false,
   permissionRequest,
-  onPermissionResult
+  onPermissionResult,
+  onRewind
 }: {
   message: StreamMessage;
   isLast?: boolean;
   isRunning?: boolean;
   permissionRequest?: PermissionRequest;
   onPermissionResult?: (toolUseId: string, result: PermissionResult) => void;
+  onRewind?: () => void;
 }) {
   const showIndicator = isLast && isRunning;
 
   if (message.type === "user_prompt") {
-    return <UserMessageCard message={message} showIndicator={showIndicator} />;
+    return <UserMessageCard message={message} showIndicator={showIndicator} onRewind={onRewind} />;
+  }
+
+  if (message.type === "marker") {
+    return (
+      <div className="my-3 text-xs text-muted">
+        <div className="flex items-center gap-3">
+          <span className="h-px flex-1 bg-ink-900/10" />
+          <span>{message.text}</span>
+          <span className="h-px flex-1 bg-ink-900/10" />
+        </div>
+        {message.summary && (
+          <details className="mt-2 rounded-lg bg-ink-900/5 px-3 py-2">
+            <summary className="cursor-pointer">Summary</summary>
+            <div className="mt-2 whitespace-pre-wrap text-ink-700">{message.summary}</div>
+          </details>
+        )}
+      </div>
+    );
   }
 
   const sdkMessage = message as SDKMessage;
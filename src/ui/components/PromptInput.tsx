@@ -1,13 +1,32 @@
 import { useCallback, useEffect, useMemo, useRef } from "react";
-import type { ClientEvent, ProviderKind } from "../types";
+import type { ClientEvent, GenerationParams, ProviderKind } from "../types";
 import { useAppStore } from "../store/useAppStore";
-import { ANTHROPIC_MODELS, OPENAI_MODELS } from "./ProviderSettings";
+import { useOllamaModels } from "../hooks/useOllamaModels";
+import { ATTACHMENT_ACCEPT, useAttachments } from "../hooks/useAttachments";
+import { ANTHROPIC_MODELS, GEMINI_MODELS, OPENAI_MODELS } from "./ProviderSettings";
 
-const DEFAULT_ALLOWED_TOOLS = "Read,Edit,Bash";
+// Skips the prompt for read-only file tools; `Read` rules also cover Glob and Grep.
+const DEFAULT_ALLOWED_TOOLS = "Read";
 const MAX_ROWS = 12;
 const LINE_HEIGHT = 21;
 const MAX_HEIGHT = MAX_ROWS * LINE_HEIGHT;
 
+/** Drops blank fields so the backend falls back to the provider defaults. */
+export function cleanGenerationParams(params?: GenerationParams): GenerationParams | undefined {
+  if (!params) return undefined;
+  const stop = params.stop?.filter((sequence) => sequence.length > 0) ?? [];
+  const cleaned: GenerationParams = {
+    maxTokens: params.maxTokens,
+    temperature: params.temperature,
+    topP: params.topP,
+    stop: stop.length > 0 ? stop : undefined,
+    reasoningEffort: params.reasoningEffort,
+    thinkingBudget: params.thinkingBudget
+  };
+  const hasValue = Object.values(cleaned).some((value) => value !== undefined);
+  return hasValue ? cleaned : undefined;
+}
+
 interface PromptInputProps {
   sendEvent: (event: ClientEvent) => void;
   connected: boolean;
@@ -15,6 +34,8 @@ interface PromptInputProps {
 
 export function usePromptActions(sendEvent: (event: ClientEvent) => void, connected: boolean) {
   const prompt = useAppStore((state) => state.prompt);
+  const attachments = useAppStore((state) => state.attachments);
+  const setAttachments = useAppStore((state) => state.setAttachments);
   const cwd = useAppStore((state) => state.cwd);
   const activeSessionId = useAppStore((state) => state.activeSessionId);
   const sessions = useAppStore((state) => state.sessions);
@@ -40,7 +61,7 @@ export function usePromptActions(sendEvent: (event: ClientEvent) => void, connec
     const model = providerConfig.model.trim();
     const baseUrl = providerConfig.baseUrl?.trim() || undefined;
 
-    if (!apiKey || !model) {
+    if ((activeProvider !== "ollama" && !apiKey) || !model) {
       setGlobalError("请先填写 API Key 和 Model。");
       return;
     }
@@ -66,7 +87,10 @@ export function usePromptActions(sendEvent: (event: ClientEvent) => void, connec
           apiKey,
           model,
           baseUrl,
-          permissionMode
+          permissionMode,
+          openaiApi: activeProvider === "openai" ? providerConfig.openaiApi : undefined,
+          generationParams: cleanGenerationParams(providerConfig.generationParams),
+          attachments: attachments.length > 0 ? attachments : undefined
         }
       });
     } else {
@@ -74,12 +98,24 @@ export function usePromptActions(sendEvent: (event: ClientEvent) => void, connec
         setGlobalError("Session is still running. Please wait for it to finish.");
         return;
       }
-      sendEvent({ type: "session.continue", payload: { sessionId: activeSessionId, prompt } });
+      // Keys are kept in memory by the backend, so resend it for sessions restored after a restart.
+      const sessionApiKey = providerConfigs[activeSession?.provider ?? activeProvider].apiKey.trim();
+      sendEvent({
+        type: "session.continue",
+        payload: {
+          sessionId: activeSessionId,
+          prompt,
+          apiKey: sessionApiKey || undefined,
+          attachments: attachments.length > 0 ? attachments : undefined
+        }
+      });
     }
     setPrompt("");
+    setAttachments([]);
   }, [
     activeSession,
     activeSessionId,
+    attachments,
     connected,
     cwd,
     prompt,
@@ -89,7 +125,8 @@ export function usePromptActions(sendEvent: (event: ClientEvent) => void, connec
     sendEvent,
     setGlobalError,
     setPendingStart,
-    setPrompt
+    setPrompt,
+    setAttachments
   ]);
 
   const handleStop = useCallback(() => {
@@ -108,8 +145,9 @@ export function usePromptActions(sendEvent: (event: ClientEvent) => void, connec
   return { prompt, setPrompt, isRunning, handleSend, handleStop, handleStartFromModal };
 }
 
-const getModelOptions = (provider: ProviderKind, currentModel: string) => {
-  const base = provider === "anthropic" ? ANTHROPIC_MODELS : OPENAI_MODELS;
+const getModelOptions = (provider: ProviderKind, currentModel: string, localModels: string[]) => {
+  const presets = { anthropic: ANTHROPIC_MODELS, openai: OPENAI_MODELS, gemini: GEMINI_MODELS, ollama: localModels };
+  const base = presets[provider];
   const options = [currentModel, ...base].filter(Boolean);
   return Array.from(new Set(options));
 };
@@ -117,15 +155,18 @@ const getModelOptions = (provider: ProviderKind, currentModel: string) => {
 export function PromptInput({ sendEvent, connected }: PromptInputProps) {
   const { prompt, setPrompt, isRunning, handleSend, handleStop } = usePromptActions(sendEvent, connected);
   const promptRef = useRef<HTMLTextAreaElement | null>(null);
+  const fileInputRef = useRef<HTMLInputElement | null>(null);
+  const { attachments, addFiles, removeAttachment } = useAttachments();
   const activeProvider = useAppStore((state) => state.activeProvider);
   const providerConfigs = useAppStore((state) => state.providerConfigs);
   const setActiveProvider = useAppStore((state) => state.setActiveProvider);
   const setProviderConfig = useAppStore((state) => state.setProviderConfig);
 
   const currentConfig = providerConfigs[activeProvider];
+  const ollamaModels = useOllamaModels(activeProvider === "ollama", providerConfigs.ollama.baseUrl);
   const modelOptions = useMemo(
-    () => getModelOptions(activeProvider, currentConfig.model),
-    [activeProvider, currentConfig.model]
+    () => getModelOptions(activeProvider, currentConfig.model, ollamaModels),
+    [activeProvider, currentConfig.model, ollamaModels]
   );
 
   const handleKeyDown = (e: React.KeyboardEvent<HTMLTextAreaElement>) => {
@@ -138,6 +179,12 @@ export function PromptInput({ sendEvent, connected }: PromptInputProps) {
     handleSend();
   };
 
+  const handlePaste = (e: React.ClipboardEvent<HTMLTextAreaElement>) => {
+    if (e.clipboardData.files.length === 0) return;
+    e.preventDefault();
+    void addFiles(e.clipboardData.files);
+  };
+
   const handleInput = (e: React.FormEvent<HTMLTextAreaElement>) => {
     const target = e.currentTarget;
     target.style.height = "auto";
@@ -168,7 +215,48 @@ export function PromptInput({ sendEvent, connected }: PromptInputProps) {
     <section className="fixed bottom-0 left-[var(--sidebar-width)] right-0 z-20 bg-gradient-to-t from-surface via-surface/95 to-transparent pb-6 pt-8">
       <div className="mx-auto w-full max-w-4xl px-4 lg:px-6">
         <div className="rounded-[1.5rem] border border-ink-900/10 bg-panel/90 p-4 shadow-card backdrop-blur">
+          {attachments.length > 0 && (
+            <div className="mb-3 flex flex-wrap gap-2">
+              {attachments.map((attachment, index) => (
+                <span
+                  key={`${attachment.name}-${index}`}
+                  className="flex items-center gap-1.5 rounded-full border border-ink-900/10 bg-surface px-3 py-1 text-xs text-ink-700"
+                >
+                  <span className="max-w-[180px] truncate">{attachment.name}</span>
+                  <button
+                    type="button"
+                    className="text-muted hover:text-ink-800"
+                    aria-label={`Remove ${attachment.name}`}
+                    onClick={() => removeAttachment(index)}
+                  >
+                    ×
+                  </button>
+                </span>
+              ))}
+            </div>
+          )}
           <div className="flex items-end gap-3">
+            <input
+              ref={fileInputRef}
+              type="file"
+              multiple
+              accept={ATTACHMENT_ACCEPT}
+              className="hidden"
+              onChange={(e) => {
+                if (e.target.files) void addFiles(e.target.files);
+                e.target.value = "";
+              }}
+            />
+            <button
+              type="button"
+              className="flex h-10 w-10 shrink-0 items-center justify-center rounded-full text-muted hover:bg-ink-900/5 hover:text-ink-700"
+              onClick={() => fileInputRef.current?.click()}
+              aria-label="Attach files"
+            >
+              <svg viewBox="0 0 24 24" className="h-4 w-4" fill="none" stroke="currentColor" strokeWidth="1.8" aria-hidden="true">
+                <path d="M21 11.5l-8.6 8.6a5 5 0 0 1-7.1-7.1l8.6-8.6a3.5 3.5 0 0 1 5 5l-8.6 8.6a2 2 0 0 1-2.8-2.8l7.9-7.9" />
+              </svg>
+            </button>
             <textarea
               rows={1}
               className="flex-1 resize-none bg-transparent py-1.5 text-sm text-ink-800 placeholder:text-muted focus:outline-none"
@@ -176,6 +264,7 @@ export function PromptInput({ sendEvent, connected }: PromptInputProps) {
               value={prompt}
               onChange={(e) => setPrompt(e.target.value)}
               onKeyDown={handleKeyDown}
+              onPaste={handlePaste}
               onInput={handleInput}
               ref={promptRef}
             />
@@ -204,6 +293,8 @@ export function PromptInput({ sendEvent, connected }: PromptInputProps) {
                 >
                   <option value="anthropic">Anthropic</option>
                   <option value="openai">OpenAI</option>
+                  <option value="gemini">Gemini</option>
+                  <option value="ollama">Ollama</option>
                 </select>
               </div>
               <div className="flex items-center gap-2">
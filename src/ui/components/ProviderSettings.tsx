@@ -1,4 +1,5 @@
-import type { PermissionMode, ProviderConfig, ProviderKind } from "../types";
+import type { GenerationParams, OpenAIApi, PermissionMode, ProviderConfig, ProviderKind, ReasoningEffort } from "../types";
+import { useOllamaModels } from "../hooks/useOllamaModels";
 
 export const ANTHROPIC_MODELS = [
   "claude-sonnet-4-5-20250929",
@@ -7,6 +8,12 @@ export const ANTHROPIC_MODELS = [
   "claude-3-opus-20240229"
 ];
 
+export const GEMINI_MODELS = [
+  "gemini-2.5-pro",
+  "gemini-2.5-flash",
+  "gemini-2.5-flash-lite"
+];
+
 export const OPENAI_MODELS = [
   "gpt-4o",
   "gpt-4o-mini",
@@ -15,6 +22,60 @@ export const OPENAI_MODELS = [
   "gpt-4.1-nano"
 ];
 
+const PRESET_MODELS: Record<Exclude<ProviderKind, "ollama">, string[]> = {
+  anthropic: ANTHROPIC_MODELS,
+  openai: OPENAI_MODELS,
+  gemini: GEMINI_MODELS
+};
+
+const PROVIDER_LABELS: Record<ProviderKind, string> = {
+  anthropic: "Anthropic",
+  openai: "OpenAI",
+  ollama: "Ollama",
+  gemini: "Gemini"
+};
+
+const KEY_PLACEHOLDERS: Record<ProviderKind, string> = {
+  anthropic: "sk-ant-...",
+  openai: "sk-...",
+  ollama: "本地运行无需填写",
+  gemini: "AIza..."
+};
+
+const MODEL_PLACEHOLDERS: Record<ProviderKind, string> = {
+  anthropic: "claude-sonnet-4-5-20250929",
+  openai: "gpt-4o-mini",
+  ollama: "llama3.1",
+  gemini: "gemini-2.5-flash"
+};
+
+const BASE_URL_PLACEHOLDERS: Record<ProviderKind, string> = {
+  anthropic: "https://api.anthropic.com/v1/messages",
+  openai: "https://api.openai.com/v1/chat/completions",
+  ollama: "http://localhost:11434",
+  gemini: "https://generativelanguage.googleapis.com/v1beta"
+};
+
+const OPENAI_RESPONSES_URL = "https://api.openai.com/v1/responses";
+
+/** Providers whose API takes a reasoning effort (Gemini maps it to a thinking budget). */
+const SUPPORTS_REASONING_EFFORT: Record<ProviderKind, boolean> = {
+  anthropic: false,
+  openai: true,
+  ollama: false,
+  gemini: true
+};
+
+/** Providers that take an explicit thinking token budget. */
+const SUPPORTS_THINKING_BUDGET: Record<ProviderKind, boolean> = {
+  anthropic: true,
+  openai: false,
+  ollama: false,
+  gemini: true
+};
+
+const parseNumber = (raw: string) => (raw.trim() === "" ? undefined : Number(raw));
+
 export function ProviderSettings({
   value,
   onChange,
@@ -30,34 +91,31 @@ export function ProviderSettings({
   permissionMode: PermissionMode;
   onPermissionModeChange: (value: PermissionMode) => void;
 }) {
-  const modelOptions = value === "anthropic" ? ANTHROPIC_MODELS : OPENAI_MODELS;
+  const ollamaModels = useOllamaModels(value === "ollama", config.baseUrl);
+  const modelOptions = value === "ollama" ? ollamaModels : PRESET_MODELS[value];
   const selectedModel = modelOptions.includes(config.model) ? config.model : "custom";
+  const params = config.generationParams ?? {};
+  const setParams = (next: GenerationParams) => onConfigChange({ ...config, generationParams: next });
+  const inputClass =
+    "rounded-lg border border-ink-900/10 bg-white px-3 py-2 text-xs text-ink-800 placeholder:text-muted-light focus:border-accent focus:outline-none focus:ring-1 focus:ring-accent/20";
   return (
     <div className="rounded-xl border border-ink-900/10 bg-surface px-3 py-3">
       <div className="text-xs font-medium text-muted">Provider</div>
       <div className="mt-2 flex gap-2">
-        <button
-          type="button"
-          onClick={() => onChange("anthropic")}
-          className={`rounded-lg border px-3 py-1 text-xs ${
-            value === "anthropic"
-              ? "border-accent/60 bg-accent/10 text-ink-800"
-              : "border-ink-900/10 bg-white text-muted hover:border-ink-900/20 hover:text-ink-700"
-          }`}
-        >
-          Anthropic
-        </button>
-        <button
-          type="button"
-          onClick={() => onChange("openai")}
-          className={`rounded-lg border px-3 py-1 text-xs ${
-            value === "openai"
-              ? "border-accent/60 bg-accent/10 text-ink-800"
-              : "border-ink-900/10 bg-white text-muted hover:border-ink-900/20 hover:text-ink-700"
-          }`}
-        >
-          OpenAI
-        </button>
+        {(Object.keys(PROVIDER_LABELS) as ProviderKind[]).map((kind) => (
+          <button
+            key={kind}
+            type="button"
+            onClick={() => onChange(kind)}
+            className={`rounded-lg border px-3 py-1 text-xs ${
+              value === kind
+                ? "border-accent/60 bg-accent/10 text-ink-800"
+                : "border-ink-900/10 bg-white text-muted hover:border-ink-900/20 hover:text-ink-700"
+            }`}
+          >
+            {PROVIDER_LABELS[kind]}
+          </button>
+        ))}
       </div>
       <div className="mt-3 grid gap-2">
         <label className="grid gap-1 text-[11px] font-medium text-muted">
@@ -77,12 +135,25 @@ export function ProviderSettings({
             <option value="custom">Custom</option>
           </select>
         </label>
+        {value === "openai" && (
+          <label className="grid gap-1 text-[11px] font-medium text-muted">
+            API
+            <select
+              className="rounded-lg border border-ink-900/10 bg-white px-3 py-2 text-xs text-ink-800 focus:border-accent focus:outline-none focus:ring-1 focus:ring-accent/20"
+              value={config.openaiApi ?? "chat_completions"}
+              onChange={(event) => onConfigChange({ ...config, openaiApi: event.target.value as OpenAIApi })}
+            >
+              <option value="chat_completions">Chat Completions</option>
+              <option value="responses">Responses (reasoning models)</option>
+            </select>
+          </label>
+        )}
         <label className="grid gap-1 text-[11px] font-medium text-muted">
           API Key
           <input
             type="password"
             className="rounded-lg border border-ink-900/10 bg-white px-3 py-2 text-xs text-ink-800 placeholder:text-muted-light focus:border-accent focus:outline-none focus:ring-1 focus:ring-accent/20"
-            placeholder={value === "anthropic" ? "sk-ant-..." : "sk-..."}
+            placeholder={KEY_PLACEHOLDERS[value]}
             value={config.apiKey}
             onChange={(event) => onConfigChange({ ...config, apiKey: event.target.value })}
           />
@@ -91,7 +162,7 @@ export function ProviderSettings({
           Model
           <input
             className="rounded-lg border border-ink-900/10 bg-white px-3 py-2 text-xs text-ink-800 placeholder:text-muted-light focus:border-accent focus:outline-none focus:ring-1 focus:ring-accent/20"
-            placeholder={value === "anthropic" ? "claude-sonnet-4-5-20250929" : "gpt-4o-mini"}
+            placeholder={MODEL_PLACEHOLDERS[value]}
             value={config.model}
             onChange={(event) => onConfigChange({ ...config, model: event.target.value })}
           />
@@ -100,12 +171,101 @@ export function ProviderSettings({
           Base URL (可选)
           <input
             className="rounded-lg border border-ink-900/10 bg-white px-3 py-2 text-xs text-ink-800 placeholder:text-muted-light focus:border-accent focus:outline-none focus:ring-1 focus:ring-accent/20"
-            placeholder={value === "anthropic" ? "https://api.anthropic.com/v1/messages" : "https://api.openai.com/v1/chat/completions"}
+            placeholder={
+              value === "openai" && config.openaiApi === "responses" ? OPENAI_RESPONSES_URL : BASE_URL_PLACEHOLDERS[value]
+            }
             value={config.baseUrl ?? ""}
             onChange={(event) => onConfigChange({ ...config, baseUrl: event.target.value })}
           />
         </label>
       </div>
+      <div className="mt-4 border-t border-ink-900/10 pt-3">
+        <div className="text-xs font-medium text-muted">Generation (可选)</div>
+        <div className="mt-2 grid grid-cols-3 gap-2">
+          <label className="grid gap-1 text-[11px] font-medium text-muted">
+            Max tokens
+            <input
+              type="number"
+              min={1}
+              className={inputClass}
+              placeholder="默认"
+              value={params.maxTokens ?? ""}
+              onChange={(event) => setParams({ ...params, maxTokens: parseNumber(event.target.value) })}
+            />
+          </label>
+          <label className="grid gap-1 text-[11px] font-medium text-muted">
+            Temperature
+            <input
+              type="number"
+              min={0}
+              max={value === "anthropic" ? 1 : 2}
+              step={0.1}
+              className={inputClass}
+              placeholder="默认"
+              value={params.temperature ?? ""}
+              onChange={(event) => setParams({ ...params, temperature: parseNumber(event.target.value) })}
+            />
+          </label>
+          <label className="grid gap-1 text-[11px] font-medium text-muted">
+            Top P
+            <input
+              type="number"
+              min={0}
+              max={1}
+              step={0.05}
+              className={inputClass}
+              placeholder="默认"
+              value={params.topP ?? ""}
+              onChange={(event) => setParams({ ...params, topP: parseNumber(event.target.value) })}
+            />
+          </label>
+        </div>
+        <div className="mt-2 grid gap-2">
+          <label className="grid gap-1 text-[11px] font-medium text-muted">
+            Stop sequences (每行一个)
+            <textarea
+              rows={2}
+              className={inputClass}
+              value={(params.stop ?? []).join("\n")}
+              onChange={(event) =>
+                setParams({ ...params, stop: event.target.value ? event.target.value.split("\n") : undefined })
+              }
+            />
+          </label>
+          {SUPPORTS_REASONING_EFFORT[value] && (
+            <label className="grid gap-1 text-[11px] font-medium text-muted">
+              Reasoning effort
+              <select
+                className={inputClass}
+                value={params.reasoningEffort ?? ""}
+                onChange={(event) =>
+                  setParams({ ...params, reasoningEffort: (event.target.value || undefined) as ReasoningEffort | undefined })
+                }
+              >
+                <option value="">默认</option>
+                <option value="minimal">Minimal</option>
+                <option value="low">Low</option>
+                <option value="medium">Medium</option>
+                <option value="high">High</option>
+              </select>
+            </label>
+          )}
+          {SUPPORTS_THINKING_BUDGET[value] && (
+            <label className="grid gap-1 text-[11px] font-medium text-muted">
+              Thinking budget (tokens)
+              <input
+                type="number"
+                min={value === "anthropic" ? 1024 : 0}
+                step={1024}
+                className={inputClass}
+                placeholder={value === "anthropic" ? "关闭" : "默认"}
+                value={params.thinkingBudget ?? ""}
+                onChange={(event) => setParams({ ...params, thinkingBudget: parseNumber(event.target.value) })}
+              />
+            </label>
+          )}
+        </div>
+      </div>
       <div className="mt-4 border-t border-ink-900/10 pt-3">
         <div className="text-xs font-medium text-muted">Permissions</div>
         <div className="mt-2 grid gap-2">
@@ -131,6 +291,17 @@ export function ProviderSettings({
           >
             Ask before edits
           </button>
+          <button
+            type="button"
+            onClick={() => onPermissionModeChange("plan")}
+            className={`rounded-lg border px-3 py-2 text-left text-xs ${
+              permissionMode === "plan"
+                ? "border-accent/60 bg-accent/10 text-ink-800"
+                : "border-ink-900/10 bg-white text-muted hover:border-ink-900/20 hover:text-ink-700"
+            }`}
+          >
+            Plan first (read-only until you approve a plan)
+          </button>
         </div>
       </div>
     </div>
@@ -2,12 +2,45 @@ import { useCallback, useEffect, useMemo, useRef, useState } from "react";
 import * as DropdownMenu from "@radix-ui/react-dropdown-menu";
 import * as Dialog from "@radix-ui/react-dialog";
 import { useAppStore } from "../store/useAppStore";
+import type { UsageTotals } from "../types";
 import { ProviderSettings } from "./ProviderSettings";
 
+const formatCost = (usage?: UsageTotals) => {
+  if (!usage || usage.calls === 0) return "";
+  const cost = usage.costUsd < 0.01 && usage.costUsd > 0 ? "<$0.01" : `$${usage.costUsd.toFixed(2)}`;
+  // Calls to models without a known price are counted in tokens only.
+  return usage.unpricedCalls > 0 ? `${cost}+` : cost;
+};
+
+const compactCount = (count: number) => (count >= 1000 ? `${(count / 1000).toFixed(1)}k` : `${count}`);
+
+const formatTokens = (usage: UsageTotals) => {
+  const total = usage.inputTokens + usage.outputTokens + usage.cacheReadTokens + usage.cacheWriteTokens;
+  const lines = [
+    `${compactCount(total)} tokens`,
+    `Input ${compactCount(usage.inputTokens)} · Output ${compactCount(usage.outputTokens)}`
+  ];
+  const prompt = usage.inputTokens + usage.cacheReadTokens + usage.cacheWriteTokens;
+  if (usage.cacheReadTokens + usage.cacheWriteTokens > 0 && prompt > 0) {
+    // Share of prompt tokens served from the prompt cache, which bills reads at a fraction of input.
+    const hitRate = Math.round((usage.cacheReadTokens / prompt) * 100);
+    lines.push(
+      `Cache read ${compactCount(usage.cacheReadTokens)} · write ${compactCount(usage.cacheWriteTokens)} (${hitRate}% cached)`
+    );
+  }
+  return lines.join("\n");
+};
+
+const localDate = (date: Date) => {
+  const pad = (value: number) => String(value).padStart(2, "0");
+  return `${date.getFullYear()}-${pad(date.getMonth() + 1)}-${pad(date.getDate())}`;
+};
+
 interface SidebarProps {
   connected: boolean;
   onNewSession: () => void;
   onDeleteSession: (sessionId: string) => void;
+  onCompactSession: (sessionId: string) => void;
   collapsed: boolean;
   onToggleCollapse: () => void;
 }
@@ -15,6 +48,7 @@ interface SidebarProps {
 export function Sidebar({
   onNewSession,
   onDeleteSession,
+  onCompactSession,
   connected,
   collapsed,
   onToggleCollapse
@@ -28,6 +62,7 @@ export function Sidebar({
   const setProviderConfig = useAppStore((state) => state.setProviderConfig);
   const permissionMode = useAppStore((state) => state.permissionMode);
   const setPermissionMode = useAppStore((state) => state.setPermissionMode);
+  const dailyUsage = useAppStore((state) => state.dailyUsage);
   const [resumeSessionId, setResumeSessionId] = useState<string | null>(null);
   const [settingsOpen, setSettingsOpen] = useState(false);
   const [copied, setCopied] = useState(false);
@@ -46,6 +81,12 @@ export function Sidebar({
     return list;
   }, [sessions]);
 
+  const usageSummary = useMemo(() => {
+    const today = dailyUsage.find((day) => day.date === localDate(new Date()));
+    const monthCost = dailyUsage.reduce((sum, day) => sum + day.costUsd, 0);
+    return { today: today?.costUsd ?? 0, month: monthCost };
+  }, [dailyUsage]);
+
   const resetResumeSession = useCallback((nextId: string | null) => {
     setResumeSessionId(nextId);
     setCopied(false);
@@ -193,6 +234,11 @@ export function Sidebar({
                   </div>
                   <div className="flex items-center justify-between mt-0.5 text-xs text-muted">
                     <span className="truncate">{formatCwd(session.cwd)}</span>
+                    {session.usage && session.usage.calls > 0 && (
+                      <span className="ml-2 shrink-0 tabular-nums" title={formatTokens(session.usage)}>
+                        {formatCost(session.usage)}
+                      </span>
+                    )}
                   </div>
                 </div>
                 <DropdownMenu.Root>
@@ -213,6 +259,12 @@ export function Sidebar({
                         </svg>
                         Delete this session
                       </DropdownMenu.Item>
+                      <DropdownMenu.Item className="flex cursor-pointer items-center gap-2 rounded-lg px-3 py-2 text-sm text-ink-700 outline-none hover:bg-ink-900/5 data-[disabled]:cursor-default data-[disabled]:opacity-50" disabled={session.status === "running"} onSelect={() => onCompactSession(session.id)}>
+                        <svg viewBox="0 0 24 24" className="h-4 w-4 text-ink-500" fill="none" stroke="currentColor" strokeWidth="1.8">
+                          <path d="M4 6h16M4 10h16" /><path d="M9 15l3 3 3-3" /><path d="M12 12v6" />
+                        </svg>
+                        Compact context
+                      </DropdownMenu.Item>
                       <DropdownMenu.Item className="flex cursor-pointer items-center gap-2 rounded-lg px-3 py-2 text-sm text-ink-700 outline-none hover:bg-ink-900/5" onSelect={() => resetResumeSession(session.id)}>
                         <svg viewBox="0 0 24 24" className="h-4 w-4 text-ink-500" fill="none" stroke="currentColor" strokeWidth="1.8">
                           <path d="M4 5h16v14H4z" /><path d="M7 9h10M7 12h6" /><path d="M13 15l3 2-3 2" />
@@ -227,6 +279,12 @@ export function Sidebar({
           </div>
         ))}
       </div>
+      {!collapsed && dailyUsage.length > 0 && (
+        <div className="flex items-center justify-between rounded-xl border border-ink-900/10 bg-surface px-3 py-2 text-xs text-muted tabular-nums">
+          <span>Today ${usageSummary.today.toFixed(2)}</span>
+          <span>30 days ${usageSummary.month.toFixed(2)}</span>
+        </div>
+      )}
       <div className={`mt-auto flex items-center gap-2 rounded-xl border border-ink-900/10 bg-surface px-3 py-2 text-xs text-muted ${collapsed ? "justify-center" : ""}`}>
         <span className={`h-2 w-2 rounded-full ${connected ? "bg-success" : "bg-error"}`} />
         {!collapsed && <span>{connected ? "Backend connected" : "Backend offline"}</span>}
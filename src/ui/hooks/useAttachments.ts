@@ -0,0 +1,88 @@
+import { useCallback } from "react";
+import type { Attachment } from "../types";
+import { useAppStore } from "../store/useAppStore";
+
+const IMAGE_TYPES = ["image/png", "image/jpeg", "image/webp", "image/gif"];
+const TEXT_EXTENSIONS = ["txt", "md", "markdown", "csv", "json", "log", "yaml", "yml", "toml", "xml", "html"];
+/** Longest image edge the vision models use; larger images are scaled down before sending. */
+const MAX_IMAGE_EDGE = 1568;
+const MAX_IMAGE_BYTES = 5 * 1024 * 1024;
+const MAX_ATTACHMENTS = 10;
+
+export const ATTACHMENT_ACCEPT = [...IMAGE_TYPES, "application/pdf", "text/*", ".md", ".json", ".csv"].join(",");
+
+const mediaTypeOf = (file: File) => {
+  if (file.type) return file.type === "text/x-markdown" ? "text/markdown" : file.type;
+  const extension = file.name.split(".").pop()?.toLowerCase() ?? "";
+  if (extension === "pdf") return "application/pdf";
+  if (extension === "json") return "application/json";
+  return TEXT_EXTENSIONS.includes(extension) ? "text/plain" : "application/octet-stream";
+};
+
+const readBase64 = (blob: Blob) =>
+  new Promise<string>((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve(String(reader.result).split(",", 2)[1] ?? "");
+    reader.onerror = () => reject(reader.error);
+    reader.readAsDataURL(blob);
+  });
+
+/** Re-encodes images whose longest edge or size is over the limit; GIFs become a still PNG. */
+async function downscaleImage(file: File): Promise<{ blob: Blob; mediaType: string }> {
+  const bitmap = await createImageBitmap(file);
+  const scale = Math.min(1, MAX_IMAGE_EDGE / Math.max(bitmap.width, bitmap.height));
+  if (scale === 1 && file.size <= MAX_IMAGE_BYTES) {
+    bitmap.close();
+    return { blob: file, mediaType: file.type };
+  }
+  const canvas = document.createElement("canvas");
+  canvas.width = Math.round(bitmap.width * scale);
+  canvas.height = Math.round(bitmap.height * scale);
+  canvas.getContext("2d")?.drawImage(bitmap, 0, 0, canvas.width, canvas.height);
+  bitmap.close();
+  const mediaType = file.type === "image/png" || file.type === "image/gif" ? "image/png" : "image/jpeg";
+  const blob = await new Promise<Blob | null>((resolve) => canvas.toBlob(resolve, mediaType, 0.85));
+  if (!blob) throw new Error(`无法压缩图片 ${file.name}`);
+  return { blob, mediaType };
+}
+
+export async function readAttachment(file: File): Promise<Attachment> {
+  const mediaType = mediaTypeOf(file);
+  if (IMAGE_TYPES.includes(mediaType)) {
+    const scaled = await downscaleImage(file);
+    return { name: file.name, mediaType: scaled.mediaType, data: await readBase64(scaled.blob) };
+  }
+  return { name: file.name, mediaType, data: await readBase64(file) };
+}
+
+/** Attachments of the prompt being composed; size and type limits are enforced by the backend. */
+export function useAttachments() {
+  const attachments = useAppStore((state) => state.attachments);
+  const setAttachments = useAppStore((state) => state.setAttachments);
+  const setGlobalError = useAppStore((state) => state.setGlobalError);
+
+  const addFiles = useCallback(
+    async (files: Iterable<File>) => {
+      const list = Array.from(files);
+      if (list.length === 0) return;
+      try {
+        const added = await Promise.all(list.map(readAttachment));
+        const next = [...useAppStore.getState().attachments, ...added];
+        if (next.length > MAX_ATTACHMENTS) {
+          setGlobalError(`每条消息最多只能附加 ${MAX_ATTACHMENTS} 个文件。`);
+        }
+        setAttachments(next.slice(0, MAX_ATTACHMENTS));
+      } catch (error) {
+        setGlobalError(`读取附件失败：${error instanceof Error ? error.message : String(error)}`);
+      }
+    },
+    [setAttachments, setGlobalError]
+  );
+
+  const removeAttachment = useCallback(
+    (index: number) => setAttachments(attachments.filter((_, position) => position !== index)),
+    [attachments, setAttachments]
+  );
+
+  return { attachments, addFiles, removeAttachment };
+}
@@ -0,0 +1,24 @@
+import { useEffect, useState } from "react";
+
+/** Models installed on the local Ollama server (`/api/tags`); empty when it is unreachable. */
+export function useOllamaModels(enabled: boolean, baseUrl?: string) {
+  const [models, setModels] = useState<string[]>([]);
+
+  useEffect(() => {
+    if (!enabled || !window.__TAURI__?.core?.invoke) return;
+    let active = true;
+    window.__TAURI__.core.invoke("ollama_models", { baseUrl: baseUrl?.trim() || undefined })
+      .then((result) => {
+        if (active && Array.isArray(result)) setModels(result.filter((item) => typeof item === "string"));
+      })
+      .catch((error) => {
+        console.error(error);
+        if (active) setModels([]);
+      });
+    return () => {
+      active = false;
+    };
+  }, [enabled, baseUrl]);
+
+  return models;
+}
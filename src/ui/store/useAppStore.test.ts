@@ -35,3 +35,28 @@ describe("stream messages", () => {
     expect(after).toBe(before);
   });
 });
+
+describe("usage", () => {
+  it("stores session totals and the daily report", () => {
+    const sessionId = "session-usage";
+    useAppStore.setState({ sessions: {}, dailyUsage: [] });
+    const usage = {
+      calls: 1,
+      inputTokens: 10,
+      outputTokens: 5,
+      cacheReadTokens: 0,
+      cacheWriteTokens: 0,
+      costUsd: 0.01,
+      unpricedCalls: 0
+    };
+
+    useAppStore.getState().handleServerEvent({ type: "session.usage", payload: { sessionId, usage } });
+    expect(useAppStore.getState().sessions[sessionId]?.usage).toEqual(usage);
+
+    useAppStore.getState().handleServerEvent({
+      type: "usage.report",
+      payload: { days: [{ date: "2026-10-17", ...usage }] }
+    });
+    expect(useAppStore.getState().dailyUsage).toHaveLength(1);
+  });
+});
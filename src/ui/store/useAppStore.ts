@@ -1,5 +1,5 @@
 import { create } from 'zustand';
-import type { PermissionMode, ProviderConfig, ProviderConfigs, ProviderKind, ServerEvent, SessionStatus, StreamMessage } from "../types";
+import type { Attachment, DailyUsage, PermissionMode, PermissionRule, ProviderConfig, ProviderConfigs, ProviderKind, ServerEvent, SessionSearchResult, SessionStatus, StreamMessage, UsageTotals } from "../types";
 
 const PROVIDER_STORAGE_KEY = "open-cowork.provider-configs";
 const PERMISSION_STORAGE_KEY = "open-cowork.permission-mode";
@@ -14,6 +14,16 @@ const DEFAULT_PROVIDER_CONFIGS: ProviderConfigs = {
     apiKey: "",
     model: "gpt-4o-mini",
     baseUrl: ""
+  },
+  ollama: {
+    apiKey: "",
+    model: "llama3.1",
+    baseUrl: ""
+  },
+  gemini: {
+    apiKey: "",
+    model: "gemini-2.5-flash",
+    baseUrl: ""
   }
 };
 
@@ -25,7 +35,9 @@ const loadProviderConfigs = (): ProviderConfigs => {
     const parsed = JSON.parse(raw) as Partial<ProviderConfigs>;
     return {
       anthropic: { ...DEFAULT_PROVIDER_CONFIGS.anthropic, ...parsed.anthropic },
-      openai: { ...DEFAULT_PROVIDER_CONFIGS.openai, ...parsed.openai }
+      openai: { ...DEFAULT_PROVIDER_CONFIGS.openai, ...parsed.openai },
+      ollama: { ...DEFAULT_PROVIDER_CONFIGS.ollama, ...parsed.ollama },
+      gemini: { ...DEFAULT_PROVIDER_CONFIGS.gemini, ...parsed.gemini }
     };
   } catch {
     return DEFAULT_PROVIDER_CONFIGS;
@@ -40,7 +52,7 @@ const persistProviderConfigs = (configs: ProviderConfigs) => {
 const loadPermissionMode = (): PermissionMode => {
   if (typeof window === "undefined") return "ask";
   const stored = window.localStorage.getItem(PERMISSION_STORAGE_KEY);
-  if (stored === "auto" || stored === "ask") return stored;
+  if (stored === "auto" || stored === "ask" || stored === "plan") return stored;
   return "ask";
 };
 
@@ -53,6 +65,14 @@ export type PermissionRequest = {
   toolUseId: string;
   toolName: string;
   input: unknown;
+  /** Unified diff of the change, for Write/Edit/MultiEdit. */
+  diff?: string;
+  /** Why the call needs approval, e.g. it reaches outside the workspace. */
+  reason?: string;
+  /** The permission rule that matched the call, if any. */
+  rule?: PermissionRule;
+  /** Generalized rule offered for "allow for session" / "always allow in project". */
+  suggestion?: string;
 };
 
 export type SessionView = {
@@ -60,11 +80,13 @@ export type SessionView = {
   title: string;
   status: SessionStatus;
   cwd?: string;
+  provider?: ProviderKind;
   messages: StreamMessage[];
   permissionRequests: PermissionRequest[];
   lastPrompt?: string;
   createdAt?: number;
   updatedAt?: number;
+  usage?: UsageTotals;
   hydrated: boolean;
 };
 
@@ -72,6 +94,8 @@ interface AppState {
   sessions: Record<string, SessionView>;
   activeSessionId: string | null;
   prompt: string;
+  /** Files attached to the prompt being composed. */
+  attachments: Attachment[];
   cwd: string;
   pendingStart: boolean;
   globalError: string | null;
@@ -81,8 +105,12 @@ interface AppState {
   activeProvider: ProviderKind;
   providerConfigs: ProviderConfigs;
   permissionMode: PermissionMode;
+  searchQuery: string;
+  searchResults: SessionSearchResult[];
+  dailyUsage: DailyUsage[];
 
   setPrompt: (prompt: string) => void;
+  setAttachments: (attachments: Attachment[]) => void;
   setCwd: (cwd: string) => void;
   setPendingStart: (pending: boolean) => void;
   setGlobalError: (error: string | null) => void;
@@ -104,6 +132,7 @@ export const useAppStore = create<AppState>((set, get) => ({
   sessions: {},
   activeSessionId: null,
   prompt: "",
+  attachments: [],
   cwd: "",
   pendingStart: false,
   globalError: null,
@@ -113,8 +142,12 @@ export const useAppStore = create<AppState>((set, get) => ({
   activeProvider: "anthropic",
   providerConfigs: loadProviderConfigs(),
   permissionMode: loadPermissionMode(),
+  searchQuery: "",
+  searchResults: [],
+  dailyUsage: [],
 
   setPrompt: (prompt) => set({ prompt }),
+  setAttachments: (attachments) => set({ attachments }),
   setCwd: (cwd) => set({ cwd }),
   setPendingStart: (pendingStart) => set({ pendingStart }),
   setGlobalError: (globalError) => set({ globalError }),
@@ -170,8 +203,10 @@ export const useAppStore = create<AppState>((set, get) => ({
             status: session.status,
             title: session.title,
             cwd: session.cwd,
+            provider: session.provider,
             createdAt: session.createdAt,
-            updatedAt: session.updatedAt
+            updatedAt: session.updatedAt,
+            usage: session.usage
           };
         }
 
@@ -231,6 +266,7 @@ export const useAppStore = create<AppState>((set, get) => ({
                 status,
                 title: title ?? existing.title,
                 cwd: cwd ?? existing.cwd,
+                permissionRequests: status === "running" ? existing.permissionRequests : [],
                 updatedAt: Date.now()
               }
             }
@@ -263,6 +299,31 @@ export const useAppStore = create<AppState>((set, get) => ({
         break;
       }
 
+      case "session.rewound": {
+        // The shortened history follows as `session.history`; put the prompt back up for editing.
+        if (get().activeSessionId === event.payload.sessionId) set({ prompt: event.payload.prompt });
+        break;
+      }
+
+      case "session.search_results": {
+        set({ searchQuery: event.payload.query, searchResults: event.payload.results });
+        break;
+      }
+
+      case "session.usage": {
+        const { sessionId, usage } = event.payload;
+        set((state) => {
+          const existing = state.sessions[sessionId] ?? createSession(sessionId);
+          return { sessions: { ...state.sessions, [sessionId]: { ...existing, usage } } };
+        });
+        break;
+      }
+
+      case "usage.report": {
+        set({ dailyUsage: event.payload.days });
+        break;
+      }
+
       case "stream.message": {
         const { sessionId, message } = event.payload;
         if (message.type === "stream_event") break;
@@ -279,7 +340,7 @@ export const useAppStore = create<AppState>((set, get) => ({
       }
 
       case "stream.user_prompt": {
-        const { sessionId, prompt } = event.payload;
+        const { sessionId, prompt, attachments } = event.payload;
         set((state) => {
           const existing = state.sessions[sessionId] ?? createSession(sessionId);
           return {
@@ -287,7 +348,7 @@ export const useAppStore = create<AppState>((set, get) => ({
               ...state.sessions,
               [sessionId]: {
                 ...existing,
-                messages: [...existing.messages, { type: "user_prompt", prompt }]
+                messages: [...existing.messages, { type: "user_prompt", prompt, attachments }]
               }
             }
           };
@@ -296,7 +357,7 @@ export const useAppStore = create<AppState>((set, get) => ({
       }
 
       case "permission.request": {
-        const { sessionId, toolUseId, toolName, input } = event.payload;
+        const { sessionId, toolUseId, toolName, input, diff, reason, rule, suggestion } = event.payload;
         set((state) => {
           const existing = state.sessions[sessionId] ?? createSession(sessionId);
           return {
@@ -304,7 +365,7 @@ export const useAppStore = create<AppState>((set, get) => ({
               ...state.sessions,
               [sessionId]: {
                 ...existing,
-                permissionRequests: [...existing.permissionRequests, { toolUseId, toolName, input }]
+                permissionRequests: [...existing.permissionRequests, { toolUseId, toolName, input, diff, reason, rule, suggestion }]
               }
             }
           };
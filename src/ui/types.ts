@@ -1,22 +1,55 @@
 import type { SDKMessage, PermissionResult } from "@anthropic-ai/claude-agent-sdk";
 
-export type ProviderKind = "anthropic" | "openai";
-export type PermissionMode = "ask" | "auto";
+export type ProviderKind = "anthropic" | "openai" | "ollama" | "gemini";
+export type PermissionMode = "ask" | "auto" | "plan";
+export type OpenAIApi = "chat_completions" | "responses";
+export type ReasoningEffort = "minimal" | "low" | "medium" | "high";
+
+/** Sampling settings sent with session.start; unset fields use the provider defaults. */
+export type GenerationParams = {
+  maxTokens?: number;
+  temperature?: number;
+  topP?: number;
+  stop?: string[];
+  reasoningEffort?: ReasoningEffort;
+  /** Anthropic extended thinking / Gemini thinking budget, in tokens. */
+  thinkingBudget?: number;
+};
 
 export type ProviderConfig = {
   apiKey: string;
   model: string;
   baseUrl?: string;
+  /** OpenAI only: which endpoint family to stream from. */
+  openaiApi?: OpenAIApi;
+  generationParams?: GenerationParams;
 };
 
 export type ProviderConfigs = {
   anthropic: ProviderConfig;
   openai: ProviderConfig;
+  ollama: ProviderConfig;
+  gemini: ProviderConfig;
+};
+
+export type PermissionRule = {
+  behavior: "allow" | "deny" | "ask";
+  rule: string;
+  /** Settings file the rule came from, or "session". */
+  source: string;
+};
+
+/** A file sent with a prompt; `data` is the base64-encoded contents. */
+export type Attachment = {
+  name: string;
+  mediaType: string;
+  data: string;
 };
 
 export type UserPromptMessage = {
   type: "user_prompt";
   prompt: string;
+  attachments?: Attachment[];
 };
 
 export type StreamEventMessage = {
@@ -27,7 +60,16 @@ export type StreamEventMessage = {
   };
 };
 
-export type StreamMessage = SDKMessage | UserPromptMessage | StreamEventMessage;
+/** Transcript-only entry recorded by the backend, e.g. when a run is interrupted. */
+export type MarkerMessage = {
+  type: "marker";
+  kind: string;
+  text: string;
+  /** Set on `compaction` markers: the summary that replaced the earlier messages. */
+  summary?: string;
+};
+
+export type StreamMessage = SDKMessage | UserPromptMessage | StreamEventMessage | MarkerMessage;
 
 export type SessionStatus = "idle" | "running" | "completed" | "error";
 
@@ -37,19 +79,70 @@ export type SessionInfo = {
   status: SessionStatus;
   claudeSessionId?: string;
   cwd?: string;
+  provider?: ProviderKind;
+  model?: string;
   createdAt: number;
   updatedAt: number;
+  usage?: UsageTotals;
+};
+
+/** Token counts and estimated cost; calls to models without a known price add no cost. */
+export type UsageTotals = {
+  calls: number;
+  inputTokens: number;
+  outputTokens: number;
+  cacheReadTokens: number;
+  cacheWriteTokens: number;
+  costUsd: number;
+  unpricedCalls: number;
+};
+
+export type DailyUsage = UsageTotals & {
+  /** Local calendar day, YYYY-MM-DD. */
+  date: string;
+};
+
+export type SearchMatch = {
+  messageIndex: number;
+  kind: "user_prompt" | "assistant" | "tool_result";
+  /** Plain-text excerpt; render it as text, never as HTML. */
+  snippet: string;
+  /** `[start, end)` ranges of matched terms in `snippet`, as string indexes. */
+  highlights: Array<[number, number]>;
+};
+
+export type SessionSearchResult = {
+  sessionId: string;
+  title: string;
+  updatedAt: number;
+  matches: SearchMatch[];
 };
 
 // Server -> Client events
 export type ServerEvent =
   | { type: "stream.message"; payload: { sessionId: string; message: StreamMessage } }
-  | { type: "stream.user_prompt"; payload: { sessionId: string; prompt: string } }
+  | { type: "stream.user_prompt"; payload: { sessionId: string; prompt: string; attachments?: Attachment[] } }
   | { type: "session.status"; payload: { sessionId: string; status: SessionStatus; title?: string; cwd?: string; error?: string } }
   | { type: "session.list"; payload: { sessions: SessionInfo[] } }
   | { type: "session.history"; payload: { sessionId: string; status: SessionStatus; messages: StreamMessage[] } }
   | { type: "session.deleted"; payload: { sessionId: string } }
-  | { type: "permission.request"; payload: { sessionId: string; toolUseId: string; toolName: string; input: unknown } }
+  | { type: "session.rewound"; payload: { sessionId: string; prompt: string; restoredFiles: string[] } }
+  | { type: "session.search_results"; payload: { query: string; results: SessionSearchResult[] } }
+  | { type: "session.usage"; payload: { sessionId: string; usage: UsageTotals } }
+  | { type: "usage.report"; payload: { days: DailyUsage[] } }
+  | {
+      type: "permission.request";
+      payload: {
+        sessionId: string;
+        toolUseId: string;
+        toolName: string;
+        input: unknown;
+        diff?: string;
+        reason?: string;
+        rule?: PermissionRule;
+        suggestion?: string;
+      };
+    }
   | { type: "runner.error"; payload: { sessionId?: string; message: string } };
 
 // Client -> Server events
@@ -66,11 +159,21 @@ export type ClientEvent =
         model: string;
         baseUrl?: string;
         permissionMode?: PermissionMode;
+        openaiApi?: OpenAIApi;
+        generationParams?: GenerationParams;
+        attachments?: Attachment[];
       };
     }
-  | { type: "session.continue"; payload: { sessionId: string; prompt: string } }
+  | {
+      type: "session.continue";
+      payload: { sessionId: string; prompt: string; apiKey?: string; attachments?: Attachment[] };
+    }
   | { type: "session.stop"; payload: { sessionId: string } }
   | { type: "session.delete"; payload: { sessionId: string } }
   | { type: "session.list" }
   | { type: "session.history"; payload: { sessionId: string } }
+  | { type: "session.search"; payload: { query: string } }
+  | { type: "session.compact"; payload: { sessionId: string; apiKey?: string } }
+  | { type: "session.rewind"; payload: { sessionId: string; toMessageIndex: number } }
+  | { type: "usage.report"; payload: { days?: number } }
   | { type: "permission.response"; payload: { sessionId: string; toolUseId: string; result: PermissionResult } };